@@ -1,19 +1,101 @@
-//! A simple parser for a tiny subset of CSS.
+//! A parser for a subset of CSS.
 //!
-//! To support more CSS syntax, it would probably be easiest to replace this
-//! hand-rolled parser with one based on a library or parser generator.
+//! The source is split into tokens as CSS Syntax Level 3 specifies, then rules and declarations
+//! are parsed from the tokens. Constructs this engine doesn't support are dropped like invalid
+//! ones, so they don't affect the rest of the stylesheet.
+
+mod tokenizer;
+
+use tokenizer::{Token, tokenize};
 
 // Data structures:
 
 #[derive(Debug)]
 pub struct Stylesheet {
     pub rules: Vec<Rule>,
+    /// Leading `@import` rules, not yet resolved.
+    pub imports: Vec<Import>,
+    pub font_faces: Vec<FontFaceRule>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Import {
+    /// The URL as written, relative to the importing stylesheet.
+    pub url: String,
+    /// Media queries the imported rules are conditional on. Empty means unconditional.
+    pub media: MediaQueryList,
+}
+
+/// The descriptors of a `@font-face` rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFaceRule {
+    pub family: String,
+    /// `url()` sources in order of preference, relative to the stylesheet.
+    pub sources: Vec<String>,
+    /// The range of weights the face covers. A single weight is a one-point range.
+    pub weight: (u16, u16),
+    pub style: FontStyle,
+    /// Inclusive code point ranges the face covers.
+    pub unicode_range: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FontStyle {
+    #[default]
+    Normal,
+    Italic,
+    Oblique,
 }
 
 #[derive(Debug)]
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
+    /// Enclosing `@media` conditions. The rule applies only if every list matches.
+    pub media: Vec<MediaQueryList>,
+}
+
+/// A comma-separated list of media queries. It matches if any of its queries match.
+pub type MediaQueryList = Vec<MediaQuery>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+    pub negated: bool,
+    /// Lowercase media type, `all` when omitted.
+    pub media_type: String,
+    pub features: Vec<MediaFeature>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaFeature {
+    Width(MediaRange, f32),
+    Height(MediaRange, f32),
+    Orientation(Orientation),
+    /// Resolution in dots per px unit (`dppx`).
+    Resolution(MediaRange, f32),
+    PrefersColorScheme(ColorScheme),
+    /// A feature or value this engine doesn't understand. It never matches.
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaRange {
+    Min,
+    Max,
+    Exact,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorScheme {
+    #[default]
+    Light,
+    Dark,
 }
 
 #[derive(Debug)]
@@ -26,6 +108,15 @@ pub struct SimpleSelector {
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub class: Vec<String>,
+    pub pseudo_element: Option<PseudoElement>,
+}
+
+/// A pseudo-element a selector can end with, which it matches instead of the element.
+/// https://www.w3.org/TR/css-pseudo-4/#generated-content
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PseudoElement {
+    Before,
+    After,
 }
 
 #[derive(Debug)]
@@ -39,11 +130,26 @@ pub enum Value {
     Keyword(String),
     Length(f32, Unit),
     ColorValue(Color),
+    Str(String),
+    /// A `var(--name, fallback)` reference, substituted at computed-value time.
+    Var(String, Option<Box<Value>>),
+    Number(f32),
+    Percentage(f32),
+    /// A `url()`, not yet resolved against the stylesheet's URL.
+    Url(String),
+    /// Any other function, e.g. `minmax(10px, 1fr)`, with its comma-separated arguments.
+    Function(String, Vec<Value>),
+    /// Space-separated values, e.g. `margin: 4px auto`.
+    List(Vec<Value>),
+    /// Comma-separated values, e.g. `font-family: Noto, serif`.
+    CommaList(Vec<Value>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Unit {
     Px,
+    /// A fraction of the free space in a grid container.
+    Fr,
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -64,7 +170,7 @@ impl Selector {
         let Selector::Simple(ref simple) = *self;
         let a = simple.id.iter().count();
         let b = simple.class.len();
-        let c = simple.tag_name.iter().count();
+        let c = simple.tag_name.iter().count() + simple.pseudo_element.iter().count();
         (a, b, c)
     }
 }
@@ -80,213 +186,730 @@ impl Value {
 }
 
 /// Parse a whole CSS stylesheet.
+///
+/// Errors are recovered from as CSS requires: an invalid declaration, rule or at-rule is dropped
+/// and parsing resumes after it.
 pub fn parse(source: String) -> Stylesheet {
-    let mut parser = Parser {
-        pos: 0,
-        input: source,
-    };
+    let mut parser = Parser::new(tokenize(&source));
     Stylesheet {
+        imports: parser.parse_imports(),
         rules: parser.parse_rules(),
+        font_faces: parser.font_faces,
     }
 }
 
+/// Parse a media query list on its own, e.g. the `media` attribute of a `<link>` element.
+pub fn parse_media(source: String) -> MediaQueryList {
+    parse_media_query_list(&tokenize(&source))
+}
+
 struct Parser {
+    tokens: Vec<Token>,
     pos: usize,
-    input: String,
+    // `@font-face` rules found so far, including ones nested in other at-rules.
+    font_faces: Vec<FontFaceRule>,
 }
 
 impl Parser {
-    /// Parse a list of rule sets, separated by optional whitespace.
+    fn new(tokens: Vec<Token>) -> Parser {
+        Parser {
+            tokens,
+            pos: 0,
+            font_faces: Vec::new(),
+        }
+    }
+
+    /// Parse the `@import` rules at the start of a stylesheet. Imports that appear after other
+    /// rules are invalid and get skipped as unknown at-rules.
+    fn parse_imports(&mut self) -> Vec<Import> {
+        let mut imports = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.next_token() {
+                Some(Token::AtKeyword(name)) if name.eq_ignore_ascii_case("import") => {}
+                _ => break,
+            }
+            let (_, prelude, block) = self.consume_at_rule();
+            if block.is_none()
+                && let Some(import) = parse_import(&prelude)
+            {
+                imports.push(import);
+            }
+        }
+        imports
+    }
+
+    /// Parse a list of rule sets and at-rules, up to the end of input.
     fn parse_rules(&mut self) -> Vec<Rule> {
         let mut rules = Vec::new();
         loop {
-            self.consume_whitespace();
-            if self.eof() {
-                break;
+            self.skip_whitespace();
+            match self.next_token() {
+                None => break,
+                Some(Token::AtKeyword(_)) => rules.extend(self.parse_at_rule()),
+                Some(_) => rules.extend(self.parse_rule()),
             }
-            rules.push(self.parse_rule());
         }
         rules
     }
 
-    /// Parse a rule set: `<selectors> { <declarations> }`.
-    fn parse_rule(&mut self) -> Rule {
-        Rule {
-            selectors: self.parse_selectors(),
-            declarations: self.parse_declarations(),
-        }
+    /// Parse a rule set: `<selectors> { <declarations> }`. The rule is consumed but `None` is
+    /// returned if its selectors are invalid or unsupported.
+    fn parse_rule(&mut self) -> Option<Rule> {
+        let mut prelude = Vec::new();
+        let block = loop {
+            match self.next_token() {
+                // A rule set without a block is invalid.
+                None => return None,
+                Some(Token::OpenCurly) => {
+                    self.pos += 1;
+                    break self.consume_block(Token::CloseCurly);
+                }
+                Some(_) => self.consume_component_value(&mut prelude),
+            }
+        };
+        Some(Rule {
+            selectors: parse_selectors(&prelude)?,
+            declarations: parse_declarations(&block),
+            media: Vec::new(),
+        })
     }
 
-    /// Parse a comma-separated list of selectors.
-    fn parse_selectors(&mut self) -> Vec<Selector> {
-        let mut selectors = Vec::new();
-        loop {
-            selectors.push(Selector::Simple(self.parse_simple_selector()));
-            self.consume_whitespace();
-            match self.next_char() {
-                ',' => {
-                    self.consume_char();
-                    self.consume_whitespace();
+    /// Parse an at-rule, returning the style rules it contributes. Unknown at-rules are skipped.
+    fn parse_at_rule(&mut self) -> Vec<Rule> {
+        let (name, prelude, block) = self.consume_at_rule();
+        let Some(block) = block else {
+            return Vec::new();
+        };
+        match &*name.to_ascii_lowercase() {
+            "media" => {
+                let media = parse_media_query_list(&prelude);
+                let mut parser = Parser::new(block);
+                let mut rules = parser.parse_rules();
+                self.font_faces.append(&mut parser.font_faces);
+                if !media.is_empty() {
+                    for rule in &mut rules {
+                        rule.media.insert(0, media.clone());
+                    }
                 }
-                '{' => break,
-                c => panic!("Unexpected character {} in selector list", c),
+                rules
+            }
+            "font-face" => {
+                self.font_faces.push(parse_font_face(&block));
+                Vec::new()
             }
+            _ => Vec::new(),
         }
-        // Return selectors with highest specificity first, for use in matching.
-        selectors.sort_by_key(|s| s.specificity());
-        selectors
     }
 
-    /// Parse one simple selector, e.g.: `type#id.class1.class2.class3`
-    fn parse_simple_selector(&mut self) -> SimpleSelector {
-        let mut selector = SimpleSelector {
-            tag_name: None,
-            id: None,
-            class: Vec::new(),
+    /// Consume an at-rule, returning its name, its prelude, and its block unless it ended with a
+    /// `;` instead.
+    fn consume_at_rule(&mut self) -> (String, Vec<Token>, Option<Vec<Token>>) {
+        let name = match self.consume_token() {
+            Some(Token::AtKeyword(name)) => name,
+            _ => String::new(),
         };
-        while !self.eof() {
-            match self.next_char() {
-                '#' => {
-                    self.consume_char();
-                    selector.id = Some(self.parse_identifier());
-                }
-                '.' => {
-                    self.consume_char();
-                    selector.class.push(self.parse_identifier());
+        let mut prelude = Vec::new();
+        loop {
+            match self.next_token() {
+                None => return (name, prelude, None),
+                Some(Token::Semicolon) => {
+                    self.pos += 1;
+                    return (name, prelude, None);
                 }
-                '*' => {
-                    // universal selector
-                    self.consume_char();
+                Some(Token::OpenCurly) => {
+                    self.pos += 1;
+                    let block = self.consume_block(Token::CloseCurly);
+                    return (name, prelude, Some(block));
                 }
-                c if valid_identifier_char(c) => {
-                    selector.tag_name = Some(self.parse_identifier());
+                Some(_) => self.consume_component_value(&mut prelude),
+            }
+        }
+    }
+
+    /// Consume one token into `into`, along with the rest of the block if it opens one.
+    fn consume_component_value(&mut self, into: &mut Vec<Token>) {
+        let Some(token) = self.consume_token() else {
+            return;
+        };
+        let close = closing_token(&token);
+        into.push(token);
+        if let Some(close) = close {
+            into.extend(self.consume_block(close.clone()));
+            into.push(close);
+        }
+    }
+
+    /// Consume the contents of a block whose opening token has just been consumed, up to the
+    /// matching `close` token or the end of input. The closing token is consumed but not returned.
+    fn consume_block(&mut self, close: Token) -> Vec<Token> {
+        let mut contents = Vec::new();
+        let mut expected = vec![close];
+        while let Some(token) = self.consume_token() {
+            if expected.last() == Some(&token) {
+                expected.pop();
+                if expected.is_empty() {
+                    break;
                 }
-                _ => break,
+            } else if let Some(close) = closing_token(&token) {
+                expected.push(close);
             }
+            contents.push(token);
         }
-        selector
+        contents
     }
 
-    /// Parse a list of declarations enclosed in `{ ... }`.
-    fn parse_declarations(&mut self) -> Vec<Declaration> {
-        self.expect_char('{');
-        let mut declarations = Vec::new();
-        loop {
-            self.consume_whitespace();
-            if self.next_char() == '}' {
-                self.consume_char();
+    /// Skip whitespace, and the `<!--` and `-->` tokens allowed around rules.
+    fn skip_whitespace(&mut self) {
+        while matches!(
+            self.next_token(),
+            Some(Token::Whitespace | Token::Cdo | Token::Cdc)
+        ) {
+            self.pos += 1;
+        }
+    }
+
+    /// Return the current token, and advance to the next one.
+    fn consume_token(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Read the current token without consuming it.
+    fn next_token(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+}
+
+// Functions for parsing the contents of rules. They work on token slices, where nested blocks
+// are included together with their opening and closing tokens.
+
+/// Parse a comma-separated list of selectors. Returns `None` if any of them is invalid.
+fn parse_selectors(tokens: &[Token]) -> Option<Vec<Selector>> {
+    let mut selectors = split_top_level(tokens, &Token::Comma)
+        .into_iter()
+        .map(|tokens| parse_simple_selector(trim_whitespace(tokens)).map(Selector::Simple))
+        .collect::<Option<Vec<_>>>()?;
+    // Return selectors with highest specificity first, for use in matching.
+    selectors.sort_by_key(|s| s.specificity());
+    Some(selectors)
+}
+
+/// Parse one simple selector, e.g.: `type#id.class1.class2.class3`, optionally followed by a
+/// `::before` or `::after` pseudo-element. Returns `None` for anything else, including
+/// combinators and pseudo-classes, which aren't supported.
+fn parse_simple_selector(tokens: &[Token]) -> Option<SimpleSelector> {
+    let mut selector = SimpleSelector {
+        tag_name: None,
+        id: None,
+        class: Vec::new(),
+        pseudo_element: None,
+    };
+    if tokens.is_empty() {
+        return None;
+    }
+    let mut i = 0;
+    while i < tokens.len() {
+        match (&tokens[i], tokens.get(i + 1)) {
+            (Token::Ident(name), _) if i == 0 => selector.tag_name = Some(name.clone()),
+            // universal selector
+            (Token::Delim('*'), _) if i == 0 => {}
+            (Token::Hash(id, true), _) => selector.id = Some(id.clone()),
+            (Token::Delim('.'), Some(Token::Ident(class))) => {
+                selector.class.push(class.clone());
+                i += 1;
+            }
+            // Nothing may follow a pseudo-element. The one-colon syntax of CSS 2 is allowed too.
+            (Token::Colon, _) => {
+                let name = match &tokens[i + 1..] {
+                    [Token::Colon, Token::Ident(name)] | [Token::Ident(name)] => name,
+                    _ => return None,
+                };
+                selector.pseudo_element = match &*name.to_ascii_lowercase() {
+                    "before" => Some(PseudoElement::Before),
+                    "after" => Some(PseudoElement::After),
+                    _ => return None,
+                };
                 break;
             }
-            declarations.push(self.parse_declaration());
+            _ => return None,
         }
-        declarations
+        i += 1;
+    }
+    Some(selector)
+}
+
+/// Parse the contents of a declaration block. Invalid declarations are dropped.
+fn parse_declarations(tokens: &[Token]) -> Vec<Declaration> {
+    parse_declaration_list(tokens)
+        .into_iter()
+        .filter_map(|(name, value)| {
+            Some(Declaration {
+                value: parse_value(value)?,
+                name,
+            })
+        })
+        .collect()
+}
+
+/// Split the contents of a declaration block into `<name>: <value>` pairs.
+///
+/// Names are lowercased, except for custom properties, which are case-sensitive. A trailing
+/// `!important` is dropped, as importance isn't part of the cascade. Anything that isn't a
+/// declaration, such as a nested at-rule, is skipped.
+fn parse_declaration_list(tokens: &[Token]) -> Vec<(String, &[Token])> {
+    let mut declarations = Vec::new();
+    for declaration in split_top_level(tokens, &Token::Semicolon) {
+        let [Token::Ident(name), rest @ ..] = trim_whitespace(declaration) else {
+            continue;
+        };
+        let [Token::Colon, value @ ..] = trim_whitespace(rest) else {
+            continue;
+        };
+        let name = if name.starts_with("--") {
+            name.clone()
+        } else {
+            name.to_ascii_lowercase()
+        };
+        declarations.push((name, strip_important(trim_whitespace(value))));
+    }
+    declarations
+}
+
+fn strip_important(value: &[Token]) -> &[Token] {
+    if let [rest @ .., Token::Ident(important)] = value
+        && important.eq_ignore_ascii_case("important")
+        && let [rest @ .., Token::Delim('!')] = trim_whitespace(rest)
+    {
+        return trim_whitespace(rest);
     }
+    value
+}
 
-    /// Parse one `<property>: <value>;` declaration.
-    fn parse_declaration(&mut self) -> Declaration {
-        let name = self.parse_identifier();
-        self.consume_whitespace();
-        self.expect_char(':');
-        self.consume_whitespace();
-        let value = self.parse_value();
-        self.consume_whitespace();
-        self.expect_char(';');
+// Functions for parsing values:
 
-        Declaration { name, value }
+/// Parse a property value. Space-separated values become a `List` and comma-separated ones a
+/// `CommaList`. Returns `None` if the value is empty or any part of it is invalid.
+fn parse_value(tokens: &[Token]) -> Option<Value> {
+    let mut groups = split_top_level(tokens, &Token::Comma)
+        .into_iter()
+        .map(parse_space_separated)
+        .collect::<Option<Vec<_>>>()?;
+    if groups.len() == 1 {
+        groups.pop()
+    } else {
+        Some(Value::CommaList(groups))
     }
+}
 
-    // Methods for parsing values:
+fn parse_space_separated(tokens: &[Token]) -> Option<Value> {
+    let mut values = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let value = match &tokens[i] {
+            Token::Whitespace => None,
+            Token::Ident(keyword) => Some(Value::Keyword(keyword.clone())),
+            Token::Number(number) => Some(Value::Number(*number)),
+            Token::Percentage(percentage) => Some(Value::Percentage(*percentage)),
+            Token::Dimension(number, unit) => Some(Value::Length(*number, parse_unit(unit)?)),
+            Token::Hash(hex, _) => Some(Value::ColorValue(parse_hex_color(hex)?)),
+            Token::String(string) => Some(Value::Str(string.clone())),
+            Token::Url(url) => Some(Value::Url(url.clone())),
+            // Separators like the one in `font: 12px/1.5`, and operators for `calc()`.
+            Token::Delim(c @ ('/' | '*' | '+' | '-')) => Some(Value::Keyword(c.to_string())),
+            Token::Function(name) => {
+                let end = block_end(tokens, i);
+                let value = parse_function(name, &tokens[i + 1..end])?;
+                i = end;
+                Some(value)
+            }
+            _ => return None,
+        };
+        values.extend(value);
+        i += 1;
+    }
+    match values.len() {
+        0 => None,
+        1 => values.pop(),
+        _ => Some(Value::List(values)),
+    }
+}
 
-    fn parse_value(&mut self) -> Value {
-        match self.next_char() {
-            '0'..='9' => self.parse_length(),
-            '#' => self.parse_color(),
-            _ => Value::Keyword(self.parse_identifier()),
+/// Parse a function call from its name and the tokens between its parentheses.
+fn parse_function(name: &str, args: &[Token]) -> Option<Value> {
+    let name = name.to_ascii_lowercase();
+    let args = trim_whitespace(args);
+    match &*name {
+        "var" => parse_var(args),
+        "url" => match args {
+            [Token::String(url)] => Some(Value::Url(url.clone())),
+            _ => None,
+        },
+        _ if args.is_empty() => Some(Value::Function(name, Vec::new())),
+        _ => {
+            let args = split_top_level(args, &Token::Comma)
+                .into_iter()
+                .map(parse_space_separated)
+                .collect::<Option<Vec<_>>>()?;
+            Some(Value::Function(name, args))
         }
     }
+}
 
-    fn parse_length(&mut self) -> Value {
-        Value::Length(self.parse_float(), self.parse_unit())
+/// Parse the arguments of a `var()` reference: `--name` or `--name, <fallback>`.
+fn parse_var(args: &[Token]) -> Option<Value> {
+    let [Token::Ident(name), rest @ ..] = args else {
+        return None;
+    };
+    if !name.starts_with("--") {
+        return None;
     }
+    let fallback = match trim_whitespace(rest) {
+        [] => None,
+        [Token::Comma, fallback @ ..] => Some(Box::new(parse_value(fallback)?)),
+        _ => return None,
+    };
+    Some(Value::Var(name.clone(), fallback))
+}
 
-    fn parse_float(&mut self) -> f32 {
-        self.consume_while(|c| matches!(c, '0'..='9' | '.'))
-            .parse()
-            .unwrap()
+fn parse_unit(unit: &str) -> Option<Unit> {
+    match &*unit.to_ascii_lowercase() {
+        "px" => Some(Unit::Px),
+        "fr" => Some(Unit::Fr),
+        _ => None,
     }
+}
 
-    fn parse_unit(&mut self) -> Unit {
-        match &*self.parse_identifier().to_ascii_lowercase() {
-            "px" => Unit::Px,
-            _ => panic!("unrecognized unit"),
+/// Parse the digits of a hex color: `rgb`, `rgba`, `rrggbb` or `rrggbbaa`.
+fn parse_hex_color(hex: &str) -> Option<Color> {
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digits = match hex.len() {
+        3 | 4 => 1,
+        6 | 8 => 2,
+        _ => return None,
+    };
+    let channel = |i: usize| {
+        let value = u8::from_str_radix(&hex[i * digits..(i + 1) * digits], 16).unwrap();
+        if digits == 1 { value * 17 } else { value }
+    };
+    Some(Color {
+        r: channel(0),
+        g: channel(1),
+        b: channel(2),
+        a: if hex.len() / digits == 4 {
+            channel(3)
+        } else {
+            255
+        },
+    })
+}
+
+/// Parse a `url()` or a quoted string at the start of `tokens`, returning the URL it contains and
+/// the tokens after it.
+fn parse_url(tokens: &[Token]) -> Option<(String, &[Token])> {
+    match tokens {
+        [Token::Url(url) | Token::String(url), rest @ ..] => Some((url.clone(), rest)),
+        [Token::Function(name), ..] if name.eq_ignore_ascii_case("url") => {
+            let end = block_end(tokens, 0);
+            let [Token::String(url)] = trim_whitespace(&tokens[1..end]) else {
+                return None;
+            };
+            Some((url.clone(), tokens.get(end + 1..).unwrap_or(&[])))
         }
+        _ => None,
     }
+}
 
-    fn parse_color(&mut self) -> Value {
-        self.expect_char('#');
-        Value::ColorValue(Color {
-            r: self.parse_hex_pair(),
-            g: self.parse_hex_pair(),
-            b: self.parse_hex_pair(),
-            a: 255,
-        })
+// Functions for parsing at-rules:
+
+/// Parse the prelude of an import rule: `url(<url>) <media-query-list>` or `"<url>" ...`.
+fn parse_import(prelude: &[Token]) -> Option<Import> {
+    let (url, media) = parse_url(trim_whitespace(prelude))?;
+    Some(Import {
+        url,
+        media: parse_media_query_list(media),
+    })
+}
+
+/// Parse the descriptor block of a `@font-face` rule. Unknown descriptors are ignored.
+fn parse_font_face(tokens: &[Token]) -> FontFaceRule {
+    let mut font_face = FontFaceRule {
+        family: String::new(),
+        sources: Vec::new(),
+        weight: (400, 400),
+        style: FontStyle::Normal,
+        unicode_range: vec![(0, 0x10FFFF)],
+    };
+    for (name, value) in parse_declaration_list(tokens) {
+        match &*name {
+            "font-family" => font_face.family = parse_family_name(value),
+            // Only `url()` sources are used; `local()` ones and `format()` hints are skipped.
+            "src" => {
+                font_face.sources = split_top_level(value, &Token::Comma)
+                    .into_iter()
+                    .filter_map(|source| parse_url(trim_whitespace(source)))
+                    .map(|(url, _)| url)
+                    .collect()
+            }
+            "font-weight" => font_face.weight = parse_font_weight_range(value),
+            "font-style" => {
+                font_face.style = match value {
+                    [Token::Ident(style)] if style.eq_ignore_ascii_case("italic") => {
+                        FontStyle::Italic
+                    }
+                    [Token::Ident(style), ..] if style.eq_ignore_ascii_case("oblique") => {
+                        FontStyle::Oblique
+                    }
+                    _ => FontStyle::Normal,
+                }
+            }
+            "unicode-range" => {
+                font_face.unicode_range = value
+                    .iter()
+                    .filter_map(|token| match *token {
+                        Token::UnicodeRange(start, end) => Some((start, end)),
+                        _ => None,
+                    })
+                    .collect()
+            }
+            _ => {}
+        }
     }
+    font_face
+}
 
-    /// Parse two hexadecimal digits.
-    fn parse_hex_pair(&mut self) -> u8 {
-        let s = &self.input[self.pos..self.pos + 2];
-        self.pos += 2;
-        u8::from_str_radix(s, 16).unwrap()
+/// Parse a family name: a quoted string, or a sequence of identifiers.
+fn parse_family_name(tokens: &[Token]) -> String {
+    match tokens {
+        [Token::String(family), ..] => family.clone(),
+        _ => tokens
+            .iter()
+            .map_while(|token| match token {
+                Token::Ident(word) => Some(word.as_str()),
+                Token::Whitespace => Some(""),
+                _ => None,
+            })
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join(" "),
     }
+}
 
-    /// Parse a property name or keyword.
-    fn parse_identifier(&mut self) -> String {
-        self.consume_while(valid_identifier_char)
+/// Parse `normal`, `bold`, a weight, or a `<min> <max>` range of weights.
+fn parse_font_weight_range(tokens: &[Token]) -> (u16, u16) {
+    let weights: Vec<u16> = tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Number(weight) => Some(*weight as u16),
+            Token::Ident(keyword) if keyword.eq_ignore_ascii_case("bold") => Some(700),
+            Token::Ident(_) => Some(400),
+            _ => None,
+        })
+        .collect();
+    match weights[..] {
+        [weight] => (weight, weight),
+        [min, max, ..] => (min.min(max), min.max(max)),
+        [] => (400, 400),
     }
+}
 
-    /// Consume and discard zero or more whitespace characters.
-    fn consume_whitespace(&mut self) {
-        self.consume_while(char::is_whitespace);
+// Functions for parsing media queries:
+
+/// Parse a comma-separated list of media queries. A query that fails to parse becomes `not all`,
+/// which never matches, while the rest of the list is kept.
+fn parse_media_query_list(tokens: &[Token]) -> MediaQueryList {
+    if trim_whitespace(tokens).is_empty() {
+        return Vec::new();
     }
+    split_top_level(tokens, &Token::Comma)
+        .into_iter()
+        .map(|query| {
+            parse_media_query(query).unwrap_or_else(|| MediaQuery {
+                negated: true,
+                media_type: "all".to_string(),
+                features: Vec::new(),
+            })
+        })
+        .collect()
+}
 
-    /// Consume characters until `test` returns false.
-    fn consume_while(&mut self, test: impl Fn(char) -> bool) -> String {
-        let mut result = String::new();
-        while !self.eof() && test(self.next_char()) {
-            result.push(self.consume_char());
+// The parts of a media query: keywords, and the contents of parenthesized features.
+enum MediaQueryPart<'t> {
+    Keyword(String),
+    Feature(&'t [Token]),
+}
+
+/// Parse one media query, e.g.: `not screen and (min-width: 600px) and (orientation: portrait)`
+fn parse_media_query(tokens: &[Token]) -> Option<MediaQuery> {
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Whitespace => {}
+            Token::Ident(keyword) => {
+                parts.push(MediaQueryPart::Keyword(keyword.to_ascii_lowercase()))
+            }
+            Token::OpenParen => {
+                let end = block_end(tokens, i);
+                parts.push(MediaQueryPart::Feature(&tokens[i + 1..end]));
+                i = end;
+            }
+            _ => return None,
         }
-        result
+        i += 1;
     }
 
-    /// Return the current character, and advance self.pos to the next character.
-    fn consume_char(&mut self) -> char {
-        let c = self.next_char();
-        self.pos += c.len_utf8();
-        c
+    let mut query = MediaQuery {
+        negated: false,
+        media_type: "all".to_string(),
+        features: Vec::new(),
+    };
+    let mut parts = parts.into_iter().peekable();
+    if let Some(MediaQueryPart::Keyword(keyword)) = parts.peek()
+        && (keyword == "not" || keyword == "only")
+    {
+        query.negated = keyword == "not";
+        parts.next();
     }
+    match parts.next()? {
+        MediaQueryPart::Keyword(media_type) if media_type != "and" => query.media_type = media_type,
+        MediaQueryPart::Feature(feature) => query.features.push(parse_media_feature(feature)?),
+        _ => return None,
+    }
+    while let Some(part) = parts.next() {
+        match (part, parts.next()) {
+            (MediaQueryPart::Keyword(and), Some(MediaQueryPart::Feature(feature)))
+                if and == "and" =>
+            {
+                query.features.push(parse_media_feature(feature)?)
+            }
+            _ => return None,
+        }
+    }
+    Some(query)
+}
 
-    /// If the exact string `s` is found at the current position, consume it.
-    /// Otherwise, panic.
-    fn expect_char(&mut self, c: char) {
-        if self.consume_char() != c {
-            panic!("Expected {:?} at byte {} but it was not found", c, self.pos);
+/// Parse the contents of a media feature, e.g.: `max-width: 600px`
+fn parse_media_feature(tokens: &[Token]) -> Option<MediaFeature> {
+    let tokens: Vec<&Token> = tokens
+        .iter()
+        .filter(|token| **token != Token::Whitespace)
+        .collect();
+    let (name, value) = match tokens[..] {
+        [Token::Ident(name), Token::Colon, value] => (name.to_ascii_lowercase(), value),
+        // Boolean features like `(color)`, and ones with unsupported values.
+        [Token::Ident(name), ..] => return Some(MediaFeature::Unknown(name.to_ascii_lowercase())),
+        _ => return None,
+    };
+    let (number, unit) = match value {
+        Token::Dimension(number, unit) => (*number, unit.to_ascii_lowercase()),
+        Token::Number(number) => (*number, String::new()),
+        Token::Ident(keyword) => (0.0, keyword.to_ascii_lowercase()),
+        _ => return Some(MediaFeature::Unknown(name)),
+    };
+
+    let (range, feature) = match name.split_once('-') {
+        Some(("min", feature)) => (MediaRange::Min, feature),
+        Some(("max", feature)) => (MediaRange::Max, feature),
+        _ => (MediaRange::Exact, &*name),
+    };
+    let length = match &*unit {
+        "px" => Some(number),
+        "" if number == 0.0 => Some(0.0),
+        _ => None,
+    };
+    let dppx = match &*unit {
+        "dppx" | "x" => Some(number),
+        "dpi" => Some(number / 96.0),
+        "dpcm" => Some(number * 2.54 / 96.0),
+        _ => None,
+    };
+    Some(match (feature, range, &*unit, length, dppx) {
+        ("width", _, _, Some(px), _) => MediaFeature::Width(range, px),
+        ("height", _, _, Some(px), _) => MediaFeature::Height(range, px),
+        ("resolution", _, _, _, Some(dppx)) => MediaFeature::Resolution(range, dppx),
+        ("orientation", MediaRange::Exact, "portrait", _, _) => {
+            MediaFeature::Orientation(Orientation::Portrait)
         }
+        ("orientation", MediaRange::Exact, "landscape", _, _) => {
+            MediaFeature::Orientation(Orientation::Landscape)
+        }
+        ("prefers-color-scheme", MediaRange::Exact, "light", _, _) => {
+            MediaFeature::PrefersColorScheme(ColorScheme::Light)
+        }
+        ("prefers-color-scheme", MediaRange::Exact, "dark", _, _) => {
+            MediaFeature::PrefersColorScheme(ColorScheme::Dark)
+        }
+        _ => MediaFeature::Unknown(name),
+    })
+}
+
+// Token slice helpers:
+
+/// The token that closes a block opened by `token`, if it opens one.
+fn closing_token(token: &Token) -> Option<Token> {
+    match token {
+        Token::OpenCurly => Some(Token::CloseCurly),
+        Token::OpenSquare => Some(Token::CloseSquare),
+        Token::OpenParen | Token::Function(_) => Some(Token::CloseParen),
+        _ => None,
     }
+}
 
-    /// Read the current character without consuming it.
-    fn next_char(&self) -> char {
-        self.input[self.pos..].chars().next().unwrap()
+/// The index of the token closing the block opened at `tokens[start]`, or the length of `tokens`
+/// if the block is unterminated.
+fn block_end(tokens: &[Token], start: usize) -> usize {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::OpenCurly | Token::OpenSquare | Token::OpenParen | Token::Function(_) => {
+                depth += 1
+            }
+            Token::CloseCurly | Token::CloseSquare | Token::CloseParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
     }
+    tokens.len()
+}
 
-    /// Return true if all input is consumed.
-    fn eof(&self) -> bool {
-        self.pos >= self.input.len()
+/// Split `tokens` at each `separator` that isn't nested inside a block.
+fn split_top_level<'t>(tokens: &'t [Token], separator: &Token) -> Vec<&'t [Token]> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenCurly | Token::OpenSquare | Token::OpenParen | Token::Function(_) => {
+                depth += 1
+            }
+            Token::CloseCurly | Token::CloseSquare | Token::CloseParen => depth -= 1,
+            _ if depth == 0 && token == separator => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
     }
+    parts.push(&tokens[start..]);
+    parts
 }
 
-fn valid_identifier_char(c: char) -> bool {
-    // TODO: Include U+00A0 and higher.
-    matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_')
+fn trim_whitespace(mut tokens: &[Token]) -> &[Token] {
+    while let [Token::Whitespace, rest @ ..] = tokens {
+        tokens = rest;
+    }
+    while let [rest @ .., Token::Whitespace] = tokens {
+        tokens = rest;
+    }
+    tokens
 }
 
 mod tests {
@@ -300,4 +923,227 @@ div.note { margin-bottom: 20px; padding: 10px; }
         );
         println!("{:?}", css)
     }
+
+    #[test]
+    fn parse_custom_properties() {
+        use super::{Unit, Value};
+
+        let css = super::parse(
+            "* { --gap: 8px; --accent: var(--brand, #336699); margin: var(--gap); }".to_owned(),
+        );
+        let declarations = &css.rules[0].declarations;
+
+        assert_eq!(declarations[0].name, "--gap");
+        assert_eq!(declarations[0].value, Value::Length(8.0, Unit::Px));
+        assert!(matches!(
+            &declarations[1].value,
+            Value::Var(name, Some(fallback))
+                if name == "--brand" && matches!(**fallback, Value::ColorValue(_))
+        ));
+        assert_eq!(declarations[2].value, Value::Var("--gap".to_string(), None));
+    }
+
+    #[test]
+    fn parse_imports() {
+        let css = super::parse(
+            "@import url(\"base.css\");
+@import 'theme/dark.css' (prefers-color-scheme: dark);
+@import url(print.css) print, screen and (max-width: 400px);
+p { margin: 4px; }
+@import url(late.css);"
+                .to_owned(),
+        );
+
+        let urls: Vec<_> = css.imports.iter().map(|i| i.url.as_str()).collect();
+        assert_eq!(urls, vec!["base.css", "theme/dark.css", "print.css"]);
+        assert!(css.imports[0].media.is_empty());
+        assert_eq!(css.imports[1].media.len(), 1);
+        assert_eq!(css.imports[2].media.len(), 2);
+        // An @import after a style rule is ignored.
+        assert_eq!(css.rules.len(), 1);
+    }
+
+    #[test]
+    fn parse_font_faces() {
+        use super::{FontFaceRule, FontStyle};
+
+        let css = super::parse(
+            "@font-face {
+  font-family: \"Noto Serif\";
+  src: local(Noto Serif), url(fonts/NotoSerif-Bold.ttf) format(\"truetype\"), url('b.ttf');
+  font-weight: bold;
+  unicode-range: U+0000-00FF, U+0131, U+4??;
+}
+@media print { @font-face { font-family: Print Face; src: url(p.ttf); font-weight: 300 500; font-style: italic } }
+p { font-family: \"Noto Serif\"; }"
+                .to_owned(),
+        );
+
+        assert_eq!(
+            css.font_faces,
+            vec![
+                FontFaceRule {
+                    family: "Noto Serif".to_string(),
+                    sources: vec!["fonts/NotoSerif-Bold.ttf".to_string(), "b.ttf".to_string()],
+                    weight: (700, 700),
+                    style: FontStyle::Normal,
+                    unicode_range: vec![(0, 0xFF), (0x131, 0x131), (0x400, 0x4FF)],
+                },
+                FontFaceRule {
+                    family: "Print Face".to_string(),
+                    sources: vec!["p.ttf".to_string()],
+                    weight: (300, 500),
+                    style: FontStyle::Italic,
+                    unicode_range: vec![(0, 0x10FFFF)],
+                },
+            ]
+        );
+        assert_eq!(
+            css.rules[0].declarations[0].value,
+            super::Value::Str("Noto Serif".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_media_rules() {
+        use super::{ColorScheme, MediaFeature, MediaRange, Orientation};
+
+        let css = super::parse(
+            "p { margin: 4px; }
+@media screen and (min-width: 600px) and (orientation: landscape), print {
+  p { margin: 8px; }
+  @media (prefers-color-scheme: dark) { p { color: #ffffff; } }
+}
+@page { margin: 1px; }
+@media not all and (min-resolution: 192dpi) { p { padding: 2px; } }"
+                .to_owned(),
+        );
+
+        assert_eq!(css.rules.len(), 4);
+        assert!(css.rules[0].media.is_empty());
+
+        let wide = &css.rules[1].media;
+        assert_eq!(wide.len(), 1);
+        assert_eq!(wide[0].len(), 2);
+        assert_eq!(wide[0][0].media_type, "screen");
+        assert_eq!(
+            wide[0][0].features,
+            vec![
+                MediaFeature::Width(MediaRange::Min, 600.0),
+                MediaFeature::Orientation(Orientation::Landscape)
+            ]
+        );
+        assert_eq!(wide[0][1].media_type, "print");
+
+        let nested = &css.rules[2].media;
+        assert_eq!(nested.len(), 2);
+        assert_eq!(
+            nested[1][0].features,
+            vec![MediaFeature::PrefersColorScheme(ColorScheme::Dark)]
+        );
+
+        let negated = &css.rules[3].media[0][0];
+        assert!(negated.negated);
+        assert_eq!(
+            negated.features,
+            vec![MediaFeature::Resolution(MediaRange::Min, 2.0)]
+        );
+    }
+
+    #[test]
+    fn parse_syntax() {
+        use super::{Color, Unit, Value};
+
+        let css = super::parse(
+            "/* header */ <!-- h1/**/, .caf\\e9 , #main { COLOR: #f00a /* red */; content: 'a;}b' }
+p a { margin: 1px; }
+p:hover, p { margin: 2px; }
+p { margin: 1em; padding: 2px }
+p { margin: 0 auto !important; font: bold 12px/1.5 \"Noto Serif\", serif; width: calc(100% - 4px) }
+p { color: #zz0000; } -->
+@unknown { p { margin: 3px; } }
+p { width: 10px"
+                .to_owned(),
+        );
+
+        let selectors: Vec<_> = css.rules.iter().map(|r| r.selectors.len()).collect();
+        // The rules with combinators or pseudo-classes are dropped; an unclosed block is closed by
+        // the end of input.
+        assert_eq!(selectors, vec![3, 1, 1, 1, 1]);
+
+        let super::Selector::Simple(ref class) = css.rules[0].selectors[1];
+        assert_eq!(class.class, vec!["café"]);
+        let declarations = &css.rules[0].declarations;
+        assert_eq!(declarations[0].name, "color");
+        assert_eq!(
+            declarations[0].value,
+            Value::ColorValue(Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 170
+            })
+        );
+        assert_eq!(declarations[1].value, Value::Str("a;}b".to_string()));
+
+        // The declaration with an unknown unit is dropped, but the next one is kept.
+        let declarations = &css.rules[1].declarations;
+        assert_eq!(declarations.len(), 1);
+        assert_eq!(declarations[0].value, Value::Length(2.0, Unit::Px));
+
+        let declarations = &css.rules[2].declarations;
+        assert_eq!(
+            declarations[0].value,
+            Value::List(vec![Value::Number(0.0), Value::Keyword("auto".to_string())])
+        );
+        assert_eq!(
+            declarations[1].value,
+            Value::CommaList(vec![
+                Value::List(vec![
+                    Value::Keyword("bold".to_string()),
+                    Value::Length(12.0, Unit::Px),
+                    Value::Keyword("/".to_string()),
+                    Value::Number(1.5),
+                    Value::Str("Noto Serif".to_string()),
+                ]),
+                Value::Keyword("serif".to_string()),
+            ])
+        );
+        assert!(
+            matches!(&declarations[2].value, Value::Function(name, args) if name == "calc" && args.len() == 1)
+        );
+
+        // An invalid color drops its declaration and leaves an empty block.
+        assert!(css.rules[3].declarations.is_empty());
+        assert_eq!(
+            css.rules[4].declarations[0].value,
+            Value::Length(10.0, Unit::Px)
+        );
+    }
+
+    #[test]
+    fn parse_pseudo_elements() {
+        use super::PseudoElement;
+
+        let css = super::parse(
+            "p::before, .note:AFTER { content: 'x'; }
+p::before.note { margin: 1px; }
+p::first-line { margin: 1px; }"
+                .to_owned(),
+        );
+        // Only `::before` and `::after` are supported, and nothing may follow them.
+        assert_eq!(css.rules.len(), 1);
+        let pseudo_elements: Vec<_> = css.rules[0]
+            .selectors
+            .iter()
+            .map(|selector| {
+                let super::Selector::Simple(ref simple) = *selector;
+                simple.pseudo_element
+            })
+            .collect();
+        assert!(pseudo_elements.contains(&Some(PseudoElement::Before)));
+        assert!(pseudo_elements.contains(&Some(PseudoElement::After)));
+        // A pseudo-element counts like a type selector.
+        assert_eq!(css.rules[0].selectors[0].specificity(), (0, 0, 2));
+    }
 }
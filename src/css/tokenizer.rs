@@ -0,0 +1,531 @@
+//! A CSS tokenizer following CSS Syntax Level 3.
+//!
+//! https://www.w3.org/TR/css-syntax-3/#tokenization
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+    Ident(String),
+    /// A function name; the opening parenthesis is part of the token.
+    Function(String),
+    AtKeyword(String),
+    /// A `#name`. The flag is set when the name would be a valid identifier (an "id" hash).
+    Hash(String, bool),
+    String(String),
+    BadString,
+    Url(String),
+    BadUrl,
+    Delim(char),
+    Number(f32),
+    Percentage(f32),
+    Dimension(f32, String),
+    UnicodeRange(u32, u32),
+    Whitespace,
+    Cdo,
+    Cdc,
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquare,
+    CloseSquare,
+    OpenParen,
+    CloseParen,
+    OpenCurly,
+    CloseCurly,
+}
+
+/// Split a stylesheet into tokens. Comments are dropped.
+pub(crate) fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokenizer = Tokenizer {
+        chars: preprocess(input),
+        pos: 0,
+    };
+    let mut tokens = Vec::new();
+    while let Some(token) = tokenizer.consume_token() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+// https://www.w3.org/TR/css-syntax-3/#input-preprocessing
+fn preprocess(input: &str) -> Vec<char> {
+    let input = input
+        .replace("\r\n", "\n")
+        .replace(['\r', '\u{c}'], "\n")
+        .replace('\0', "\u{fffd}");
+    input.chars().collect()
+}
+
+struct Tokenizer {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Tokenizer {
+    /// Look at the character `offset` positions ahead without consuming anything.
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn consume_char(&mut self) -> Option<char> {
+        let c = self.peek(0);
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.peek(i) == Some(c))
+    }
+
+    /// Consume the next token, or return `None` at the end of input.
+    fn consume_token(&mut self) -> Option<Token> {
+        self.consume_comments();
+        let c = self.consume_char()?;
+        let token = match c {
+            c if is_whitespace(c) => {
+                while self.peek(0).is_some_and(is_whitespace) {
+                    self.pos += 1;
+                }
+                Token::Whitespace
+            }
+            '"' | '\'' => self.consume_string(c),
+            '#' => {
+                if self.peek(0).is_some_and(is_name_char) || self.is_valid_escape(0) {
+                    let is_id = self.would_start_identifier(0);
+                    Token::Hash(self.consume_name(), is_id)
+                } else {
+                    Token::Delim(c)
+                }
+            }
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '[' => Token::OpenSquare,
+            ']' => Token::CloseSquare,
+            '{' => Token::OpenCurly,
+            '}' => Token::CloseCurly,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            ';' => Token::Semicolon,
+            '+' | '.' if self.would_start_number(-1) => {
+                self.pos -= 1;
+                self.consume_numeric()
+            }
+            '-' if self.would_start_number(-1) => {
+                self.pos -= 1;
+                self.consume_numeric()
+            }
+            '-' if self.starts_with("->") => {
+                self.pos += 2;
+                Token::Cdc
+            }
+            '-' if self.would_start_identifier(-1) => {
+                self.pos -= 1;
+                self.consume_ident_like()
+            }
+            '<' if self.starts_with("!--") => {
+                self.pos += 3;
+                Token::Cdo
+            }
+            '@' if self.would_start_identifier(0) => Token::AtKeyword(self.consume_name()),
+            '\\' if self.is_valid_escape(-1) => {
+                self.pos -= 1;
+                self.consume_ident_like()
+            }
+            '0'..='9' => {
+                self.pos -= 1;
+                self.consume_numeric()
+            }
+            'u' | 'U'
+                if self.peek(0) == Some('+')
+                    && self
+                        .peek(1)
+                        .is_some_and(|c| c == '?' || c.is_ascii_hexdigit()) =>
+            {
+                self.pos += 1;
+                self.consume_unicode_range()
+            }
+            c if is_name_start_char(c) => {
+                self.pos -= 1;
+                self.consume_ident_like()
+            }
+            c => Token::Delim(c),
+        };
+        Some(token)
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-comments
+    fn consume_comments(&mut self) {
+        while self.starts_with("/*") {
+            self.pos += 2;
+            while self.peek(0).is_some() && !self.starts_with("*/") {
+                self.pos += 1;
+            }
+            self.pos = (self.pos + 2).min(self.chars.len());
+        }
+    }
+
+    /// Do the two characters starting `offset` positions from here form a valid escape?
+    fn is_valid_escape(&self, offset: isize) -> bool {
+        let at = |i: isize| self.char_at(offset + i);
+        at(0) == Some('\\') && at(1).is_some_and(|c| c != '\n')
+    }
+
+    fn char_at(&self, offset: isize) -> Option<char> {
+        let index = self.pos as isize + offset;
+        if index < 0 {
+            None
+        } else {
+            self.chars.get(index as usize).copied()
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#would-start-an-identifier
+    fn would_start_identifier(&self, offset: isize) -> bool {
+        match self.char_at(offset) {
+            Some('-') => {
+                self.char_at(offset + 1)
+                    .is_some_and(|c| is_name_start_char(c) || c == '-')
+                    || self.is_valid_escape(offset + 1)
+            }
+            Some('\\') => self.is_valid_escape(offset),
+            Some(c) => is_name_start_char(c),
+            None => false,
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#starts-with-a-number
+    fn would_start_number(&self, offset: isize) -> bool {
+        let digit = |i: isize| self.char_at(offset + i).is_some_and(|c| c.is_ascii_digit());
+        match self.char_at(offset) {
+            Some('+') | Some('-') => {
+                digit(1) || (self.char_at(offset + 1) == Some('.') && digit(2))
+            }
+            Some('.') => digit(1),
+            Some(c) => c.is_ascii_digit(),
+            None => false,
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-escaped-code-point
+    // Called after the backslash has been consumed.
+    fn consume_escape(&mut self) -> char {
+        let Some(c) = self.consume_char() else {
+            return '\u{fffd}';
+        };
+        if !c.is_ascii_hexdigit() {
+            return c;
+        }
+        let mut hex = c.to_string();
+        while hex.len() < 6 && self.peek(0).is_some_and(|c| c.is_ascii_hexdigit()) {
+            hex.push(self.consume_char().unwrap());
+        }
+        if self.peek(0).is_some_and(is_whitespace) {
+            self.pos += 1;
+        }
+        match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+            Some(c) if c != '\0' => c,
+            _ => '\u{fffd}',
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-name
+    fn consume_name(&mut self) -> String {
+        let mut name = String::new();
+        loop {
+            match self.peek(0) {
+                Some(c) if is_name_char(c) => {
+                    self.pos += 1;
+                    name.push(c);
+                }
+                Some('\\') if self.is_valid_escape(0) => {
+                    self.pos += 1;
+                    name.push(self.consume_escape());
+                }
+                _ => return name,
+            }
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-a-string-token
+    // Called after the opening quote has been consumed.
+    fn consume_string(&mut self, quote: char) -> Token {
+        let mut value = String::new();
+        loop {
+            match self.consume_char() {
+                None => return Token::String(value),
+                Some(c) if c == quote => return Token::String(value),
+                Some('\n') => {
+                    self.pos -= 1;
+                    return Token::BadString;
+                }
+                Some('\\') => match self.peek(0) {
+                    None => {}
+                    Some('\n') => self.pos += 1,
+                    Some(_) => value.push(self.consume_escape()),
+                },
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-a-number
+    fn consume_number(&mut self) -> f32 {
+        let mut repr = String::new();
+        if let Some(sign @ ('+' | '-')) = self.peek(0) {
+            self.pos += 1;
+            repr.push(sign);
+        }
+        self.consume_digits(&mut repr);
+        if self.peek(0) == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+            repr.push('.');
+            self.consume_digits(&mut repr);
+        }
+        let exponent_digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+        if matches!(self.peek(0), Some('e' | 'E'))
+            && (exponent_digit(self.peek(1))
+                || (matches!(self.peek(1), Some('+' | '-')) && exponent_digit(self.peek(2))))
+        {
+            repr.push('e');
+            self.pos += 1;
+            if let Some(sign @ ('+' | '-')) = self.peek(0) {
+                self.pos += 1;
+                repr.push(sign);
+            }
+            self.consume_digits(&mut repr);
+        }
+        repr.parse().unwrap_or(0.0)
+    }
+
+    fn consume_digits(&mut self, into: &mut String) {
+        while let Some(c) = self.peek(0).filter(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+            into.push(c);
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-a-numeric-token
+    fn consume_numeric(&mut self) -> Token {
+        let number = self.consume_number();
+        if self.would_start_identifier(0) {
+            Token::Dimension(number, self.consume_name())
+        } else if self.peek(0) == Some('%') {
+            self.pos += 1;
+            Token::Percentage(number)
+        } else {
+            Token::Number(number)
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-an-ident-like-token
+    fn consume_ident_like(&mut self) -> Token {
+        let name = self.consume_name();
+        if name.eq_ignore_ascii_case("url") && self.peek(0) == Some('(') {
+            self.pos += 1;
+            while self.peek(0).is_some_and(is_whitespace) && self.peek(1).is_some_and(is_whitespace)
+            {
+                self.pos += 1;
+            }
+            let quoted = |c: Option<char>| matches!(c, Some('"' | '\''));
+            if quoted(self.peek(0))
+                || (self.peek(0).is_some_and(is_whitespace) && quoted(self.peek(1)))
+            {
+                Token::Function(name)
+            } else {
+                self.consume_url()
+            }
+        } else if self.peek(0) == Some('(') {
+            self.pos += 1;
+            Token::Function(name)
+        } else {
+            Token::Ident(name)
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-a-url-token
+    // Called after `url(` has been consumed.
+    fn consume_url(&mut self) -> Token {
+        let mut url = String::new();
+        while self.peek(0).is_some_and(is_whitespace) {
+            self.pos += 1;
+        }
+        loop {
+            match self.consume_char() {
+                None | Some(')') => return Token::Url(url),
+                Some(c) if is_whitespace(c) => {
+                    while self.peek(0).is_some_and(is_whitespace) {
+                        self.pos += 1;
+                    }
+                    if matches!(self.peek(0), None | Some(')')) {
+                        self.pos = (self.pos + 1).min(self.chars.len());
+                        return Token::Url(url);
+                    }
+                    self.consume_bad_url();
+                    return Token::BadUrl;
+                }
+                Some('"' | '\'' | '(') => {
+                    self.consume_bad_url();
+                    return Token::BadUrl;
+                }
+                Some(c) if is_non_printable(c) => {
+                    self.consume_bad_url();
+                    return Token::BadUrl;
+                }
+                Some('\\') => {
+                    if self.is_valid_escape(-1) {
+                        url.push(self.consume_escape());
+                    } else {
+                        self.consume_bad_url();
+                        return Token::BadUrl;
+                    }
+                }
+                Some(c) => url.push(c),
+            }
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-the-remnants-of-a-bad-url
+    fn consume_bad_url(&mut self) {
+        loop {
+            match self.consume_char() {
+                None | Some(')') => return,
+                Some('\\') if self.is_valid_escape(-1) => {
+                    self.consume_escape();
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    // https://www.w3.org/TR/2014/CR-css-syntax-3-20140220/#consume-a-unicode-range-token
+    // Called after `U+` has been consumed.
+    fn consume_unicode_range(&mut self) -> Token {
+        let mut start = String::new();
+        while start.len() < 6 && self.peek(0).is_some_and(|c| c.is_ascii_hexdigit()) {
+            start.push(self.consume_char().unwrap());
+        }
+        let mut wildcards = 0;
+        while start.len() + wildcards < 6 && self.peek(0) == Some('?') {
+            self.pos += 1;
+            wildcards += 1;
+        }
+        let parse = |s: &str| u32::from_str_radix(s, 16).unwrap_or(0);
+        if wildcards > 0 {
+            let low = format!("{}{}", start, "0".repeat(wildcards));
+            let high = format!("{}{}", start, "F".repeat(wildcards));
+            return Token::UnicodeRange(parse(&low), parse(&high));
+        }
+        let start = parse(&start);
+        if self.peek(0) == Some('-') && self.peek(1).is_some_and(|c| c.is_ascii_hexdigit()) {
+            self.pos += 1;
+            let mut end = String::new();
+            while end.len() < 6 && self.peek(0).is_some_and(|c| c.is_ascii_hexdigit()) {
+                end.push(self.consume_char().unwrap());
+            }
+            Token::UnicodeRange(start, parse(&end))
+        } else {
+            Token::UnicodeRange(start, start)
+        }
+    }
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\n' | '\t' | ' ')
+}
+
+fn is_name_start_char(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c >= '\u{80}'
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn is_non_printable(c: char) -> bool {
+    matches!(c, '\0'..='\u{8}' | '\u{b}' | '\u{e}'..='\u{1f}' | '\u{7f}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Token::*;
+    use super::*;
+
+    #[test]
+    fn tokenize_rules() {
+        assert_eq!(
+            tokenize("p.note>a{margin:-1.5e1px 50%/*x*/ +.5}"),
+            vec![
+                Ident("p".into()),
+                Delim('.'),
+                Ident("note".into()),
+                Delim('>'),
+                Ident("a".into()),
+                OpenCurly,
+                Ident("margin".into()),
+                Colon,
+                Dimension(-15.0, "px".into()),
+                Whitespace,
+                Percentage(50.0),
+                Whitespace,
+                Number(0.5),
+                CloseCurly,
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_strings_urls_and_escapes() {
+        assert_eq!(
+            tokenize(
+                r#"'it\'s' "a\62 c" "bad
+url( a.png ) url("b.png") url(c d) \31 0 --x café"#
+            ),
+            vec![
+                String("it's".into()),
+                Whitespace,
+                String("abc".into()),
+                Whitespace,
+                BadString,
+                Whitespace,
+                Url("a.png".into()),
+                Whitespace,
+                Function("url".into()),
+                String("b.png".into()),
+                CloseParen,
+                Whitespace,
+                BadUrl,
+                Whitespace,
+                Ident("10".into()),
+                Whitespace,
+                Ident("--x".into()),
+                Whitespace,
+                Ident("café".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_at_rules_hashes_and_ranges() {
+        assert_eq!(
+            tokenize("@media<!-- #fff #1a -->U+0-7F,u+4??;var(--a)"),
+            vec![
+                AtKeyword("media".into()),
+                Cdo,
+                Whitespace,
+                Hash("fff".into(), true),
+                Whitespace,
+                Hash("1a".into(), false),
+                Whitespace,
+                Cdc,
+                UnicodeRange(0, 0x7f),
+                Comma,
+                UnicodeRange(0x400, 0x4ff),
+                Semicolon,
+                Function("var".into()),
+                Ident("--a".into()),
+                CloseParen,
+            ]
+        );
+    }
+}
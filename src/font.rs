@@ -0,0 +1,394 @@
+//! Font faces declared with `@font-face`, looked up by family name for text layout.
+
+use super::css::{FontFaceRule, FontStyle, Stylesheet};
+use super::loader::ResourceLoader;
+use fontdue::{Font, FontSettings};
+use rustybuzz::{Direction, Face, UnicodeBuffer};
+use std::fmt;
+
+/// A loaded font together with the descriptors of the `@font-face` rule that declared it.
+pub struct FontFace {
+    pub family: String,
+    pub weight: (u16, u16),
+    pub style: FontStyle,
+    pub unicode_range: Vec<(u32, u32)>,
+    pub(crate) font: Font,
+    // The font file, whose OpenType tables are read for shaping.
+    data: Vec<u8>,
+}
+
+impl fmt::Debug for FontFace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FontFace")
+            .field("family", &self.family)
+            .field("weight", &self.weight)
+            .field("style", &self.style)
+            .finish()
+    }
+}
+
+/// Vertical metrics of a face at a given size, in px.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FontMetrics {
+    // Distance from the baseline to the top of the tallest glyphs.
+    pub ascent: f32,
+    // Distance from the baseline to the bottom of the lowest glyphs (positive).
+    pub descent: f32,
+    pub line_gap: f32,
+}
+
+/// A glyph of shaped text, with its advance and offset from the pen position in px. Offsets go
+/// right and up.
+#[derive(Clone, Copy, Debug)]
+pub struct Glyph {
+    pub id: u16,
+    // Byte offset in the text of the cluster of characters the glyph is for.
+    pub cluster: usize,
+    pub advance: f32,
+    pub offset: (f32, f32),
+}
+
+impl FontFace {
+    /// Does the face have a glyph for `c`? Characters that are never shown, like joiners and
+    /// variation selectors, need none.
+    pub fn has_glyph(&self, c: char) -> bool {
+        is_default_ignorable(c) || self.font.lookup_glyph_index(c) != 0
+    }
+
+    /// Is `c` inside the face's `unicode-range`?
+    pub fn covers(&self, c: char) -> bool {
+        let c = c as u32;
+        self.unicode_range
+            .iter()
+            .any(|&(start, end)| start <= c && c <= end)
+    }
+
+    /// Shape `text` at `size` px, right-to-left if `rtl`, with the default OpenType features:
+    /// ligatures, kerning, mark positioning and the forms of complex scripts. The glyphs are in
+    /// visual order, from left to right.
+    pub fn shape(&self, text: &str, size: f32, rtl: bool) -> Vec<Glyph> {
+        // The file was checked to have them when the face was added.
+        let Some(face) = Face::from_slice(&self.data, 0) else {
+            return Vec::new();
+        };
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(text);
+        buffer.set_direction(match rtl {
+            true => Direction::RightToLeft,
+            false => Direction::LeftToRight,
+        });
+        buffer.guess_segment_properties();
+        let output = rustybuzz::shape(&face, &[], buffer);
+        let scale = size / face.units_per_em() as f32;
+        output
+            .glyph_infos()
+            .iter()
+            .zip(output.glyph_positions())
+            .map(|(info, position)| Glyph {
+                id: info.glyph_id as u16,
+                cluster: info.cluster as usize,
+                advance: position.x_advance as f32 * scale,
+                offset: (
+                    position.x_offset as f32 * scale,
+                    position.y_offset as f32 * scale,
+                ),
+            })
+            .collect()
+    }
+
+    /// The advance width of `text` at `size` px, shaped left-to-right.
+    pub fn measure(&self, text: &str, size: f32) -> f32 {
+        self.shape(text, size, false)
+            .iter()
+            .map(|glyph| glyph.advance)
+            .sum()
+    }
+
+    pub fn metrics(&self, size: f32) -> FontMetrics {
+        match self.font.horizontal_line_metrics(size) {
+            Some(metrics) => FontMetrics {
+                ascent: metrics.ascent,
+                descent: -metrics.descent,
+                line_gap: metrics.line_gap,
+            },
+            None => FontMetrics {
+                ascent: size * 0.8,
+                descent: size * 0.2,
+                line_gap: 0.0,
+            },
+        }
+    }
+}
+
+/// The set of font faces available to text layout.
+#[derive(Debug, Default)]
+pub struct FontRegistry {
+    faces: Vec<FontFace>,
+    // Families to try after those of `font-family` for characters they have no glyphs for.
+    fallback_families: Vec<String>,
+}
+
+impl FontRegistry {
+    pub fn new() -> FontRegistry {
+        Default::default()
+    }
+
+    /// Register a face described by `rule` from the font file contents in `data`.
+    pub fn add_face(&mut self, rule: &FontFaceRule, data: &[u8]) -> Result<(), &'static str> {
+        let font = Font::from_bytes(data, FontSettings::default())?;
+        Face::from_slice(data, 0).ok_or("font tables can't be read for shaping")?;
+        self.faces.push(FontFace {
+            family: rule.family.clone(),
+            weight: rule.weight,
+            style: rule.style,
+            unicode_range: rule.unicode_range.clone(),
+            font,
+            data: data.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Load the faces declared by the `@font-face` rules of `stylesheet`.
+    ///
+    /// Each rule uses the first of its sources that loads and parses. Rules without a usable
+    /// source are skipped, as browsers do.
+    pub fn load_font_faces(&mut self, stylesheet: &Stylesheet, loader: &dyn ResourceLoader) {
+        for rule in &stylesheet.font_faces {
+            for source in &rule.sources {
+                let Ok(data) = loader.load(source) else {
+                    continue;
+                };
+                if self.add_face(rule, &data).is_ok() {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Set the families to fall back to, in order, for characters that none of the families of
+    /// `font-family` has glyphs for, like those of other scripts or emoji. Their faces are
+    /// registered like any other, from `@font-face` rules or with `add_face`.
+    pub fn set_fallback_families(&mut self, families: Vec<String>) {
+        self.fallback_families = families;
+    }
+
+    /// Find the face to render `c` with. See `find_cluster`.
+    pub fn find(
+        &self,
+        families: &[String],
+        weight: u16,
+        style: FontStyle,
+        c: char,
+    ) -> Option<&FontFace> {
+        self.find_cluster(families, weight, style, c.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Find the face to render a cluster of characters with, trying each family and then each
+    /// fallback family in order, until one has glyphs for all of them. If none does, it's the
+    /// face the families have for the first character, which shows it as missing.
+    ///
+    /// Within a family, faces whose `unicode-range` excludes a character are ignored, then the
+    /// closest style and weight are chosen following the CSS font matching algorithm.
+    pub fn find_cluster(
+        &self,
+        families: &[String],
+        weight: u16,
+        style: FontStyle,
+        cluster: &str,
+    ) -> Option<&FontFace> {
+        let supported = families
+            .iter()
+            .chain(&self.fallback_families)
+            .find_map(|family| {
+                self.best_face(family, weight, style, |face| {
+                    cluster.chars().all(|c| face.covers(c) && face.has_glyph(c))
+                })
+            });
+        let first = cluster.chars().next()?;
+        supported.or_else(|| {
+            families
+                .iter()
+                .find_map(|family| self.best_face(family, weight, style, |face| face.covers(first)))
+        })
+    }
+
+    // The face of `family` that best matches `weight` and `style`, among those that are `usable`.
+    fn best_face(
+        &self,
+        family: &str,
+        weight: u16,
+        style: FontStyle,
+        usable: impl Fn(&FontFace) -> bool,
+    ) -> Option<&FontFace> {
+        let style_preference = match style {
+            FontStyle::Normal => [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic],
+            FontStyle::Italic => [FontStyle::Italic, FontStyle::Oblique, FontStyle::Normal],
+            FontStyle::Oblique => [FontStyle::Oblique, FontStyle::Italic, FontStyle::Normal],
+        };
+        let candidates: Vec<&FontFace> = self
+            .faces
+            .iter()
+            .filter(|face| face.family.eq_ignore_ascii_case(family) && usable(face))
+            .collect();
+        style_preference.iter().find_map(|&style| {
+            candidates
+                .iter()
+                .filter(|face| face.style == style)
+                .min_by_key(|face| weight_preference(weight, face.weight))
+                .copied()
+        })
+    }
+}
+
+// Characters that are never shown on their own, which fonts needn't have glyphs for.
+// https://www.unicode.org/reports/tr44/#Default_Ignorable_Code_Point
+fn is_default_ignorable(c: char) -> bool {
+    matches!(
+        c,
+        '\u{AD}'
+            | '\u{34F}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{206F}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{FEFF}'
+            | '\u{E0000}'..='\u{E0FFF}'
+    )
+}
+
+// Rank a face's weight range for a desired weight; lower is better.
+// https://www.w3.org/TR/css-fonts-4/#font-style-matching
+fn weight_preference(desired: u16, (min, max): (u16, u16)) -> (u8, u16) {
+    if min <= desired && desired <= max {
+        return (0, 0);
+    }
+    let lighter = max < desired;
+    let nearest = if lighter { max } else { min };
+    let distance = desired.abs_diff(nearest);
+    let group = if (400..=500).contains(&desired) {
+        // Heavier weights up to 500 first, then lighter ones, then anything heavier.
+        match (lighter, nearest <= 500) {
+            (false, true) => 1,
+            (true, _) => 2,
+            (false, false) => 3,
+        }
+    } else if (desired < 400) == lighter {
+        1
+    } else {
+        2
+    };
+    (group, distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::css;
+    use super::super::loader::FileSystemLoader;
+    use super::*;
+
+    #[test]
+    fn test_font_matching() {
+        let stylesheet = css::parse(
+            "@font-face { font-family: Noto; src: url(missing.ttf), url(NotoSerif-Regular.ttf); }
+@font-face { font-family: Noto; src: url(NotoSerif-Bold.ttf); font-weight: 700; }
+@font-face { font-family: Noto; src: url(NotoSerif-Italic.ttf); font-style: italic; }
+@font-face { font-family: Latin; src: url(NotoSerif-Bold.ttf); unicode-range: U+0-7F; }
+@font-face { font-family: Broken; src: url(missing.ttf); }"
+                .to_owned(),
+        );
+        let mut fonts = FontRegistry::new();
+        fonts.load_font_faces(&stylesheet, &FileSystemLoader::new("testfiles"));
+        assert_eq!(fonts.faces.len(), 4);
+
+        let families = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let find = |names: &[&str], weight, style| {
+            fonts
+                .find(&families(names), weight, style, 'a')
+                .map(|face| (face.family.clone(), face.weight.0, face.style))
+        };
+
+        let regular = Some(("Noto".to_string(), 400, FontStyle::Normal));
+        let bold = Some(("Noto".to_string(), 700, FontStyle::Normal));
+        assert_eq!(find(&["noto"], 400, FontStyle::Normal), regular);
+        assert_eq!(find(&["Noto"], 300, FontStyle::Normal), regular);
+        assert_eq!(find(&["Noto"], 600, FontStyle::Normal), bold);
+        assert_eq!(find(&["Noto"], 900, FontStyle::Normal), bold);
+        assert_eq!(
+            find(&["Noto"], 700, FontStyle::Oblique),
+            Some(("Noto".to_string(), 400, FontStyle::Italic))
+        );
+        assert_eq!(find(&["Broken", "Noto"], 400, FontStyle::Normal), regular);
+        assert_eq!(find(&["Unknown"], 400, FontStyle::Normal), None);
+
+        // Faces are only used for characters in their unicode-range.
+        let latin = families(&["Latin", "Noto"]);
+        let face = fonts.find(&latin, 400, FontStyle::Normal, 'a').unwrap();
+        assert_eq!(face.family, "Latin");
+        let face = fonts.find(&latin, 400, FontStyle::Normal, 'é').unwrap();
+        assert_eq!(face.family, "Noto");
+
+        let face = fonts.find(&latin, 400, FontStyle::Normal, 'a').unwrap();
+        assert!(face.measure("Hello", 16.0) > 0.0);
+        assert!(face.metrics(16.0).ascent > 0.0);
+    }
+
+    #[test]
+    fn test_shaping() {
+        let stylesheet = css::parse(
+            "@font-face { font-family: Noto; src: url(NotoSerif-Regular.ttf); }".to_owned(),
+        );
+        let mut fonts = FontRegistry::new();
+        fonts.load_font_faces(&stylesheet, &FileSystemLoader::new("testfiles"));
+        let face = &fonts.faces[0];
+        let index = |c| face.font.lookup_glyph_index(c);
+
+        // Ligatures take the place of the characters they join, in one cluster.
+        let glyphs = face.shape("office", 20.0, false);
+        assert!(glyphs.len() < 6);
+        let clusters: Vec<usize> = glyphs.iter().map(|glyph| glyph.cluster).collect();
+        assert_eq!(clusters.first(), Some(&0));
+        assert!(clusters.windows(2).all(|pair| pair[0] < pair[1]));
+        // Kerning moves pairs closer than their advances.
+        let width = |text| face.measure(text, 20.0);
+        assert!(width("AV") < width("A") + width("V"));
+
+        // Right-to-left text comes out in visual order, with mirrored brackets.
+        let glyphs = face.shape("(a)", 20.0, true);
+        let ids: Vec<u16> = glyphs.iter().map(|glyph| glyph.id).collect();
+        assert_eq!(ids, vec![index('('), index('a'), index(')')]);
+        let clusters: Vec<usize> = glyphs.iter().map(|glyph| glyph.cluster).collect();
+        assert_eq!(clusters, vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn test_font_fallback() {
+        let stylesheet = css::parse(
+            "@font-face { font-family: Noto; src: url(NotoSerif-Regular.ttf); }
+@font-face { font-family: Latin; src: url(NotoSerif-Bold.ttf); unicode-range: U+0-7F; }"
+                .to_owned(),
+        );
+        let mut fonts = FontRegistry::new();
+        fonts.load_font_faces(&stylesheet, &FileSystemLoader::new("testfiles"));
+        let latin = vec!["Latin".to_string()];
+        let family = |fonts: &FontRegistry, cluster| {
+            fonts
+                .find_cluster(&latin, 400, FontStyle::Normal, cluster)
+                .map(|face| face.family.clone())
+        };
+        assert_eq!(family(&fonts, "e"), Some("Latin".to_string()));
+        assert_eq!(family(&fonts, "é"), None);
+
+        // Characters no family has go to the fallback families, and a cluster goes as a whole.
+        fonts.set_fallback_families(vec!["Noto".to_string()]);
+        assert_eq!(family(&fonts, "é"), Some("Noto".to_string()));
+        assert_eq!(family(&fonts, "e\u{301}"), Some("Noto".to_string()));
+
+        // Without a face that has its glyph, a character is shown missing in the first family
+        // whose range it's in.
+        let families = vec!["Latin".to_string(), "Noto".to_string()];
+        let face = fonts.find(&families, 400, FontStyle::Normal, 'א').unwrap();
+        assert_eq!(face.family, "Noto");
+        assert!(!face.has_glyph('א'));
+        assert!(face.has_glyph('\u{200D}'));
+    }
+}
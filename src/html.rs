@@ -78,7 +78,16 @@ impl Parser {
         self.expect("<");
         let tag_name = self.parse_name();
         let attrs = self.parse_attributes();
+
+        // Self-closing and void elements have no contents or closing tag.
+        if self.starts_with("/>") {
+            self.expect("/>");
+            return dom::elem(tag_name, attrs, Vec::new());
+        }
         self.expect(">");
+        if is_void_element(&tag_name) {
+            return dom::elem(tag_name, attrs, Vec::new());
+        }
 
         // Contents.
         let children = self.parse_nodes();
@@ -91,9 +100,13 @@ impl Parser {
         return dom::elem(tag_name, attrs, children);
     }
 
-    // Parse a single name="value" pair.
+    // Parse a single name="value" pair, or a boolean attribute like `reversed`, whose value is
+    // empty.
     fn parse_attr(&mut self) -> (String, String) {
         let name = self.parse_name();
+        if !self.starts_with("=") {
+            return (name, String::new());
+        }
         self.expect("=");
         let value = self.parse_attr_value();
         return (name, value);
@@ -114,7 +127,7 @@ impl Parser {
         let mut attributes = HashMap::new();
         loop {
             self.consume_whitespace();
-            if self.next_char() == '>' {
+            if self.next_char() == '>' || self.starts_with("/>") {
                 break;
             }
             let (name, value) = self.parse_attr();
@@ -137,6 +150,14 @@ impl Parser {
     }
 }
 
+// Elements that can't have contents, so they are never closed.
+fn is_void_element(tag_name: &str) -> bool {
+    matches!(
+        &*tag_name.to_ascii_lowercase(),
+        "br" | "col" | "hr" | "img" | "input" | "link" | "meta"
+    )
+}
+
 // Parse an HTML document and return the root element.
 pub fn parse(source: String) -> dom::Node {
     let mut nodes = Parser {
@@ -165,4 +186,33 @@ mod tests {
         );
         println!("{:?}", node)
     }
+
+    #[test]
+    fn void_elements() {
+        let node = parse(
+            "<html><link rel=\"stylesheet\" href=\"a.css\"><br/><p>Hi</p></html>".to_string(),
+        );
+        assert_eq!(node.children.len(), 3);
+        match &node.children[0].node_type {
+            dom::NodeType::Element(link) => {
+                assert_eq!(link.tag_name, "link");
+                assert_eq!(link.attrs.get("href").map(|s| s.as_str()), Some("a.css"));
+            }
+            _ => panic!("expected a link element"),
+        }
+        assert!(node.children[1].children.is_empty());
+        assert_eq!(node.children[2].children.len(), 1);
+    }
+
+    #[test]
+    fn boolean_attributes() {
+        let node = parse("<ol reversed start=\"3\"></ol>".to_string());
+        match node.node_type {
+            dom::NodeType::Element(ol) => {
+                assert_eq!(ol.attrs.get("reversed").map(|s| s.as_str()), Some(""));
+                assert_eq!(ol.attrs.get("start").map(|s| s.as_str()), Some("3"));
+            }
+            _ => panic!("expected an ol element"),
+        }
+    }
 }
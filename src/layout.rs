@@ -1,6 +1,22 @@
 use super::css::*;
+use super::dom::NodeType;
+use super::font::{FontFace, FontMetrics, FontRegistry, Glyph};
 use super::style::*;
 
+mod bidi;
+mod flex;
+mod float;
+mod grid;
+mod inline;
+mod list;
+mod position;
+mod table;
+
+use float::FloatContext;
+use list::default_list_style_type;
+use std::ptr;
+use unicode_segmentation::UnicodeSegmentation;
+
 // CSS box model. All sizes are in px.
 
 #[derive(Clone, Copy, Default, Debug)]
@@ -68,17 +84,121 @@ pub struct LayoutBox<'a> {
     pub(crate) dimensions: Dimensions,
     pub(crate) box_type: BoxType<'a>,
     pub(crate) children: Vec<LayoutBox<'a>>,
+    pub(crate) child_margins: ChildMargins,
+    pub(crate) imposed_size: ImposedSize,
+    // For a piece of an inline box split around a block: whether there are pieces of it before
+    // and after this one, which have its start and end edges instead.
+    pub(crate) split: (bool, bool),
+}
+
+// Adjoining vertical margins, which collapse into a single margin: the largest positive margin
+// plus the most negative one.
+// https://www.w3.org/TR/CSS2/box.html#collapsing-margins
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MarginStrut {
+    positive: f32,
+    negative: f32,
+}
+
+impl MarginStrut {
+    fn new(margin: f32) -> MarginStrut {
+        MarginStrut {
+            positive: margin.max(0.0),
+            negative: margin.min(0.0),
+        }
+    }
+
+    fn adjoin(&mut self, other: MarginStrut) {
+        self.positive = self.positive.max(other.positive);
+        self.negative = self.negative.min(other.negative);
+    }
+
+    fn resolve(&self) -> f32 {
+        self.positive + self.negative
+    }
+}
+
+// The children's margins adjoining the top and bottom of a block's content box, kept so that
+// the parent block can collapse them with the block's own margins.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ChildMargins {
+    // Margins above the first child with content.
+    leading: MarginStrut,
+    // Margins below the last child with content.
+    trailing: MarginStrut,
+    // No child has content, so the leading and trailing margins are the same.
+    empty: bool,
+    // Height of the stacked children, including the leading and trailing margins.
+    height: f32,
+}
+
+// Content sizes that the formatting context of a box imposes on it, like the flexed width of a
+// flex item, in place of the ones its style gives.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ImposedSize {
+    pub(crate) width: Option<f32>,
+    pub(crate) height: Option<f32>,
+}
+
+// The margins of a box as seen by its parent, after collapsing with its children.
+struct CollapsedMargins {
+    top: MarginStrut,
+    bottom: MarginStrut,
+    // The box is empty and its top and bottom margins collapse together into `top`.
+    through: bool,
 }
 
 #[derive(Debug, Clone)]
-pub enum InlineFormattingContextRun {
-    // TextRun(),
-    Atom(usize),
+pub enum InlineFormattingContextRun<'a> {
+    TextRun(TextRun<'a>),
+    // The atomic inline at a path in the anonymous block.
+    Atom(Vec<usize>),
+    Fragment(InlineFragment),
 }
 
 #[derive(Debug, Clone, Default)]
-pub struct InlineFormattingContext {
-    pub(crate) elements: Vec<InlineFormattingContextRun>,
+pub struct InlineFormattingContext<'a> {
+    pub(crate) elements: Vec<InlineFormattingContextRun<'a>>,
+    // The style of the box the lines are in, which they take `text-align` and `text-indent` from.
+    pub(crate) style: Option<&'a StyledNode<'a>>,
+    // The lines start the box they're in, so the first one is indented.
+    pub(crate) first_line: bool,
+    // Distance from the top of the block to the baseline of its last line, if it has any.
+    pub(crate) baseline: Option<f32>,
+}
+
+// A word of text placed on a line, cut from the text box at `path` in the anonymous block.
+#[derive(Debug, Clone)]
+pub struct TextRun<'a> {
+    pub(crate) path: Vec<usize>,
+    pub(crate) text: String,
+    pub(crate) font: Option<&'a FontFace>,
+    // The text shaped in the font, in the order it's painted in.
+    pub(crate) glyphs: Vec<Glyph>,
+    pub(crate) font_size: f32,
+    // Position relative to the content area of the anonymous block:
+    pub(crate) left: f32,
+    pub(crate) top: f32,
+    pub(crate) width: f32,
+    pub(crate) height: f32,
+    // Distance from the top of the run to its baseline.
+    pub(crate) ascent: f32,
+}
+
+// The part of an inline box on one line, around what's in it there, for the box at `path` in the
+// anonymous block.
+#[derive(Debug, Clone)]
+pub struct InlineFragment {
+    pub(crate) path: Vec<usize>,
+    // Position of the border box relative to the content area of the anonymous block:
+    pub(crate) left: f32,
+    pub(crate) top: f32,
+    pub(crate) width: f32,
+    pub(crate) height: f32,
+    // Whether the fragment has the left and right edges of the box. With
+    // `box-decoration-break: slice`, only the first and last fragments have them.
+    pub(crate) first: bool,
+    pub(crate) last: bool,
 }
 
 impl Dimensions {
@@ -121,18 +241,141 @@ pub enum BoxType<'a> {
     BlockNode(&'a StyledNode<'a>),
     InlineBlockNode(&'a StyledNode<'a>),
     InlineNode(&'a StyledNode<'a>),
-    AnonymousBlock(InlineFormattingContext),
+    TextNode(&'a StyledNode<'a>),
+    // The marker of a list item, with its text. It's styled like the list item.
+    MarkerNode(&'a StyledNode<'a>, String),
+    // The text a `::before` or `::after` pseudo-element generates with its `content`.
+    ContentNode(&'a StyledNode<'a>, String),
+    AnonymousBlock(InlineFormattingContext<'a>),
+    FlexNode(&'a StyledNode<'a>),
+    InlineFlexNode(&'a StyledNode<'a>),
+    GridNode(&'a StyledNode<'a>),
+    InlineGridNode(&'a StyledNode<'a>),
+    TableNode(&'a StyledNode<'a>),
+    InlineTableNode(&'a StyledNode<'a>),
+    // Row groups: `table-row-group`, `table-header-group` and `table-footer-group`.
+    TableRowGroupNode(&'a StyledNode<'a>),
+    TableRowNode(&'a StyledNode<'a>),
+    TableCellNode(&'a StyledNode<'a>),
+    TableCaptionNode(&'a StyledNode<'a>),
+    // Columns and column groups.
+    TableColumnNode(&'a StyledNode<'a>),
 }
 enum Display {
     Inline,
     InlineBlock,
     Block,
+    ListItem,
+    Flex,
+    InlineFlex,
+    Grid,
+    InlineGrid,
+    Table,
+    InlineTable,
+    TableRowGroup,
+    TableRow,
+    TableCell,
+    TableCaption,
+    TableColumn,
+    None,
+}
+
+// The positioning scheme of a box, from the `position` property.
+// https://www.w3.org/TR/css-position-3/#position-property
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+    Fixed,
+    Sticky,
+}
+
+// The side a box floats to, from the `float` property.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Float {
+    None,
+    Left,
+    Right,
+}
+
+// The sides of earlier floats that a box is moved below, from the `clear` property.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Clear {
     None,
+    Left,
+    Right,
+    Both,
 }
 
 impl<'a> LayoutBox<'a> {
     pub fn is_segmentable(&self) -> bool {
-        false
+        matches!(
+            self.box_type,
+            BoxType::TextNode(_) | BoxType::MarkerNode(..) | BoxType::ContentNode(..)
+        )
+    }
+
+    // The positioning scheme of the box. Anonymous boxes and boxes of text are never positioned.
+    pub fn position(&self) -> Position {
+        match self.box_type {
+            BoxType::AnonymousBlock(_)
+            | BoxType::TextNode(_)
+            | BoxType::MarkerNode(..)
+            | BoxType::ContentNode(..) => Position::Static,
+            _ => self.get_style_node().position(),
+        }
+    }
+
+    pub fn is_positioned(&self) -> bool {
+        self.position() != Position::Static
+    }
+
+    // Absolutely positioned boxes are taken out of flow: they take up no space among their
+    // siblings.
+    pub fn is_out_of_flow(&self) -> bool {
+        matches!(self.position(), Position::Absolute | Position::Fixed)
+    }
+
+    // Whether the box is laid out as a block among blocks, rather than on lines or on its own.
+    pub fn is_block_level(&self) -> bool {
+        matches!(
+            self.box_type,
+            BoxType::BlockNode(_)
+                | BoxType::FlexNode(_)
+                | BoxType::GridNode(_)
+                | BoxType::TableNode(_)
+                | BoxType::TableRowGroupNode(_)
+                | BoxType::TableRowNode(_)
+                | BoxType::TableCellNode(_)
+                | BoxType::TableCaptionNode(_)
+                | BoxType::TableColumnNode(_)
+        ) && !self.is_float()
+            && !self.is_out_of_flow()
+    }
+
+    // Floats are out of flow too, but lines and blocks in flow make room for them. An absolutely
+    // positioned box doesn't float.
+    pub fn is_float(&self) -> bool {
+        match self.box_type {
+            BoxType::AnonymousBlock(_)
+            | BoxType::TextNode(_)
+            | BoxType::MarkerNode(..)
+            | BoxType::ContentNode(..) => false,
+            _ => self.get_style_node().float() != Float::None && !self.is_out_of_flow(),
+        }
+    }
+
+    // The `z-index` of a positioned box, or `None` for `auto`, when the box doesn't establish a
+    // stacking context.
+    pub fn z_index(&self) -> Option<i32> {
+        if !self.is_positioned() {
+            return None;
+        }
+        match self.get_style_node().value("z-index") {
+            Some(Value::Number(z_index)) => Some(z_index as i32),
+            _ => None,
+        }
     }
 }
 
@@ -150,41 +393,291 @@ impl<'a> StyledNode<'a> {
 
     // The value of the `display` property (defaults to inline).
     fn display(&self) -> Display {
-        match self.value("display") {
+        match &*self.display_keyword() {
+            "block" => Display::Block,
+            "list-item" => Display::ListItem,
+            "inline-block" => Display::InlineBlock,
+            "flex" => Display::Flex,
+            "inline-flex" => Display::InlineFlex,
+            "grid" => Display::Grid,
+            "inline-grid" => Display::InlineGrid,
+            "table" => Display::Table,
+            "inline-table" => Display::InlineTable,
+            "table-row-group" | "table-header-group" | "table-footer-group" => {
+                Display::TableRowGroup
+            }
+            "table-row" => Display::TableRow,
+            "table-cell" => Display::TableCell,
+            "table-caption" => Display::TableCaption,
+            "table-column" | "table-column-group" => Display::TableColumn,
+            "none" => Display::None,
+            _ => Display::Inline,
+        }
+    }
+
+    // The `display` keyword. Without one, lists and table elements are displayed as the lists
+    // and table parts they are, like the HTML user agent style sheet has it.
+    pub(crate) fn display_keyword(&self) -> String {
+        if let Some(Value::Keyword(display)) = self.value("display") {
+            return display;
+        }
+        let NodeType::Element(ref element) = self.node.node_type else {
+            return "inline".to_string();
+        };
+        if self.pseudo_element.is_some() {
+            return "inline".to_string();
+        }
+        match &*element.tag_name.to_ascii_lowercase() {
+            "ol" | "ul" => "block",
+            "li" => "list-item",
+            "table" => "table",
+            "caption" => "table-caption",
+            "colgroup" => "table-column-group",
+            "col" => "table-column",
+            "thead" => "table-header-group",
+            "tbody" => "table-row-group",
+            "tfoot" => "table-footer-group",
+            "tr" => "table-row",
+            "td" | "th" => "table-cell",
+            _ => "inline",
+        }
+        .to_string()
+    }
+
+    // The value of the `position` property (defaults to static).
+    pub fn position(&self) -> Position {
+        match self.value("position") {
             Some(Value::Keyword(s)) => match &*s {
-                "block" => Display::Block,
-                "inline-block" => Display::InlineBlock,
-                "none" => Display::None,
-                _ => Display::Inline,
+                "relative" => Position::Relative,
+                "absolute" => Position::Absolute,
+                "fixed" => Position::Fixed,
+                "sticky" => Position::Sticky,
+                _ => Position::Static,
             },
-            _ => Display::Inline,
+            _ => Position::Static,
+        }
+    }
+
+    // The value of the `float` property (defaults to none).
+    pub fn float(&self) -> Float {
+        match self.value("float") {
+            Some(Value::Keyword(s)) => match &*s {
+                "left" => Float::Left,
+                "right" => Float::Right,
+                _ => Float::None,
+            },
+            _ => Float::None,
+        }
+    }
+
+    // The value of the `clear` property (defaults to none).
+    pub fn clear(&self) -> Clear {
+        match self.value("clear") {
+            Some(Value::Keyword(s)) => match &*s {
+                "left" => Clear::Left,
+                "right" => Clear::Right,
+                "both" => Clear::Both,
+                _ => Clear::None,
+            },
+            _ => Clear::None,
+        }
+    }
+
+    // Whether specified sizes include padding and border (`box-sizing: border-box`), rather than
+    // only the content box.
+    pub fn is_border_box(&self) -> bool {
+        matches!(self.value("box-sizing"), Some(Value::Keyword(s)) if s == "border-box")
+    }
+
+    // The `font-family` list, most preferred first.
+    pub fn font_families(&self) -> Vec<String> {
+        match self.value("font-family") {
+            Some(Value::CommaList(families)) => families.iter().filter_map(family_name).collect(),
+            Some(family) => family_name(&family).into_iter().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // The used `font-size` in px (defaults to 16px).
+    pub fn font_size(&self) -> f32 {
+        match self.value("font-size") {
+            Some(Value::Length(size, Unit::Px)) => size,
+            _ => 16.0,
+        }
+    }
+
+    // The numeric `font-weight` (defaults to 400).
+    pub fn font_weight(&self) -> u16 {
+        match self.value("font-weight") {
+            Some(Value::Keyword(s)) if s == "bold" => 700,
+            Some(Value::Number(weight)) if (1.0..=1000.0).contains(&weight) => weight as u16,
+            _ => 400,
         }
     }
+
+    // The used `line-height` in px. `normal` is what the font's `metrics` suggest.
+    pub fn line_height(&self, metrics: FontMetrics) -> f32 {
+        match self.value("line-height") {
+            Some(Value::Number(factor)) => factor * self.font_size(),
+            Some(Value::Percentage(percentage)) => self.font_size() * percentage / 100.0,
+            Some(value) if value.is_specific_length() => value.to_px(),
+            _ => metrics.ascent + metrics.descent + metrics.line_gap,
+        }
+    }
+
+    // The first font of the `font-family` list there is a face for.
+    pub(crate) fn first_font<'f>(&self, fonts: &'f FontRegistry) -> Option<&'f FontFace> {
+        let families = self.font_families();
+        fonts.find(&families, self.font_weight(), self.font_style(), ' ')
+    }
+
+    pub fn font_style(&self) -> FontStyle {
+        match self.value("font-style") {
+            Some(Value::Keyword(s)) => match &*s {
+                "italic" => FontStyle::Italic,
+                "oblique" => FontStyle::Oblique,
+                _ => FontStyle::Normal,
+            },
+            _ => FontStyle::Normal,
+        }
+    }
+}
+
+// One entry of a `font-family` list: a string, or a sequence of identifiers like `Noto Serif`.
+fn family_name(value: &Value) -> Option<String> {
+    match value {
+        Value::Keyword(family) | Value::Str(family) => Some(family.clone()),
+        Value::List(words) => words
+            .iter()
+            .map(|word| match word {
+                Value::Keyword(word) => Some(word.as_str()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|words| words.join(" ")),
+        _ => None,
+    }
+}
+
+// A keyword property, or `default` when it's unset.
+fn keyword(style: &StyledNode, name: &str, default: &str) -> String {
+    match style.value(name) {
+        Some(Value::Keyword(keyword)) => keyword,
+        _ => default.to_string(),
+    }
 }
 
 // Build the tree of LayoutBoxes, but don't perform any layout calculations yet.
 pub fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
     // Create the root box.
-    let mut root = LayoutBox::new(match style_node.display() {
-        Display::Block => BoxType::BlockNode(style_node),
-        Display::Inline => BoxType::InlineNode(style_node),
-        Display::InlineBlock => BoxType::InlineBlockNode(style_node),
-        Display::None => panic!("Root node has display: none."),
+    let mut root = LayoutBox::new(match style_node.node.node_type {
+        NodeType::Text(_) => BoxType::TextNode(style_node),
+        NodeType::Element(_) => match style_node.display() {
+            Display::Block | Display::ListItem => BoxType::BlockNode(style_node),
+            Display::Inline => BoxType::InlineNode(style_node),
+            Display::InlineBlock => BoxType::InlineBlockNode(style_node),
+            Display::Flex => BoxType::FlexNode(style_node),
+            Display::InlineFlex => BoxType::InlineFlexNode(style_node),
+            Display::Grid => BoxType::GridNode(style_node),
+            Display::InlineGrid => BoxType::InlineGridNode(style_node),
+            Display::Table => BoxType::TableNode(style_node),
+            Display::InlineTable => BoxType::InlineTableNode(style_node),
+            Display::TableRowGroup => BoxType::TableRowGroupNode(style_node),
+            Display::TableRow => BoxType::TableRowNode(style_node),
+            Display::TableCell => BoxType::TableCellNode(style_node),
+            Display::TableCaption => BoxType::TableCaptionNode(style_node),
+            Display::TableColumn => BoxType::TableColumnNode(style_node),
+            Display::None => panic!("Root node has display: none."),
+        },
     });
+    // Absolutely positioned boxes and floats are block-level, though they stay where an inline
+    // box would be in the tree, to keep their position on the line.
+    if root.is_out_of_flow() || root.is_float() {
+        root.blockify();
+    }
+    let is_flex_or_grid_container = matches!(
+        root.box_type,
+        BoxType::FlexNode(_)
+            | BoxType::InlineFlexNode(_)
+            | BoxType::GridNode(_)
+            | BoxType::InlineGridNode(_)
+    );
+    let is_table_container = matches!(
+        root.box_type,
+        BoxType::TableNode(_)
+            | BoxType::InlineTableNode(_)
+            | BoxType::TableRowGroupNode(_)
+            | BoxType::TableRowNode(_)
+            | BoxType::TableColumnNode(_)
+    );
+
+    // A `::before` or `::after` pseudo-element has its `content` for its only child.
+    if let (Some(_), Some(Value::Str(text))) =
+        (style_node.pseudo_element, style_node.value("content"))
+    {
+        root.get_inline_container()
+            .children
+            .push(LayoutBox::new(BoxType::ContentNode(style_node, text)));
+        return root;
+    }
 
-    // Create the descendant boxes.
+    // Create the descendant boxes. List items among them get their markers.
+    let default_type = default_list_style_type(style_node);
+    let build_child = |child: &'a StyledNode<'a>| {
+        let mut layout_box = build_layout_tree(child);
+        if let Display::ListItem = child.display() {
+            layout_box.add_marker(default_type);
+        }
+        layout_box
+    };
     for child in &style_node.children {
         match child.display() {
-            Display::Block => root.children.push(build_layout_tree(child)),
-            Display::Inline => root
-                .get_inline_container()
-                .children
-                .push(build_layout_tree(child)),
-            Display::InlineBlock => root
+            Display::None => {} // Skip nodes with `display: none;`
+            // Every child of a flex or grid container is a block-level item, and every child of
+            // a table part is a block-level table part, or a cell. Runs of text are wrapped in
+            // anonymous items or cells, unless they're only white space.
+            _ if is_flex_or_grid_container || is_table_container => match child.node.node_type {
+                NodeType::Text(ref text) if text.trim().is_empty() => {}
+                NodeType::Text(_) => root
+                    .get_inline_container()
+                    .children
+                    .push(build_child(child)),
+                NodeType::Element(_) => {
+                    let mut item = build_child(child);
+                    item.blockify();
+                    root.children.push(item);
+                }
+            },
+            Display::Block
+            | Display::ListItem
+            | Display::Flex
+            | Display::Grid
+            | Display::Table
+            | Display::TableRowGroup
+            | Display::TableRow
+            | Display::TableCell
+            | Display::TableCaption
+            | Display::TableColumn => root.children.push(build_child(child)),
+            // An inline box with blocks in it is split around them, unless it's in an inline box
+            // itself, which is split with it.
+            Display::Inline if matches!(root.box_type, BoxType::InlineNode(_)) => {
+                root.children.push(build_child(child))
+            }
+            Display::Inline => {
+                for piece in build_child(child).split_around_blocks() {
+                    match piece.is_block_level() {
+                        true => root.children.push(piece),
+                        false => root.get_inline_container().children.push(piece),
+                    }
+                }
+            }
+            Display::InlineBlock
+            | Display::InlineFlex
+            | Display::InlineGrid
+            | Display::InlineTable => root
                 .get_inline_container()
                 .children
-                .push(build_layout_tree(child)),
-            Display::None => {} // Skip nodes with `display: none;`
+                .push(build_child(child)),
         }
     }
     return root;
@@ -193,9 +686,19 @@ pub fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
 impl Value {
     pub fn is_specific_length(&self) -> bool {
         match self {
-            Value::Length(_, _) => true,
+            Value::Length(_, Unit::Px) => true,
+            Value::Length(_, Unit::Fr) => false,
             Value::Keyword(_) => false,
             Value::ColorValue(_) => false,
+            Value::Str(_) => false,
+            Value::Var(_, _) => false,
+            // A unitless zero is a valid length.
+            Value::Number(number) => *number == 0.0,
+            Value::Percentage(_) => false,
+            Value::Url(_) => false,
+            Value::Function(_, _) => false,
+            Value::List(_) => false,
+            Value::CommaList(_) => false,
         }
     }
 
@@ -214,6 +717,9 @@ impl<'a> LayoutBox<'a> {
             box_type,
             dimensions: Default::default(), // initially set all fields to 0.0
             children: Vec::new(),
+            child_margins: Default::default(),
+            imposed_size: Default::default(),
+            split: (false, false),
         }
     }
 
@@ -221,10 +727,35 @@ impl<'a> LayoutBox<'a> {
         match self.box_type {
             BoxType::BlockNode(node)
             | BoxType::InlineNode(node)
-            | BoxType::InlineBlockNode(node) => node,
+            | BoxType::InlineBlockNode(node)
+            | BoxType::TextNode(node)
+            | BoxType::FlexNode(node)
+            | BoxType::InlineFlexNode(node)
+            | BoxType::GridNode(node)
+            | BoxType::InlineGridNode(node)
+            | BoxType::TableNode(node)
+            | BoxType::InlineTableNode(node)
+            | BoxType::TableRowGroupNode(node)
+            | BoxType::TableRowNode(node)
+            | BoxType::TableCellNode(node)
+            | BoxType::TableCaptionNode(node)
+            | BoxType::TableColumnNode(node)
+            | BoxType::MarkerNode(node, _)
+            | BoxType::ContentNode(node, _) => node,
             BoxType::AnonymousBlock(_) => panic!("Anonymous block box has no style node"),
         }
     }
+
+    // Turn an inline-level box into the block-level box of the same kind.
+    fn blockify(&mut self) {
+        self.box_type = match self.box_type {
+            BoxType::InlineNode(node) | BoxType::InlineBlockNode(node) => BoxType::BlockNode(node),
+            BoxType::InlineFlexNode(node) => BoxType::FlexNode(node),
+            BoxType::InlineGridNode(node) => BoxType::GridNode(node),
+            BoxType::InlineTableNode(node) => BoxType::TableNode(node),
+            ref box_type => box_type.clone(),
+        };
+    }
     // ...
 
     pub fn is_width_auto(&self) -> bool {
@@ -235,547 +766,728 @@ impl<'a> LayoutBox<'a> {
         width.is_auto()
     }
 
-    pub fn layout(&mut self, container_width: Value, context_constraints_width: Value) {
+    // Lay out the tree rooted at this box in a viewport of `container_width` by
+    // `container_height`: boxes in flow first, then the positioned ones.
+    pub fn layout(
+        &mut self,
+        container_width: Value,
+        container_height: Value,
+        context_constraints_width: Value,
+        fonts: &'a FontRegistry,
+    ) {
+        self.layout_box(
+            container_width.clone(),
+            container_height.clone(),
+            context_constraints_width,
+            fonts,
+        );
+        self.layout_positioned(container_width.to_px(), container_height.to_px(), fonts);
+    }
+
+    // Lay out the box and its descendants in flow. `container_height` is the height of the
+    // containing block if it is definite, and `auto` otherwise; for the root, it's the viewport
+    // height.
+    fn layout_box(
+        &mut self,
+        container_width: Value,
+        container_height: Value,
+        context_constraints_width: Value,
+        fonts: &'a FontRegistry,
+    ) {
         // content-box
         match self.box_type.clone() {
-            BoxType::BlockNode(_) => self.layout_block(container_width, context_constraints_width),
-            BoxType::InlineNode(_) => {
-                self.layout_inline(container_width, context_constraints_width)
-            } // TODO
-            BoxType::InlineBlockNode(_) => {
-                self.layout_inline_block(container_width, context_constraints_width)
-            } // TODO
-            BoxType::AnonymousBlock(_) => {
-                self.layout_anonymous(container_width, context_constraints_width)
-            } // TODO
+            BoxType::BlockNode(_) => self.layout_block(
+                container_width,
+                container_height,
+                context_constraints_width,
+                fonts,
+            ),
+            BoxType::InlineNode(_) => self.layout_inline(
+                container_width,
+                container_height,
+                context_constraints_width,
+                fonts,
+            ), // TODO
+            BoxType::InlineBlockNode(_) => self.layout_inline_block(
+                container_width,
+                container_height,
+                context_constraints_width,
+                fonts,
+            ), // TODO
+            BoxType::TextNode(_) | BoxType::MarkerNode(..) | BoxType::ContentNode(..) => {
+                self.layout_text(fonts)
+            }
+            BoxType::AnonymousBlock(_) => self.layout_anonymous(
+                container_width,
+                container_height,
+                context_constraints_width,
+                fonts,
+                &mut FloatContext::default(),
+                (0.0, 0.0),
+            ), // TODO
+            BoxType::FlexNode(_) | BoxType::InlineFlexNode(_) => self.layout_flex(
+                container_width,
+                container_height,
+                context_constraints_width,
+                fonts,
+            ),
+            BoxType::GridNode(_) | BoxType::InlineGridNode(_) => self.layout_grid(
+                container_width,
+                container_height,
+                context_constraints_width,
+                fonts,
+            ),
+            BoxType::TableNode(_) | BoxType::InlineTableNode(_) => self.layout_table(
+                container_width,
+                container_height,
+                context_constraints_width,
+                fonts,
+            ),
+            // Outside of a table, its parts are laid out like blocks.
+            BoxType::TableRowGroupNode(_)
+            | BoxType::TableRowNode(_)
+            | BoxType::TableCellNode(_)
+            | BoxType::TableCaptionNode(_) => self.layout_block(
+                container_width,
+                container_height,
+                context_constraints_width,
+                fonts,
+            ),
+            // Columns have no content; a table sizes them.
+            BoxType::TableColumnNode(_) => {}
         }
     }
 
-    fn layout_block(&mut self, container_width: Value, context_constraints_width: Value) {
-        self.calculate_block(container_width.clone(), context_constraints_width.clone());
+    fn layout_block(
+        &mut self,
+        container_width: Value,
+        container_height: Value,
+        context_constraints_width: Value,
+        fonts: &'a FontRegistry,
+    ) {
+        // Laid out on its own, the box is the root of a block formatting context, which grows to
+        // contain its floats.
+        let mut floats = FloatContext::default();
+        self.calculate_block(
+            container_width,
+            container_height.clone(),
+            context_constraints_width,
+            fonts,
+            &mut floats,
+            None,
+        );
+        if self.resolve_height(&container_height).is_auto() {
+            self.dimensions.inner.height =
+                self.clamp_height(self.dimensions.inner.height.max(floats.bottom()));
+        }
     }
 
-    fn calculate_block(&mut self, container_width: Value, context_constraints_width: Value) {
-        // 1. width -> (specific, auto take one line from container, empty auto)
-
-        let style = self.get_style_node();
-        let mut width = style
-            .value("width")
-            .unwrap_or(Value::Keyword("auto".to_string()));
-
-        let mut self_as_container_width = Value::Keyword("auto".to_string());
-        let mut self_as_context_constraints_width = context_constraints_width.clone();
-
-        let mut is_self_no_filled_auto = false;
-
-        let zero = Value::Length(0.0, Unit::Px);
-
-        if width.is_specific_length() {
-            let specific_width = width.to_px();
-            let underflow_content = specific_width - {
-                let mut margin_left = style.lookup("margin-left", "margin", &zero);
-                let mut margin_right = style.lookup("margin-right", "margin", &zero);
-
-                let border_left = style.lookup("border-left-width", "border-width", &zero);
-                let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-                let padding_left = style.lookup("padding-left", "padding", &zero);
-                let padding_right = style.lookup("padding-right", "padding", &zero);
-
-                sum([
-                    &margin_left,
-                    &margin_right,
-                    &border_left,
-                    &border_right,
-                    &padding_left,
-                    &padding_right,
-                    &width,
-                ]
-                .iter()
-                .map(|v| v.to_px()))
-            };
+    // Lay out a block box in the block formatting context of `floats`. `origin` is where its
+    // margin box is in the context, or `None` if the box is the root of the context.
+    fn calculate_block(
+        &mut self,
+        container_width: Value,
+        container_height: Value,
+        context_constraints_width: Value,
+        fonts: &'a FontRegistry,
+        floats: &mut FloatContext,
+        origin: Option<(f32, f32)>,
+    ) {
+        // 1. width -> (imposed or specific, auto take one line from container, empty auto)
 
-            self.dimensions.inner.width = underflow_content;
-            self_as_container_width = Value::Length(underflow_content, Unit::Px);
-            self_as_context_constraints_width = self_as_container_width.clone();
-        } else {
-            if container_width.is_specific_length() {
-                let specific_container_width = container_width.to_px();
-
-                let underflow_content = specific_container_width - {
-                    let mut margin_left = style.lookup("margin-left", "margin", &zero);
-                    let mut margin_right = style.lookup("margin-right", "margin", &zero);
-
-                    let border_left = style.lookup("border-left-width", "border-width", &zero);
-                    let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-                    let padding_left = style.lookup("padding-left", "padding", &zero);
-                    let padding_right = style.lookup("padding-right", "padding", &zero);
-
-                    sum([
-                        &margin_left,
-                        &margin_right,
-                        &border_left,
-                        &border_right,
-                        &padding_left,
-                        &padding_right,
-                        &width,
-                    ]
-                    .iter()
-                    .map(|v| v.to_px()))
-                };
-                self.dimensions.inner.width = underflow_content;
-                self_as_container_width = Value::Length(underflow_content, Unit::Px);
-                self_as_context_constraints_width = self_as_container_width.clone();
-            } else {
-                is_self_no_filled_auto = true;
-                let underflow_constraint = context_constraints_width.to_px() - {
-                    let mut margin_left = style.lookup("margin-left", "margin", &zero);
-                    let mut margin_right = style.lookup("margin-right", "margin", &zero);
-
-                    let border_left = style.lookup("border-left-width", "border-width", &zero);
-                    let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-                    let padding_left = style.lookup("padding-left", "padding", &zero);
-                    let padding_right = style.lookup("padding-right", "padding", &zero);
-
-                    sum([
-                        &margin_left,
-                        &margin_right,
-                        &border_left,
-                        &border_right,
-                        &padding_left,
-                        &padding_right,
-                        &width,
-                    ]
-                    .iter()
-                    .map(|v| v.to_px()))
-                };
-                self_as_context_constraints_width = Value::Length(underflow_constraint, Unit::Px);
-            }
-        }
+        let (self_as_container_width, self_as_context_constraints_width, is_self_no_filled_auto) =
+            self.resolve_width(&container_width, &context_constraints_width);
 
         // inner edge size position
-        {
-            let style = self.get_style_node();
-            // margin, border, and padding have initial value 0.
-            let zero = Value::Length(0.0, Unit::Px);
-
-            let margin_left = style.lookup("margin-left", "margin", &zero);
-            let margin_right = style.lookup("margin-right", "margin", &zero);
-
-            let border_left = style.lookup("border-left-width", "border-width", &zero);
-            let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-            let padding_left = style.lookup("padding-left", "padding", &zero);
-            let padding_right = style.lookup("padding-right", "padding", &zero);
-
-            let d = &mut self.dimensions;
+        self.resolve_edges();
 
-            d.padding.left = padding_left.to_px();
-            d.padding.right = padding_right.to_px();
-
-            d.border.left = border_left.to_px();
-            d.border.right = border_right.to_px();
+        // In flow at a known width, `auto` margins are resolved up front, to know where the
+        // content goes among the floats.
+        if origin.is_some()
+            && !is_self_no_filled_auto
+            && let Value::Length(width, _) = container_width
+        {
+            self.resolve_horizontal_margins(width);
+        }
+        let d = &self.dimensions;
+        let content = origin.map_or((0.0, 0.0), |(x, y)| (x + d.inner.left, y + d.inner.top));
 
-            d.margin.left = margin_left.to_px();
-            d.margin.right = margin_right.to_px();
-            // If margin-top or margin-bottom is `auto`, the used value is zero.
-            d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-            d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+        // height -> (imposed, specific or percentage of definite container: definite, auto)
+        let self_as_container_height = self.resolve_height(&container_height);
 
-            d.border.top = style
-                .lookup("border-top-width", "border-width", &zero)
-                .to_px();
-            d.border.bottom = style
-                .lookup("border-bottom-width", "border-width", &zero)
-                .to_px();
+        // 2. recursive -> vertical(block, anonymous block), measuring an empty auto width
+        if is_self_no_filled_auto {
+            // Floats placed while measuring are placed again at the final width.
+            let placed = floats.len();
+            let mut children_max_width = 0f32;
+            // Absolutely positioned children are laid out once the containing block is known.
+            for child in self
+                .children
+                .iter_mut()
+                .filter(|child| !child.is_out_of_flow() && !child.is_outside_marker())
+            {
+                child.layout_in_flow(
+                    self_as_container_width.clone(),
+                    self_as_container_height.clone(),
+                    self_as_context_constraints_width.clone(),
+                    fonts,
+                    floats,
+                    content,
+                );
+                children_max_width = children_max_width.max(child.dimensions.margin_box().width);
+            }
+            floats.truncate(placed);
 
-            d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-            d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
-            self.dimensions.inner.left = self.dimensions.margin.left
-                + self.dimensions.border.left
-                + self.dimensions.padding.left;
-            self.dimensions.inner.top = self.dimensions.margin.top
-                + self.dimensions.border.top
-                + self.dimensions.padding.top;
+            // 3. self cross: width -> (specific, max by children)
+            self.dimensions.inner.width = self.clamp_width(children_max_width);
         }
 
-        // 2. recursive -> vertical(block, anonymous block)
-        let mut children_sum_height = 0f32;
-        let mut children_max_width = 0f32;
+        // 4. children: laid out at the final width and stacked, collapsing the margins between
+        // siblings. Floats and clearance depend on where the earlier children went, so each child
+        // is placed before the next is laid out.
+        let zero = Value::Length(0.0, Unit::Px);
+        let width = self.dimensions.inner.width;
+        let extent = (content.0, content.0 + width);
+        // Bottom border edge of the last child with content.
+        let mut cursor = 0f32;
+        let mut strut = MarginStrut::default();
+        let mut leading = None;
         for child in &mut self.children {
-            child.layout(
-                self_as_container_width.clone(),
-                self_as_context_constraints_width.clone(),
-            );
-            child.dimensions.box_offset.top = children_sum_height;
-            children_sum_height += child.dimensions.margin_box().height;
-            children_max_width = children_max_width.max(child.dimensions.margin_box().width);
-        }
-
-        // 3. self main: height -> (specific, auto by children sum)
-        let mut height = style
-            .value("height")
-            .unwrap_or(Value::Keyword("auto".to_string()));
-        if height.is_specific_length() {
-            self.dimensions.inner.height = height.to_px();
-        } else if height.is_auto() {
-            self.dimensions.inner.height = children_sum_height;
-        }
-
-        // 4. self cross: width -> (specific, max by children)
-        if is_self_no_filled_auto {
-            self.dimensions.inner.width = children_max_width;
-            self.dimensions.inner.width = children_max_width;
-        }
+            child.dimensions.box_offset.left = 0.0;
+            if child.is_out_of_flow() {
+                // The static position: where the top margin edge would be if it were in flow.
+                child.dimensions.box_offset.top = cursor + strut.resolve();
+                continue;
+            }
+            if child.is_outside_marker() {
+                child.layout_outside_marker(&self_as_container_height, fonts);
+                continue;
+            }
+            if child.is_float() {
+                child.layout_float(&self_as_container_height, width, fonts);
+                let top = content.1 + cursor + strut.resolve();
+                child.place_float(floats, top, content, extent);
+                continue;
+            }
 
-        // 5. fill children: width -> block (empty auto -> fill one line)
-        if is_self_no_filled_auto {
-            let underflow_content = self.dimensions.inner.width - {
-                let margin_left = style.lookup("margin-left", "margin", &zero);
-                let margin_right = style.lookup("margin-right", "margin", &zero);
-
-                let border_left = style.lookup("border-left-width", "border-width", &zero);
-                let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-                let padding_left = style.lookup("padding-left", "padding", &zero);
-                let padding_right = style.lookup("padding-right", "padding", &zero);
-
-                sum([
-                    &margin_left,
-                    &margin_right,
-                    &border_left,
-                    &border_right,
-                    &padding_left,
-                    &padding_right,
-                    &width,
-                ]
-                .iter()
-                .map(|v| v.to_px()))
+            // Where the border box goes, unless its margins collapse with the child's children.
+            let style = child.item_style();
+            let margin_top = style.map_or(0.0, |style| {
+                style.lookup("margin-top", "margin", &zero).to_px()
+            });
+            let mut estimate = strut;
+            estimate.adjoin(MarginStrut::new(margin_top));
+            let mut border_top = cursor + estimate.resolve();
+            // Clearance puts the border box right below the floats, with no margin above it.
+            let clear = style.map_or(Clear::None, StyledNode::clear);
+            let clearance = floats
+                .clearance(clear)
+                .map(|bottom| bottom - content.1)
+                .filter(|&bottom| bottom > border_top);
+            if let Some(bottom) = clearance {
+                border_top = bottom;
+            }
+            // A box that establishes a formatting context goes beside the floats.
+            let (left, right) = match child.establishes_context() {
+                true => floats.band(content.1 + border_top, 0.0, extent),
+                false => extent,
             };
-            self_as_container_width = Value::Length(underflow_content, Unit::Px);
-            self_as_context_constraints_width = self_as_container_width.clone();
-            for child in &mut self.children {
-                if !matches!(child.box_type, BoxType::AnonymousBlock(_)) && child.is_width_auto() {
-                    // auto and not anonymous -> retake one line
-                    child.layout(
-                        self_as_container_width.clone(),
-                        self_as_context_constraints_width.clone(),
-                    );
+            let child_width = Value::Length(right - left, Unit::Px);
+            child.layout_in_flow(
+                child_width.clone(),
+                self_as_container_height.clone(),
+                child_width,
+                fonts,
+                floats,
+                (left, content.1 + border_top - margin_top),
+            );
+            // horizontal margins -> (auto, over-constrained) by the final width
+            child.resolve_horizontal_margins(right - left);
+            child.dimensions.box_offset.left = left - content.0;
+
+            let margins = child.collapse_margins(&self_as_container_height);
+            let border_top = match clearance {
+                Some(border_top) => {
+                    cursor = border_top;
+                    strut = MarginStrut::default();
+                    border_top
                 }
+                None => {
+                    strut.adjoin(margins.top);
+                    cursor + strut.resolve()
+                }
+            };
+            child.dimensions.box_offset.top = border_top - child.dimensions.margin.top;
+            if margins.through {
+                continue;
             }
+            leading.get_or_insert(strut);
+            let d = &child.dimensions;
+            cursor = border_top
+                + d.border.top
+                + d.padding.top
+                + d.inner.height
+                + d.padding.bottom
+                + d.border.bottom;
+            strut = margins.bottom;
         }
+        // Margins adjoining the top and bottom of the content box are kept here, and recorded in
+        // `child_margins` for the parent to collapse.
+        self.child_margins = ChildMargins {
+            leading: leading.unwrap_or(strut),
+            trailing: strut,
+            empty: leading.is_none(),
+            height: cursor + strut.resolve(),
+        };
+
+        // 5. self main: height -> (specific, auto by children stacked with collapsed margins)
+        self.dimensions.inner.height = match self_as_container_height {
+            Value::Length(height, _) => height,
+            _ => self.clamp_height(self.child_margins.height),
+        };
+    }
 
-        // 6. fixing children #[cfg(not(margin-auto))]
-        for child in &mut self.children {
-            // edge size auto by position block
-            // self.dimensions.box_offset.left; // no margin so to do nothing
+    // Lay out a child of a block box at `origin`. Block boxes and lines go on in the same block
+    // formatting context; other boxes establish their own.
+    fn layout_in_flow(
+        &mut self,
+        container_width: Value,
+        container_height: Value,
+        context_constraints_width: Value,
+        fonts: &'a FontRegistry,
+        floats: &mut FloatContext,
+        origin: (f32, f32),
+    ) {
+        match self.box_type {
+            BoxType::BlockNode(_) if !self.establishes_context() => self.calculate_block(
+                container_width,
+                container_height,
+                context_constraints_width,
+                fonts,
+                floats,
+                Some(origin),
+            ),
+            BoxType::AnonymousBlock(_) => self.layout_anonymous(
+                container_width,
+                container_height,
+                context_constraints_width,
+                fonts,
+                floats,
+                origin,
+            ),
+            _ => self.layout_box(
+                container_width,
+                container_height,
+                context_constraints_width,
+                fonts,
+            ),
         }
     }
 
-    fn layout_anonymous(&mut self, container_width: Value, context_constraints_width: Value) {
-        // 1. width -> (empty auto)
-
-        let self_as_container_width = Value::Keyword("auto".to_string());
-        let self_as_context_constraints_width = context_constraints_width.clone();
-
-        // 2. line break recursive -> horizontal(inline run, inline block)
-
-        // line break into ifc and recursive ifc segments // todo!()
-
-        let mut this_line_children_sum_width = 0f32;
-        let mut this_line_children_max_height = 0f32;
-        let mut computed_lines_sum_height = 0f32;
-        let mut computed_lines_max_width = 0f32;
-
-        let ifc_constraints_width = self_as_context_constraints_width.to_px();
-        let mut ifc_elements = vec![];
-
-        for (i, child) in &mut self.children.iter_mut().enumerate() {
-            if child.is_segmentable() {
-                todo!()
-            } else {
-                child.layout(
-                    self_as_container_width.clone(),
-                    self_as_context_constraints_width.clone(),
-                );
-                let mut next_sum_width =
-                    this_line_children_sum_width + child.dimensions.margin_box().width;
+    pub fn layout_inline(
+        &mut self,
+        container_width: Value,
+        container_height: Value,
+        context_constraints_width: Value,
+        fonts: &'a FontRegistry,
+    ) {
+        let style = self.get_style_node();
+        let zero = Value::Length(0.0, Unit::Px);
+        let width = style.lookup("measure-width", "ms-width", &zero);
+        let height = style.lookup("measure-height", "ms-height", &zero);
+        self.dimensions.inner.width = width.to_px();
+        self.dimensions.inner.height = height.to_px();
 
-                if next_sum_width > ifc_constraints_width {
-                    // wrap
-                    computed_lines_sum_height += this_line_children_max_height;
-                    computed_lines_max_width = ifc_constraints_width;
-                    this_line_children_sum_width = 0f32;
-                    this_line_children_max_height = 0f32;
+        // Contents are laid out on a single line, without wrapping.
+        for child in self
+            .children
+            .iter_mut()
+            .filter(|child| !child.is_out_of_flow())
+        {
+            child.layout_box(
+                Value::Keyword("auto".to_string()),
+                container_height.clone(),
+                Value::Length(f32::INFINITY, Unit::Px),
+                fonts,
+            );
+            self.dimensions.inner.width += child.dimensions.margin_box().width;
+            self.dimensions.inner.height = self
+                .dimensions
+                .inner
+                .height
+                .max(child.dimensions.margin_box().height);
+        }
+    }
 
-                    // 5.top -> child-baseline(inline run, inline-block)
-                    // todo!()
+    // Lay out a text or marker box as a single unbroken line.
+    fn layout_text(&mut self, fonts: &'a FontRegistry) {
+        let runs = self.text_runs(&[], fonts);
+        let space = runs.first().map_or(0.0, |(_, run)| run.space_width());
+        let spaces = runs.iter().skip(1).filter(|(spaced, _)| *spaced).count();
+        self.dimensions.inner.width =
+            sum(runs.iter().map(|(_, run)| run.width)) + space * spaces as f32;
+        self.dimensions.inner.height = runs.iter().map(|(_, run)| run.height).fold(0.0, f32::max);
+    }
 
-                    next_sum_width =
-                        this_line_children_sum_width + child.dimensions.margin_box().width;
+    // Split the text of a text or marker box into words, and words into runs of the clusters of
+    // characters that get the same font, each shaped in its font. Each run comes with whether it
+    // was preceded by white space.
+    fn text_runs(&self, path: &[usize], fonts: &'a FontRegistry) -> Vec<(bool, TextRun<'a>)> {
+        let style = self.get_style_node();
+        let text = self.text();
+        let families = style.font_families();
+        let font_size = style.font_size();
+
+        let mut runs = Vec::new();
+        let mut space_before = text.starts_with(char::is_whitespace);
+        for word in text.split_whitespace() {
+            let mut pieces: Vec<(usize, Option<&FontFace>)> = Vec::new();
+            for (i, cluster) in word.grapheme_indices(true) {
+                let font =
+                    fonts.find_cluster(&families, style.font_weight(), style.font_style(), cluster);
+                match pieces.last() {
+                    Some(&(_, last)) if last.map(ptr::from_ref) == font.map(ptr::from_ref) => {}
+                    _ => pieces.push((i, font)),
                 }
-
-                // 3. width -> (auto by children sum but limit by context)
-                child.dimensions.box_offset.left = this_line_children_sum_width;
-                // 4. height -> lines Σ (max by children)
-                child.dimensions.box_offset.top = computed_lines_sum_height;
-
-                this_line_children_sum_width = next_sum_width;
-                this_line_children_max_height =
-                    this_line_children_max_height.max(child.dimensions.margin_box().height);
-                ifc_elements.push(InlineFormattingContextRun::Atom(i))
             }
+            let ends = pieces.iter().skip(1).map(|&(start, _)| start);
+            for (&(start, font), end) in pieces.iter().zip(ends.chain([word.len()])) {
+                let text = &word[start..end];
+                let glyphs = font.map_or(Vec::new(), |font| font.shape(text, font_size, false));
+                let width = sum(glyphs.iter().map(|glyph| glyph.advance));
+                let (ascent, descent) = inline_box_metrics(style, font);
+                runs.push((
+                    space_before,
+                    TextRun {
+                        path: path.to_vec(),
+                        text: text.to_string(),
+                        font,
+                        glyphs,
+                        font_size,
+                        left: 0.0,
+                        top: 0.0,
+                        width,
+                        height: ascent + descent,
+                        ascent,
+                    },
+                ));
+                space_before = false;
+            }
+            space_before = true;
         }
+        runs
+    }
 
-        // final line
-        // 5.top -> child-baseline(inline run, inline-block)
-        // todo!()
-        computed_lines_sum_height += this_line_children_max_height;
-        computed_lines_max_width = computed_lines_max_width.max(this_line_children_sum_width);
-
-        self.dimensions.inner.width = computed_lines_max_width;
-        self.dimensions.inner.height = computed_lines_sum_height;
-
-        drop(this_line_children_max_height);
-        drop(this_line_children_sum_width);
-
-        match &mut self.box_type {
-            BoxType::AnonymousBlock(ifc) => ifc.elements = ifc_elements,
-            _ => unreachable!(),
-        }
+    // Does the text of a text or marker box end with collapsible white space?
+    fn ends_with_space(&self) -> bool {
+        self.text().ends_with(char::is_whitespace)
     }
 
-    pub fn layout_inline(&mut self, container_width: Value, context_constraints_width: Value) {
-        let style = self.get_style_node();
-        let zero = Value::Length(0.0, Unit::Px);
-        let width = style.lookup("measure-width", "ms-width", &zero);
-        let height = style.lookup("measure-height", "ms-height", &zero);
-        self.dimensions.inner.width = width.to_px();
-        self.dimensions.inner.height = height.to_px();
+    // The text of a text box, or the generated text of a marker or `content`.
+    fn text(&self) -> &str {
+        match self.box_type {
+            BoxType::MarkerNode(_, ref text) | BoxType::ContentNode(_, ref text) => text,
+            _ => match self.get_style_node().node.node_type {
+                NodeType::Text(ref text) => text,
+                NodeType::Element(_) => "",
+            },
+        }
     }
 
+    // An inline block is laid out inside like a block box that is the root of its own block
+    // formatting context.
     pub fn layout_inline_block(
         &mut self,
         container_width: Value,
+        container_height: Value,
         context_constraints_width: Value,
+        fonts: &'a FontRegistry,
     ) {
-        // 1. measurable (width calc from container, height calc from container)
+        self.layout_block(
+            container_width,
+            container_height,
+            context_constraints_width,
+            fonts,
+        );
+    }
 
+    // Resolve the content width from `width`, or by filling the container. Returns the width and
+    // the line length that the children lay out against, and whether the width is still to be
+    // shrunk to fit the children, because the container's width isn't known yet.
+    fn resolve_width(
+        &mut self,
+        container_width: &Value,
+        context_constraints_width: &Value,
+    ) -> (Value, Value, bool) {
         let style = self.get_style_node();
-        let mut width = style
+        let width = style
             .value("width")
             .unwrap_or(Value::Keyword("auto".to_string()));
 
-        let mut self_as_container_width = Value::Keyword("auto".to_string());
-        let mut self_as_context_constraints_width = context_constraints_width.clone();
-
-        let mut is_self_no_filled_auto = false;
+        let underflow_content = if let Some(width) = self.imposed_size.width {
+            width
+        } else if width.is_specific_length() {
+            self.clamp_width(self.content_width(width.to_px()))
+        } else {
+            let zero = Value::Length(0.0, Unit::Px);
+            let edges = sum([
+                style.lookup("margin-left", "margin", &zero),
+                style.lookup("margin-right", "margin", &zero),
+                style.lookup("border-left-width", "border-width", &zero),
+                style.lookup("border-right-width", "border-width", &zero),
+                style.lookup("padding-left", "padding", &zero),
+                style.lookup("padding-right", "padding", &zero),
+            ]
+            .iter()
+            .map(|v| v.to_px()));
+            if !container_width.is_specific_length() {
+                // Lines can't be longer than the widest the box may be.
+                let underflow_constraint =
+                    self.clamp_width(context_constraints_width.to_px() - edges);
+                return (
+                    Value::Keyword("auto".to_string()),
+                    Value::Length(underflow_constraint, Unit::Px),
+                    true,
+                );
+            }
+            self.clamp_width(container_width.to_px() - edges)
+        };
+        self.dimensions.inner.width = underflow_content;
+        let self_as_container_width = Value::Length(underflow_content, Unit::Px);
+        (
+            self_as_container_width.clone(),
+            self_as_container_width,
+            false,
+        )
+    }
 
+    // Set the margin, border and padding edges from the style, and place the content box inside
+    // them.
+    fn resolve_edges(&mut self) {
+        let style = self.get_style_node();
+        // margin, border, and padding have initial value 0.
         let zero = Value::Length(0.0, Unit::Px);
+        let d = &mut self.dimensions;
+
+        d.padding.left = style.lookup("padding-left", "padding", &zero).to_px();
+        d.padding.right = style.lookup("padding-right", "padding", &zero).to_px();
+
+        d.border.left = style
+            .lookup("border-left-width", "border-width", &zero)
+            .to_px();
+        d.border.right = style
+            .lookup("border-right-width", "border-width", &zero)
+            .to_px();
+
+        // An `auto` margin is zero until the containing block resolves it.
+        d.margin.left = style.lookup("margin-left", "margin", &zero).to_px();
+        d.margin.right = style.lookup("margin-right", "margin", &zero).to_px();
+        // If margin-top or margin-bottom is `auto`, the used value is zero.
+        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
+        d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+
+        d.border.top = style
+            .lookup("border-top-width", "border-width", &zero)
+            .to_px();
+        d.border.bottom = style
+            .lookup("border-bottom-width", "border-width", &zero)
+            .to_px();
+
+        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
+        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+
+        d.inner.left = d.margin.left + d.border.left + d.padding.left;
+        d.inner.top = d.margin.top + d.border.top + d.padding.top;
+    }
 
-        if width.is_specific_length() {
-            let specific_width = width.to_px();
-            let underflow_content = specific_width - {
-                let mut margin_left = style.lookup("margin-left", "margin", &zero);
-                let mut margin_right = style.lookup("margin-right", "margin", &zero);
-
-                let border_left = style.lookup("border-left-width", "border-width", &zero);
-                let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-                let padding_left = style.lookup("padding-left", "padding", &zero);
-                let padding_right = style.lookup("padding-right", "padding", &zero);
-
-                sum([
-                    &margin_left,
-                    &margin_right,
-                    &border_left,
-                    &border_right,
-                    &padding_left,
-                    &padding_right,
-                    &width,
-                ]
-                .iter()
-                .map(|v| v.to_px()))
-            };
-
-            self.dimensions.inner.width = underflow_content;
-            self_as_container_width = Value::Length(underflow_content, Unit::Px);
-            self_as_context_constraints_width = self_as_container_width.clone();
+    // The content height if it's definite, from a size imposed by the formatting context, or from
+    // `height`. Otherwise `auto`: the height depends on the content.
+    fn resolve_height(&self, container_height: &Value) -> Value {
+        if let Some(height) = self.imposed_size.height {
+            return Value::Length(height, Unit::Px);
+        }
+        let height = self.specified_height(container_height);
+        if height.is_specific_length() {
+            Value::Length(
+                self.clamp_height(self.content_height(height.to_px())),
+                Unit::Px,
+            )
         } else {
-            if container_width.is_specific_length() {
-                let specific_container_width = container_width.to_px();
-
-                let underflow_content = specific_container_width - {
-                    let mut margin_left = style.lookup("margin-left", "margin", &zero);
-                    let mut margin_right = style.lookup("margin-right", "margin", &zero);
-
-                    let border_left = style.lookup("border-left-width", "border-width", &zero);
-                    let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-                    let padding_left = style.lookup("padding-left", "padding", &zero);
-                    let padding_right = style.lookup("padding-right", "padding", &zero);
-
-                    sum([
-                        &margin_left,
-                        &margin_right,
-                        &border_left,
-                        &border_right,
-                        &padding_left,
-                        &padding_right,
-                        &width,
-                    ]
-                    .iter()
-                    .map(|v| v.to_px()))
-                };
-                self.dimensions.inner.width = underflow_content;
-                self_as_container_width = Value::Length(underflow_content, Unit::Px);
-                self_as_context_constraints_width = self_as_container_width.clone();
-            } else {
-                is_self_no_filled_auto = true;
-                let underflow_constraint = context_constraints_width.to_px() - {
-                    let mut margin_left = style.lookup("margin-left", "margin", &zero);
-                    let mut margin_right = style.lookup("margin-right", "margin", &zero);
-
-                    let border_left = style.lookup("border-left-width", "border-width", &zero);
-                    let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-                    let padding_left = style.lookup("padding-left", "padding", &zero);
-                    let padding_right = style.lookup("padding-right", "padding", &zero);
-
-                    sum([
-                        &margin_left,
-                        &margin_right,
-                        &border_left,
-                        &border_right,
-                        &padding_left,
-                        &padding_right,
-                        &width,
-                    ]
-                    .iter()
-                    .map(|v| v.to_px()))
-                };
-                self_as_context_constraints_width = Value::Length(underflow_constraint, Unit::Px);
-            }
+            Value::Keyword("auto".to_string())
         }
+    }
 
-        // inner edge size position
-        {
-            let style = self.get_style_node();
-            // margin, border, and padding have initial value 0.
-            let zero = Value::Length(0.0, Unit::Px);
-
-            let margin_left = style.lookup("margin-left", "margin", &zero);
-            let margin_right = style.lookup("margin-right", "margin", &zero);
-
-            let border_left = style.lookup("border-left-width", "border-width", &zero);
-            let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-            let padding_left = style.lookup("padding-left", "padding", &zero);
-            let padding_right = style.lookup("padding-right", "padding", &zero);
-
-            let d = &mut self.dimensions;
+    // The content width for a specified width. Unless `box-sizing` is `border-box`, they're the
+    // same.
+    fn content_width(&self, width: f32) -> f32 {
+        (width - self.box_sizing_edges(["left", "right"])).max(0.0)
+    }
 
-            d.padding.left = padding_left.to_px();
-            d.padding.right = padding_right.to_px();
+    // The content height for a specified height, like `content_width`.
+    fn content_height(&self, height: f32) -> f32 {
+        (height - self.box_sizing_edges(["top", "bottom"])).max(0.0)
+    }
 
-            d.border.left = border_left.to_px();
-            d.border.right = border_right.to_px();
+    // The `height` of the box, with a percentage resolved against the height of the containing
+    // block. It's `auto` if the containing block's height isn't definite.
+    fn specified_height(&self, container_height: &Value) -> Value {
+        match self.get_style_node().value("height") {
+            Some(Value::Percentage(percentage)) if container_height.is_specific_length() => {
+                Value::Length(container_height.to_px() * percentage / 100.0, Unit::Px)
+            }
+            Some(height) if height.is_specific_length() => height,
+            _ => Value::Keyword("auto".to_string()),
+        }
+    }
 
-            d.margin.left = margin_left.to_px();
-            d.margin.right = margin_right.to_px();
-            // If margin-top or margin-bottom is `auto`, the used value is zero.
-            d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-            d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+    // Clamp a content width between the box's `min-width` and `max-width`.
+    fn clamp_width(&self, width: f32) -> f32 {
+        let (min, max) = self.size_bounds("width");
+        width.min(max).max(min)
+    }
 
-            d.border.top = style
-                .lookup("border-top-width", "border-width", &zero)
-                .to_px();
-            d.border.bottom = style
-                .lookup("border-bottom-width", "border-width", &zero)
-                .to_px();
+    // Clamp a content height between the box's `min-height` and `max-height`.
+    fn clamp_height(&self, height: f32) -> f32 {
+        let (min, max) = self.size_bounds("height");
+        height.min(max).max(min)
+    }
 
-            d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-            d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
-            self.dimensions.inner.left = self.dimensions.margin.left
-                + self.dimensions.border.left
-                + self.dimensions.padding.left;
-            self.dimensions.inner.top = self.dimensions.margin.top
-                + self.dimensions.border.top
-                + self.dimensions.padding.top;
-        }
+    // The content sizes given by `min-<size>` and `max-<size>`, where `size` is `width` or
+    // `height`. A `min-width` larger than the `max-width` wins.
+    fn size_bounds(&self, size: &str) -> (f32, f32) {
+        let style = self.get_style_node();
+        let bound = |name: String| {
+            let value = style.value(&name).filter(Value::is_specific_length)?;
+            Some(match size {
+                "width" => self.content_width(value.to_px()),
+                _ => self.content_height(value.to_px()),
+            })
+        };
+        (
+            bound(format!("min-{}", size)).unwrap_or(0.0),
+            bound(format!("max-{}", size)).unwrap_or(f32::INFINITY),
+        )
+    }
 
-        // 2. recursive -> vertical(block, anonymous block)
-        let mut children_sum_height = 0f32;
-        let mut children_max_width = 0f32;
-        for child in &mut self.children {
-            child.layout(
-                self_as_container_width.clone(),
-                self_as_context_constraints_width.clone(),
-            );
-            child.dimensions.box_offset.top = children_sum_height;
-            children_sum_height += child.dimensions.margin_box().height;
-            children_max_width = children_max_width.max(child.dimensions.margin_box().width);
+    // The padding and border on two opposite sides that specified sizes include.
+    fn box_sizing_edges(&self, sides: [&str; 2]) -> f32 {
+        let style = self.get_style_node();
+        if !style.is_border_box() {
+            return 0.0;
         }
+        let zero = Value::Length(0.0, Unit::Px);
+        sum(sides.iter().flat_map(|side| {
+            [
+                style.lookup(&format!("padding-{}", side), "padding", &zero),
+                style.lookup(&format!("border-{}-width", side), "border-width", &zero),
+            ]
+            .map(|v| v.to_px())
+        }))
+    }
 
-        // 3. self main: height -> (specific, auto by children sum)
-        let mut height = style
-            .value("height")
-            .unwrap_or(Value::Keyword("auto".to_string()));
-        if height.is_specific_length() {
-            self.dimensions.inner.height = height.to_px();
-        } else if height.is_auto() {
-            self.dimensions.inner.height = children_sum_height;
+    // Resolve `auto` and over-constrained horizontal margins of a block box, so that its margin
+    // box is exactly as wide as the containing block.
+    // https://www.w3.org/TR/CSS2/visudet.html#blockwidth
+    fn resolve_horizontal_margins(&mut self, containing_width: f32) {
+        let (BoxType::BlockNode(style)
+        | BoxType::FlexNode(style)
+        | BoxType::GridNode(style)
+        | BoxType::TableNode(style)) = self.box_type
+        else {
+            return;
+        };
+        if self.is_out_of_flow() {
+            return;
         }
+        let zero = Value::Length(0.0, Unit::Px);
+        let margin_left = style.lookup("margin-left", "margin", &zero);
+        let margin_right = style.lookup("margin-right", "margin", &zero);
+
+        let d = &mut self.dimensions;
+        let border_box_width =
+            d.border.left + d.padding.left + d.inner.width + d.padding.right + d.border.right;
+        // `to_px` treats auto margins as zero.
+        let underflow =
+            containing_width - border_box_width - margin_left.to_px() - margin_right.to_px();
+
+        // An auto width already fills the line, unless `min-width` or `max-width` applied.
+        let (left, right) = match (margin_left.is_auto(), margin_right.is_auto()) {
+            // Too wide: auto margins stay zero and the box overflows to the right.
+            _ if underflow < 0.0 => (margin_left.to_px(), margin_right.to_px() + underflow),
+            (true, true) => (underflow / 2.0, underflow / 2.0),
+            (true, false) => (underflow, margin_right.to_px()),
+            (false, true) => (margin_left.to_px(), underflow),
+            // Over-constrained: the right margin gives way.
+            (false, false) => (margin_left.to_px(), margin_right.to_px() + underflow),
+        };
+        d.margin.left = left;
+        d.margin.right = right;
+        d.inner.left = d.margin.left + d.border.left + d.padding.left;
+    }
 
-        // 4. self cross: width -> (specific, max by children)
-        if is_self_no_filled_auto {
-            self.dimensions.inner.width = children_max_width;
-            self.dimensions.inner.width = children_max_width;
+    // Collapse the margins of a laid out box with the children's margins that adjoin them.
+    //
+    // This is up to the parent block: the root and boxes that establish a new block formatting
+    // context, like inline blocks, keep their children's margins inside.
+    fn collapse_margins(&mut self, container_height: &Value) -> CollapsedMargins {
+        let d = self.dimensions;
+        let mut top = MarginStrut::new(d.margin.top);
+        let mut bottom = MarginStrut::new(d.margin.bottom);
+        match self.box_type {
+            BoxType::BlockNode(_) => {}
+            // An anonymous block without line boxes doesn't separate margins.
+            BoxType::AnonymousBlock(_) => {
+                return CollapsedMargins {
+                    top,
+                    bottom,
+                    through: d.inner.height == 0.0,
+                };
+            }
+            _ => {
+                return CollapsedMargins {
+                    top,
+                    bottom,
+                    through: false,
+                };
+            }
         }
-
-        // 5. fill children: width -> block (empty auto -> fill one line)
-        if is_self_no_filled_auto {
-            let underflow_content = self.dimensions.inner.width - {
-                let margin_left = style.lookup("margin-left", "margin", &zero);
-                let margin_right = style.lookup("margin-right", "margin", &zero);
-
-                let border_left = style.lookup("border-left-width", "border-width", &zero);
-                let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-                let padding_left = style.lookup("padding-left", "padding", &zero);
-                let padding_right = style.lookup("padding-right", "padding", &zero);
-
-                sum([
-                    &margin_left,
-                    &margin_right,
-                    &border_left,
-                    &border_right,
-                    &padding_left,
-                    &padding_right,
-                    &width,
-                ]
-                .iter()
-                .map(|v| v.to_px()))
-            };
-            self_as_container_width = Value::Length(underflow_content, Unit::Px);
-            self_as_context_constraints_width = self_as_container_width.clone();
+        let height = self.specified_height(container_height);
+        let zero_height =
+            height.is_auto() || (height.is_specific_length() && height.to_px() == 0.0);
+        let (min_height, _) = self.size_bounds("height");
+        let open_top = d.border.top == 0.0 && d.padding.top == 0.0;
+        let open_bottom = d.border.bottom == 0.0 && d.padding.bottom == 0.0;
+        let children = self.child_margins;
+
+        let through = children.empty && open_top && open_bottom && zero_height && min_height == 0.0;
+        let mut children_height = children.height;
+        if open_top {
+            let shift = children.leading.resolve();
             for child in &mut self.children {
-                if !matches!(child.box_type, BoxType::AnonymousBlock(_)) && child.is_width_auto() {
-                    // auto and not anonymous -> retake one line
-                    child.layout(
-                        self_as_container_width.clone(),
-                        self_as_context_constraints_width.clone(),
-                    );
-                }
+                child.dimensions.box_offset.top -= shift;
             }
+            children_height -= shift;
+            top.adjoin(children.leading);
         }
-
-        // 6. fixing children #[cfg(not(margin-auto))]
-        for child in &mut self.children {
-            // edge size auto by position block
-            // self.dimensions.box_offset.left; // no margin so to do nothing
+        if through {
+            // Nothing separates the top and bottom margins, so they collapse through the box.
+            top.adjoin(bottom);
+            bottom = MarginStrut::default();
+        } else if open_bottom
+            && height.is_auto()
+            && min_height == 0.0
+            && !(children.empty && open_top)
+        {
+            children_height -= children.trailing.resolve();
+            bottom.adjoin(children.trailing);
+        }
+        if height.is_auto() {
+            self.dimensions.inner.height = self.clamp_height(children_height);
+        }
+        CollapsedMargins {
+            top,
+            bottom,
+            through,
         }
     }
 
@@ -795,21 +1507,55 @@ impl<'a> LayoutBox<'a> {
         }
     }
 
-    // Where a new inline child should go.
+    // The box at `path` among the descendants.
+    pub(crate) fn descendant(&self, path: &[usize]) -> &LayoutBox<'a> {
+        path.iter().fold(self, |node, &i| &node.children[i])
+    }
+
+    pub(crate) fn descendant_mut(&mut self, path: &[usize]) -> &mut LayoutBox<'a> {
+        path.iter().fold(self, |node, &i| &mut node.children[i])
+    }
+
+    // Where a new inline child should go. Inline boxes hold their inline children themselves, to
+    // put them on the lines of the anonymous block they're in.
     fn get_inline_container(&mut self) -> &mut LayoutBox<'a> {
         match self.box_type {
-            BoxType::InlineNode(_) | BoxType::AnonymousBlock(_) => self,
-            BoxType::BlockNode(_) | BoxType::InlineBlockNode(_) => {
+            BoxType::AnonymousBlock(_) | BoxType::InlineNode(_) => self,
+            BoxType::TextNode(_) | BoxType::MarkerNode(..) | BoxType::ContentNode(..) => {
+                unreachable!("Boxes of text have no children")
+            }
+            BoxType::BlockNode(_)
+            | BoxType::InlineBlockNode(_)
+            | BoxType::FlexNode(_)
+            | BoxType::InlineFlexNode(_)
+            | BoxType::GridNode(_)
+            | BoxType::InlineGridNode(_)
+            | BoxType::TableNode(_)
+            | BoxType::InlineTableNode(_)
+            | BoxType::TableRowGroupNode(_)
+            | BoxType::TableRowNode(_)
+            | BoxType::TableCellNode(_)
+            | BoxType::TableCaptionNode(_)
+            | BoxType::TableColumnNode(_) => {
                 // If we've just generated an anonymous block box, keep using it.
                 // Otherwise, create a new one.
-                match self.children.last().clone() {
+                match self.children.last() {
                     Some(LayoutBox {
-                        box_type: AnonymousBlock,
+                        box_type: BoxType::AnonymousBlock(_),
                         ..
                     }) => {}
-                    _ => self
-                        .children
-                        .push(LayoutBox::new(BoxType::AnonymousBlock(Default::default()))),
+                    _ => {
+                        let ifc = InlineFormattingContext {
+                            style: Some(self.get_style_node()),
+                            first_line: self
+                                .children
+                                .iter()
+                                .all(|child| child.is_float() || child.is_out_of_flow()),
+                            ..Default::default()
+                        };
+                        self.children
+                            .push(LayoutBox::new(BoxType::AnonymousBlock(ifc)))
+                    }
                 }
                 self.children.last_mut().unwrap()
             }
@@ -818,6 +1564,167 @@ impl<'a> LayoutBox<'a> {
     // ...
 }
 
+// Items of flex and grid containers.
+impl<'a> LayoutBox<'a> {
+    // The indices of the items in the order their `order` properties give, then in document
+    // order. Absolutely positioned children aren't items.
+    fn children_in_order(&self) -> Vec<usize> {
+        let mut order: Vec<(i32, usize)> = Vec::new();
+        for (index, child) in self.children.iter().enumerate() {
+            if child.is_out_of_flow() {
+                continue;
+            }
+            let value = child.item_style().and_then(|style| style.value("order"));
+            order.push((
+                match value {
+                    Some(Value::Number(order)) => order as i32,
+                    _ => 0,
+                },
+                index,
+            ));
+        }
+        order.sort_by_key(|&(order, _)| order);
+        order.into_iter().map(|(_, index)| index).collect()
+    }
+
+    // Lay out an item at the sizes imposed on it.
+    fn layout_item(
+        &mut self,
+        container_width: &Value,
+        container_height: &Value,
+        context_constraints_width: &Value,
+        fonts: &'a FontRegistry,
+    ) {
+        let context_constraints_width = match self.imposed_size.width {
+            Some(width) => Value::Length(width, Unit::Px),
+            None => context_constraints_width.clone(),
+        };
+        self.layout_box(
+            container_width.clone(),
+            container_height.clone(),
+            context_constraints_width,
+            fonts,
+        );
+        // Anonymous items have no style to take sizes from, so they're set here.
+        if let BoxType::AnonymousBlock(_) = self.box_type {
+            let ImposedSize { width, height } = self.imposed_size;
+            self.dimensions.inner.width = width.unwrap_or(self.dimensions.inner.width);
+            self.dimensions.inner.height = height.unwrap_or(self.dimensions.inner.height);
+        }
+    }
+
+    // The style of an item, unless it's an anonymous item wrapping text.
+    fn item_style(&self) -> Option<&'a StyledNode<'a>> {
+        match self.box_type {
+            BoxType::AnonymousBlock(_) => None,
+            _ => Some(self.get_style_node()),
+        }
+    }
+
+    // Clamp a content size along an axis by the item's min and max sizes.
+    fn clamp_axis(&self, size: f32, horizontal: bool) -> f32 {
+        match self.box_type {
+            BoxType::AnonymousBlock(_) => size.max(0.0),
+            _ if horizontal => self.clamp_width(size).max(0.0),
+            _ => self.clamp_height(size).max(0.0),
+        }
+    }
+
+    fn axis_content_size(&self, horizontal: bool) -> f32 {
+        match horizontal {
+            true => self.dimensions.inner.width,
+            false => self.dimensions.inner.height,
+        }
+    }
+
+    fn axis_margin_box_size(&self, horizontal: bool) -> f32 {
+        self.axis_content_size(horizontal) + self.axis_edges(horizontal)
+    }
+
+    // The margins, borders and padding on both sides of an axis.
+    fn axis_edges(&self, horizontal: bool) -> f32 {
+        let d = &self.dimensions;
+        match horizontal {
+            true => {
+                d.margin.left
+                    + d.border.left
+                    + d.padding.left
+                    + d.padding.right
+                    + d.border.right
+                    + d.margin.right
+            }
+            false => {
+                d.margin.top
+                    + d.border.top
+                    + d.padding.top
+                    + d.padding.bottom
+                    + d.border.bottom
+                    + d.margin.bottom
+            }
+        }
+    }
+
+    // The `auto` margins at the start and end of an axis.
+    fn auto_margins(&self, horizontal: bool) -> (bool, bool) {
+        let Some(style) = self.item_style() else {
+            return (false, false);
+        };
+        let zero = Value::Length(0.0, Unit::Px);
+        let sides = match horizontal {
+            true => ["margin-left", "margin-right"],
+            false => ["margin-top", "margin-bottom"],
+        };
+        let [start, end] = sides.map(|side| style.lookup(side, "margin", &zero).is_auto());
+        (start, end)
+    }
+
+    fn auto_margin_count(&self, horizontal: bool) -> usize {
+        let (start, end) = self.auto_margins(horizontal);
+        start as usize + end as usize
+    }
+
+    // Give `space` to each `auto` margin along an axis.
+    fn fill_auto_margins(&mut self, horizontal: bool, space: f32) {
+        let (start, end) = self.auto_margins(horizontal);
+        let d = &mut self.dimensions;
+        let (margin_start, margin_end) = match horizontal {
+            true => (&mut d.margin.left, &mut d.margin.right),
+            false => (&mut d.margin.top, &mut d.margin.bottom),
+        };
+        if start {
+            *margin_start += space;
+        }
+        if end {
+            *margin_end += space;
+        }
+        d.inner.left = d.margin.left + d.border.left + d.padding.left;
+        d.inner.top = d.margin.top + d.border.top + d.padding.top;
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    // Width of a collapsible space in the first font of a text box.
+    fn space_width(&self, fonts: &'a FontRegistry) -> f32 {
+        let style = self.get_style_node();
+        fonts
+            .find(
+                &style.font_families(),
+                style.font_weight(),
+                style.font_style(),
+                ' ',
+            )
+            .map_or(0.0, |font| font.measure(" ", style.font_size()))
+    }
+}
+
+impl<'a> TextRun<'a> {
+    // Width of a collapsible space next to this run.
+    fn space_width(&self) -> f32 {
+        self.font
+            .map_or(0.0, |font| font.measure(" ", self.font_size))
+    }
+}
+
 fn sum<I>(iter: I) -> f32
 where
     I: Iterator<Item = f32>,
@@ -825,11 +1732,89 @@ where
     iter.fold(0., |a, b| a + b)
 }
 
+// How far an inline box of `style` in `font` reaches above and below its baseline: the ascent and
+// descent of the font, with half the leading that `line-height` adds on each side.
+// https://www.w3.org/TR/CSS22/visudet.html#leading
+fn inline_box_metrics(style: &StyledNode, font: Option<&FontFace>) -> (f32, f32) {
+    let metrics = font.map_or(FontMetrics::default(), |font| {
+        font.metrics(style.font_size())
+    });
+    let half_leading = (style.line_height(metrics) - metrics.ascent - metrics.descent) / 2.0;
+    (
+        metrics.ascent + half_leading,
+        metrics.descent + half_leading,
+    )
+}
+
 mod tests {
     use super::super::css;
     use super::super::html;
     use super::*;
 
+    // Lay out a styled tree in a 400px square viewport, and find where its boxes are.
+    #[cfg(test)]
+    pub(super) fn layout<'a>(
+        styled_tree: &'a StyledNode<'a>,
+        fonts: &'a FontRegistry,
+    ) -> LayoutBox<'a> {
+        let mut layout_tree = build_layout_tree(styled_tree);
+        layout_tree.layout(
+            Value::Length(400.0, Unit::Px),
+            Value::Length(400.0, Unit::Px),
+            Value::Length(400.0, Unit::Px),
+            fonts,
+        );
+        layout_tree.calc_abs();
+        layout_tree
+    }
+
+    // The position of the content box of a laid out box in the page, and its size.
+    #[cfg(test)]
+    pub(super) fn rect(layout_box: &LayoutBox) -> (f32, f32, f32, f32) {
+        let d = &layout_box.dimensions;
+        (d.box_abs.x, d.box_abs.y, d.inner.width, d.inner.height)
+    }
+
+    // Lay out `html` with the rules of `css` in a viewport `width` px square, like `layout`, with
+    // Noto Serif loaded as the font family `Noto`. What the tree refers to is leaked, to last as
+    // long as the test.
+    #[cfg(test)]
+    pub(super) fn layout_with_fonts(html: &str, css: &str, width: f32) -> LayoutBox<'static> {
+        use super::super::loader::FileSystemLoader;
+
+        let root = Box::leak(Box::new(html::parse(html.to_owned())));
+        let css = Box::leak(Box::new(css::parse(format!(
+            "@font-face {{ font-family: Noto; src: url(NotoSerif-Regular.ttf); }}\n{css}"
+        ))));
+        let fonts = Box::leak(Box::new(FontRegistry::new()));
+        fonts.load_font_faces(css, &FileSystemLoader::new("testfiles"));
+        let styled_tree = Box::leak(Box::new(style_tree(root, css)));
+        let mut layout_tree = build_layout_tree(styled_tree);
+        layout_tree.layout(
+            Value::Length(width, Unit::Px),
+            Value::Length(width, Unit::Px),
+            Value::Length(width, Unit::Px),
+            fonts,
+        );
+        layout_tree.calc_abs();
+        layout_tree
+    }
+
+    // The text runs on the lines of an anonymous block.
+    #[cfg(test)]
+    pub(super) fn text_runs<'a, 'b>(line: &'b LayoutBox<'a>) -> Vec<&'b TextRun<'a>> {
+        let BoxType::AnonymousBlock(ref ifc) = line.box_type else {
+            panic!("text should be wrapped in an anonymous block");
+        };
+        ifc.elements
+            .iter()
+            .filter_map(|element| match element {
+                InlineFormattingContextRun::TextRun(run) => Some(run),
+                _ => None,
+            })
+            .collect()
+    }
+
     #[test]
     fn test_layout() {
         let root = html::parse("<div class=\"note\"><div class=\"note\"></div></div>".to_string());
@@ -838,6 +1823,8 @@ mod tests {
 
         let styled_tree = style_tree(&root, &css);
 
+        let fonts = FontRegistry::new();
+
         let mut layout_tree = build_layout_tree(&styled_tree);
 
         let mut dimension = Dimensions::default();
@@ -845,6 +1832,8 @@ mod tests {
         layout_tree.layout(
             Value::Length(200.0, Unit::Px),
             Value::Length(200.0, Unit::Px),
+            Value::Length(200.0, Unit::Px),
+            &fonts,
         );
 
         println!("{:#?}", layout_tree);
@@ -863,4 +1852,450 @@ mod tests {
         assert_eq!(layout_tree.children[0].dimensions.margin_box().height, 60.0);
         assert_eq!(layout_tree.dimensions.margin_box().height, 120.0);
     }
+
+    #[test]
+    fn test_margin_collapsing() {
+        let root = html::parse(
+            "<div class=\"page\"><div class=\"a\"></div><div class=\"b\"><div class=\"c\"></div></div><div class=\"empty\"></div><div class=\"d\"></div></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div { display: block; }
+.page { margin: 5px; }
+.a { height: 10px; margin-top: 6px; margin-bottom: 20px; }
+.b { margin-top: 10px; }
+.c { height: 10px; margin-top: 30px; margin-bottom: -5px; }
+.empty { margin-top: 8px; margin-bottom: 12px; }
+.d { height: 10px; margin-top: 4px; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let mut layout_tree = build_layout_tree(&styled_tree);
+        layout_tree.layout(
+            Value::Length(200.0, Unit::Px),
+            Value::Length(200.0, Unit::Px),
+            Value::Length(200.0, Unit::Px),
+            &fonts,
+        );
+        layout_tree.calc_abs();
+
+        let border_top = |b: &LayoutBox| b.dimensions.border_box().y;
+        let [a, b, empty, d] = &layout_tree.children[..] else {
+            panic!("expected four children");
+        };
+        let c = &b.children[0];
+
+        // The root doesn't collapse with its first child.
+        assert_eq!(border_top(a), 5.0 + 6.0);
+        // Sibling margins collapse, and so does the first child's margin with its parent's.
+        assert_eq!(border_top(b), border_top(a) + 10.0 + 30.0);
+        assert_eq!(border_top(c), border_top(b));
+        // The last child's negative margin collapses through its parent and the empty block.
+        assert_eq!(b.dimensions.inner.height, 10.0);
+        assert_eq!(border_top(d), border_top(b) + 10.0 + 12.0 - 5.0);
+        assert_eq!(empty.dimensions.inner.height, 0.0);
+        assert_eq!(
+            layout_tree.dimensions.inner.height,
+            border_top(d) + 10.0 - 5.0
+        );
+    }
+
+    #[test]
+    fn test_horizontal_margins() {
+        let root = html::parse(
+            "<div><p class=\"center\"></p><p class=\"right\"></p><p class=\"over\"></p><p class=\"wide\"></p><p class=\"negative\"></p></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div, p { display: block; }
+.center { width: 100px; margin: 0 auto; }
+.right { width: 50px; margin-left: auto; }
+.over { width: 100px; margin-left: 30px; margin-right: 30px; }
+.wide { width: 300px; margin: 0 auto; }
+.negative { margin-left: -10px; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let mut layout_tree = build_layout_tree(&styled_tree);
+        layout_tree.layout(
+            Value::Length(200.0, Unit::Px),
+            Value::Length(200.0, Unit::Px),
+            Value::Length(200.0, Unit::Px),
+            &fonts,
+        );
+        layout_tree.calc_abs();
+
+        let [center, right, over, wide, negative] = &layout_tree.children[..] else {
+            panic!("expected five children");
+        };
+        assert_eq!(center.dimensions.margin.left, 50.0);
+        assert_eq!(center.dimensions.margin.right, 50.0);
+        assert_eq!(right.dimensions.border_box().x, 150.0);
+        // Over-constrained boxes adjust their right margin to fill the containing block.
+        for b in [center, right, over, wide] {
+            assert_eq!(b.dimensions.margin_box().width, 200.0);
+        }
+        assert_eq!(over.dimensions.margin.left, 30.0);
+        // Auto margins of a box wider than its containing block are zero.
+        assert_eq!(wide.dimensions.margin.left, 0.0);
+        assert_eq!(negative.dimensions.border_box().x, -10.0);
+        assert_eq!(negative.dimensions.border_box().width, 210.0);
+    }
+
+    #[test]
+    fn test_box_sizing() {
+        let root = html::parse(
+            "<div><p class=\"content\"></p><p class=\"border\"></p><p class=\"small\"></p></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div, p { display: block; }
+p { width: 100px; height: 50px; padding: 10px; border-width: 2px; margin: 5px; }
+.border { box-sizing: border-box; }
+.small { box-sizing: border-box; width: 10px; height: 10px; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let mut layout_tree = build_layout_tree(&styled_tree);
+        layout_tree.layout(
+            Value::Length(200.0, Unit::Px),
+            Value::Length(200.0, Unit::Px),
+            Value::Length(200.0, Unit::Px),
+            &fonts,
+        );
+
+        let [content, border, small] = &layout_tree.children[..] else {
+            panic!("expected three children");
+        };
+        assert_eq!(content.dimensions.inner.width, 100.0);
+        assert_eq!(content.dimensions.inner.height, 50.0);
+        assert_eq!(content.dimensions.border_box().width, 124.0);
+
+        assert_eq!(border.dimensions.border_box().width, 100.0);
+        assert_eq!(border.dimensions.border_box().height, 50.0);
+        assert_eq!(border.dimensions.inner.width, 76.0);
+
+        // The content box can't be smaller than nothing.
+        assert_eq!(small.dimensions.inner.width, 0.0);
+        assert_eq!(small.dimensions.border_box().height, 24.0);
+    }
+
+    #[test]
+    fn test_min_max_sizes() {
+        let root = html::parse(
+            "<div><p class=\"max\"></p><p class=\"min\"></p><p class=\"border\"></p><p class=\"tall\"></p><p class=\"short\"></p><span class=\"fit\"><p class=\"wide\"></p><p class=\"fill\"></p></span></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div, p { display: block; }
+.max { max-width: 100px; margin: 0 auto; }
+.min { width: 50px; min-width: 80px; }
+.border { box-sizing: border-box; max-width: 100px; padding: 10px; }
+.tall { min-height: 30px; }
+.short { height: 100px; max-height: 40px; }
+.fit { display: inline-block; max-width: 60px; padding: 5px; }
+.wide { width: 100px; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let mut layout_tree = build_layout_tree(&styled_tree);
+        layout_tree.layout(
+            Value::Length(200.0, Unit::Px),
+            Value::Length(200.0, Unit::Px),
+            Value::Length(200.0, Unit::Px),
+            &fonts,
+        );
+
+        let [max, min, border, tall, short, anonymous] = &layout_tree.children[..] else {
+            panic!("expected six children");
+        };
+        // A box narrowed by max-width can be centered with auto margins.
+        assert_eq!(max.dimensions.inner.width, 100.0);
+        assert_eq!(max.dimensions.margin.left, 50.0);
+        assert_eq!(min.dimensions.inner.width, 80.0);
+        assert_eq!(border.dimensions.border_box().width, 100.0);
+        assert_eq!(tall.dimensions.inner.height, 30.0);
+        assert_eq!(short.dimensions.inner.height, 40.0);
+
+        // The shrink-to-fit width of an inline block is limited too, and its auto-width children
+        // fill it.
+        let fit = &anonymous.children[0];
+        assert_eq!(fit.dimensions.inner.width, 60.0);
+        assert_eq!(fit.children[1].dimensions.inner.width, 60.0);
+    }
+
+    #[test]
+    fn test_percentage_heights() {
+        let root = html::parse(
+            "<div><p class=\"definite\"><p class=\"half\"></p></p><p class=\"auto\"><p class=\"half\"></p></p></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div, p { display: block; }
+div { height: 50%; }
+.definite { height: 100px; padding: 10px; }
+.half { height: 50%; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let mut layout_tree = build_layout_tree(&styled_tree);
+        layout_tree.layout(
+            Value::Length(200.0, Unit::Px),
+            Value::Length(300.0, Unit::Px),
+            Value::Length(200.0, Unit::Px),
+            &fonts,
+        );
+
+        // The root resolves against the viewport height.
+        assert_eq!(layout_tree.dimensions.inner.height, 150.0);
+        let [definite, auto] = &layout_tree.children[..] else {
+            panic!("expected two children");
+        };
+        // Percentages resolve against the content height of the containing block...
+        assert_eq!(definite.children[0].dimensions.inner.height, 50.0);
+        // ...and behave as auto when that height depends on the content.
+        assert_eq!(auto.children[0].dimensions.inner.height, 0.0);
+        assert_eq!(auto.dimensions.inner.height, 0.0);
+    }
+
+    #[test]
+    fn test_text_layout() {
+        let layout_tree = layout_with_fonts(
+            "<div class=\"para\">Lorem ipsum dolor sit amet, consectetur adipiscing elit.</div>",
+            ".para { display: block; font-family: Noto; font-size: 20px; }",
+            200.0,
+        );
+
+        let anonymous = &layout_tree.children[0];
+        let runs = text_runs(anonymous);
+        assert_eq!(runs.len(), 8);
+        assert_eq!(runs[0].text, "Lorem");
+        assert!(runs.iter().all(|run| run.width > 0.0 && run.font.is_some()));
+
+        // Words are separated by a space and wrap to new lines within 200px.
+        assert!(runs[1].left > runs[0].left + runs[0].width);
+        assert!(runs.iter().all(|run| run.left + run.width <= 200.0));
+        let line_height = runs[0].height;
+        let lines = runs.iter().filter(|run| run.left == 0.0).count();
+        assert!(lines > 1);
+        assert_eq!(
+            anonymous.dimensions.inner.height,
+            line_height * lines as f32
+        );
+        assert_eq!(
+            layout_tree.dimensions.inner.height,
+            line_height * lines as f32
+        );
+    }
+
+    #[test]
+    fn test_generated_content() {
+        let layout_tree = layout_with_fonts(
+            "<div class=\"doc\"><p class=\"figure\">Replaced</p><p class=\"figure\"></p></div>",
+            ".doc { display: block; font-family: Noto; font-size: 20px; }
+.figure { display: block; counter-increment: figure; content: \"Ignored\"; }
+.figure::before { content: \"Figure \" counter(figure) \": \"; }
+.figure::after { content: \".\"; }",
+            200.0,
+        );
+
+        // The content goes before and after the children, and is laid out like text. The element's
+        // own `content` doesn't replace them.
+        let words: Vec<Vec<String>> = layout_tree
+            .children
+            .iter()
+            .map(|figure| {
+                text_runs(&figure.children[0])
+                    .iter()
+                    .map(|run| run.text.clone())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            words,
+            [
+                vec!["Figure", "1:", "Replaced", "."],
+                vec!["Figure", "2:", "."]
+            ]
+        );
+        assert!(layout_tree.children[1].dimensions.inner.height > 0.0);
+    }
+
+    #[test]
+    fn test_text_align() {
+        let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit.";
+        let layout_tree = layout_with_fonts(
+            &format!(
+                "<div class=\"doc\"><p class=\"right\">{text}</p><p class=\"center\">{text}</p><p class=\"justify\">{text}</p><p>{text}</p></div>"
+            ),
+            ".doc { display: block; font-family: Noto; font-size: 20px; text-indent: 10%; }
+p { display: block; }
+.right { text-align: right; text-indent: 0; }
+.center { text-align: center; text-indent: 0; }
+.justify { text-align: justify; text-indent: 0; }",
+            200.0,
+        );
+
+        // The left and right of each line of a paragraph.
+        let lines = |paragraph: &LayoutBox| {
+            let mut lines: Vec<(f32, f32, f32)> = vec![];
+            for run in text_runs(&paragraph.children[0]) {
+                match lines.last_mut() {
+                    Some(line) if line.0 == run.top => line.2 = run.left + run.width,
+                    _ => lines.push((run.top, run.left, run.left + run.width)),
+                }
+            }
+            lines
+                .into_iter()
+                .map(|(_, left, right)| (left, right))
+                .collect::<Vec<_>>()
+        };
+        let close = |a: f32, b: f32| (a - b).abs() < 0.01;
+
+        let right = lines(&layout_tree.children[0]);
+        assert!(right.len() > 1);
+        assert!(right.iter().all(|&(_, end)| close(end, 200.0)));
+
+        let center = lines(&layout_tree.children[1]);
+        assert!(center.iter().all(|&(start, end)| close(start, 200.0 - end)));
+
+        // Every line but the last fills the width.
+        let justify = lines(&layout_tree.children[2]);
+        let (last, rest) = justify.split_last().unwrap();
+        assert!(
+            rest.iter()
+                .all(|&(start, end)| start == 0.0 && close(end, 200.0))
+        );
+        assert!(last.0 == 0.0 && last.1 < 200.0);
+
+        // Only the first line is indented, by 10% of the width.
+        let start = lines(&layout_tree.children[3]);
+        assert_eq!(start[0].0, 20.0);
+        assert!(start[1..].iter().all(|&(start, _)| start == 0.0));
+    }
+
+    #[test]
+    fn test_font_fallback() {
+        use super::super::loader::FileSystemLoader;
+
+        let css = css::parse(
+            "@font-face { font-family: Noto; src: url(NotoSerif-Regular.ttf); }
+@font-face { font-family: Latin; src: url(NotoSerif-Bold.ttf); unicode-range: U+0-7F; }
+p { display: block; font-family: Latin; font-size: 20px; }"
+                .to_owned(),
+        );
+        let mut fonts = FontRegistry::new();
+        fonts.load_font_faces(&css, &FileSystemLoader::new("testfiles"));
+        fonts.set_fallback_families(vec!["Noto".to_string()]);
+
+        // The text, family, left, width and top of each run in a paragraph `width` wide.
+        let runs = |html: &str, width: f32| {
+            let root = html::parse(html.to_owned());
+            let styled_tree = style_tree(&root, &css);
+            let mut layout_tree = build_layout_tree(&styled_tree);
+            layout_tree.layout(
+                Value::Length(width, Unit::Px),
+                Value::Length(200.0, Unit::Px),
+                Value::Length(width, Unit::Px),
+                &fonts,
+            );
+            text_runs(&layout_tree.children[0])
+                .iter()
+                .map(|run| {
+                    (
+                        run.text.clone(),
+                        run.font.unwrap().family.clone(),
+                        run.left,
+                        run.width,
+                        run.top,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // A word is split into runs where its font changes, with no space between them.
+        let words = runs("<p>café au lait</p>", 200.0);
+        let families: Vec<(&str, &str)> = words
+            .iter()
+            .map(|(text, family, ..)| (text.as_str(), family.as_str()))
+            .collect();
+        assert_eq!(
+            families,
+            vec![
+                ("caf", "Latin"),
+                ("é", "Noto"),
+                ("au", "Latin"),
+                ("lait", "Latin"),
+            ]
+        );
+        assert_eq!(words[1].2, words[0].2 + words[0].3);
+        assert!(words[2].2 > words[1].2 + words[1].3);
+
+        // The runs of a word wrap together, onto the next line.
+        for width in [65.0, 70.0] {
+            let words = runs("<p>xx café</p>", width);
+            let (xx, caf, e) = (&words[0], &words[1], &words[2]);
+            assert!(caf.4 > xx.4);
+            assert_eq!((caf.2, caf.4), (0.0, e.4));
+            assert_eq!(e.2, caf.2 + caf.3);
+        }
+    }
+
+    #[test]
+    fn test_line_height() {
+        let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit.";
+        let layout_tree = layout_with_fonts(
+            &format!(
+                "<div class=\"doc\"><p class=\"length\">{text}</p><p class=\"number\">{text}</p><p class=\"big\">{text}</p><p>Lorem <span class=\"image\"></span> ipsum</p></div>"
+            ),
+            ".doc { display: block; font-family: Noto; font-size: 20px; line-height: 150%; }
+p { display: block; }
+.length { line-height: 40px; }
+.number { line-height: 2; font-size: 10px; }
+.big { font-size: 40px; }
+.image { display: inline-block; width: 10px; height: 50px; }",
+            200.0,
+        );
+
+        // The top, height and ascent of each text run of a paragraph.
+        let runs = |paragraph: &LayoutBox| {
+            text_runs(&paragraph.children[0])
+                .iter()
+                .map(|run| (run.top, run.height, run.ascent))
+                .collect::<Vec<_>>()
+        };
+        // Each line is as high as the line height, with the text in the middle of it.
+        let line_count = |paragraph: &LayoutBox| {
+            let mut tops: Vec<f32> = runs(paragraph).iter().map(|run| run.0).collect();
+            tops.dedup();
+            tops.len() as f32
+        };
+        let paragraphs = &layout_tree.children;
+        let length = &paragraphs[0];
+        assert!(line_count(length) > 1.0);
+        assert_eq!(length.dimensions.inner.height, 40.0 * line_count(length));
+        let (_, height, ascent) = runs(length)[0];
+        assert_eq!(height, 40.0);
+        assert!(ascent > 20.0);
+
+        // A number is of the font size of each element.
+        let number = &paragraphs[1];
+        assert_eq!(number.dimensions.inner.height, 20.0 * line_count(number));
+        // A percentage is of the font size of the element it's set on.
+        let big = &paragraphs[2];
+        assert_eq!(big.dimensions.inner.height, 30.0 * line_count(big));
+
+        // An inline-block without lines sits on the baseline, and makes its line taller.
+        let line = &paragraphs[3].children[0];
+        let image = &line.children[1];
+        let (top, height, ascent) = runs(&paragraphs[3])[0];
+        assert_eq!(image.dimensions.box_offset.top, 0.0);
+        assert_eq!(top + ascent, 50.0);
+        assert!((line.dimensions.inner.height - (50.0 + height - ascent)).abs() < 0.01);
+    }
 }
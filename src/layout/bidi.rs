@@ -0,0 +1,197 @@
+//! Bidirectional text: the Unicode Bidirectional Algorithm (UAX #9) on the lines of an anonymous
+//! block, with the `direction` and `unicode-bidi` properties of CSS Writing Modes Level 3 § 2.
+//!
+//! https://www.w3.org/TR/css-writing-modes-3/#text-direction
+
+use super::inline::InlineItem;
+use super::*;
+use unicode_bidi::{BidiInfo, Level};
+
+// The bidi controls an inline box puts around its content, as the bidi algorithm sees them.
+// https://www.w3.org/TR/css-writing-modes-3/#bidi-control-codes-injection-table
+fn bidi_controls(style: &StyledNode) -> (&'static str, &'static str) {
+    let rtl = keyword(style, "direction", "ltr") == "rtl";
+    match (keyword(style, "unicode-bidi", "normal").as_str(), rtl) {
+        ("embed", false) => ("\u{202A}", "\u{202C}"),
+        ("embed", true) => ("\u{202B}", "\u{202C}"),
+        ("isolate", false) => ("\u{2066}", "\u{2069}"),
+        ("isolate", true) => ("\u{2067}", "\u{2069}"),
+        ("bidi-override", false) => ("\u{202D}", "\u{202C}"),
+        ("bidi-override", true) => ("\u{202E}", "\u{202C}"),
+        ("isolate-override", false) => ("\u{2066}\u{202D}", "\u{202C}\u{2069}"),
+        ("isolate-override", true) => ("\u{2067}\u{202E}", "\u{202C}\u{2069}"),
+        ("plaintext", _) => ("\u{2068}", "\u{2069}"),
+        _ => ("", ""),
+    }
+}
+
+// Resolve the embedding levels of the items on the lines of a block with `style`, in a paragraph
+// of its `direction`, or that of its first strong character with `unicode-bidi: plaintext`.
+// Words that change direction are split where they do, and right-to-left words are shaped
+// again in their direction, which puts their glyphs in the order they're painted in and mirrors
+// those that need it. Returns the items with their levels, and whether the paragraph is
+// right-to-left.
+pub(super) fn resolve_levels<'a>(
+    style: Option<&StyledNode>,
+    items: Vec<InlineItem<'a>>,
+) -> (Vec<(InlineItem<'a>, Level)>, bool) {
+    let base = match style {
+        Some(style) if keyword(style, "unicode-bidi", "normal") == "plaintext" => None,
+        Some(style) if keyword(style, "direction", "ltr") == "rtl" => Some(Level::rtl()),
+        _ => Some(Level::ltr()),
+    };
+
+    // The text of the paragraph, with a space for each collapsible space and an object
+    // replacement character for each box.
+    let mut text = String::new();
+    let mut offsets = Vec::with_capacity(items.len());
+    let mut closing = Vec::new();
+    for item in &items {
+        offsets.push(text.len());
+        match item {
+            InlineItem::Space(_) => text.push(' '),
+            InlineItem::Word(run) => text.push_str(&run.text),
+            InlineItem::Start(_, style, _) => {
+                let (open, close) = bidi_controls(style);
+                text.push_str(open);
+                closing.push(close);
+            }
+            InlineItem::End => text.push_str(closing.pop().unwrap_or_default()),
+            InlineItem::Box(_) => text.push('\u{FFFC}'),
+        }
+    }
+
+    let info = BidiInfo::new(&text, base);
+    let paragraph = info.paragraphs.first().map_or(Level::ltr(), |p| p.level);
+    if !info.has_rtl() {
+        let items = items.into_iter().map(|item| (item, paragraph)).collect();
+        return (items, false);
+    }
+    let mut resolved = Vec::with_capacity(items.len());
+    for (item, offset) in items.into_iter().zip(offsets) {
+        match item {
+            InlineItem::Word(run) => {
+                let levels = &info.levels[offset..offset + run.text.len()];
+                split_word(run, levels, &mut resolved);
+            }
+            item => {
+                let level = info.levels.get(offset).copied().unwrap_or(paragraph);
+                resolved.push((item, level));
+            }
+        }
+    }
+    (resolved, paragraph.is_rtl())
+}
+
+// Split a word into runs of one level each, given the level of each of its bytes.
+fn split_word<'a>(run: TextRun<'a>, levels: &[Level], resolved: &mut Vec<(InlineItem<'a>, Level)>) {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for (i, _) in run.text.char_indices() {
+        if levels[i] != levels[start] {
+            pieces.push(start..i);
+            start = i;
+        }
+    }
+    pieces.push(start..run.text.len());
+
+    let whole = pieces.len() == 1;
+    for range in pieces {
+        let level = levels[range.start];
+        let mut piece = run.clone();
+        piece.text = run.text[range].to_string();
+        if let Some(font) = piece.font
+            && (!whole || level.is_rtl())
+        {
+            piece.glyphs = font.shape(&piece.text, piece.font_size, level.is_rtl());
+            piece.width = sum(piece.glyphs.iter().map(|glyph| glyph.advance));
+        }
+        resolved.push((InlineItem::Word(piece), level));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::css;
+    use super::super::super::loader::FileSystemLoader;
+    use super::super::tests::{layout_with_fonts, text_runs};
+    use super::*;
+
+    // A word on a line, with the glyphs it's painted with.
+    struct Word {
+        text: String,
+        left: f32,
+        width: f32,
+        glyphs: Vec<u16>,
+    }
+
+    // The words on the lines of a paragraph 400px wide, from left to right.
+    fn visual_words(html: &str, style: &str) -> Vec<Word> {
+        let layout_tree = layout_with_fonts(
+            html,
+            &format!("p {{ display: block; font-family: Noto; font-size: 20px; }}\n{style}"),
+            400.0,
+        );
+        let mut words: Vec<Word> = text_runs(&layout_tree.children[0])
+            .iter()
+            .map(|run| Word {
+                text: run.text.clone(),
+                left: run.left,
+                width: run.width,
+                glyphs: run.glyphs.iter().map(|glyph| glyph.id).collect(),
+            })
+            .collect();
+        words.sort_by(|a, b| a.left.total_cmp(&b.left));
+        words
+    }
+
+    fn texts(words: &[Word]) -> Vec<&str> {
+        words.iter().map(|word| word.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_bidi_reordering() {
+        // Right-to-left words are reversed on the line, with the spaces staying between them.
+        let words = visual_words("<p>abc אבג דהו xyz</p>", "");
+        assert_eq!(texts(&words), vec!["abc", "דהו", "אבג", "xyz"]);
+        let gaps: Vec<f32> = words
+            .windows(2)
+            .map(|pair| pair[1].left - pair[0].left - pair[0].width)
+            .collect();
+        assert!(
+            gaps.iter()
+                .all(|&gap| gap > 0.0 && (gap - gaps[0]).abs() < 0.01)
+        );
+
+        // A word that changes direction is split where it does.
+        let words = visual_words("<p>abcאבג</p>", "");
+        assert_eq!(texts(&words), vec!["abc", "אבג"]);
+        assert_eq!(words[1].left, words[0].left + words[0].width);
+    }
+
+    #[test]
+    fn test_direction() {
+        // A right-to-left paragraph starts on the right.
+        let words = visual_words("<p class=\"rtl\">(אבג) abc</p>", ".rtl { direction: rtl; }");
+        assert_eq!(texts(&words), vec!["abc", "(אבג)"]);
+        assert!((words[1].left + words[1].width - 400.0).abs() < 0.01);
+        // Its words are painted from right to left, with their brackets mirrored.
+        let css = css::parse(
+            "@font-face { font-family: Noto; src: url(NotoSerif-Regular.ttf); }".to_owned(),
+        );
+        let mut fonts = FontRegistry::new();
+        fonts.load_font_faces(&css, &FileSystemLoader::new("testfiles"));
+        let noto = fonts.find(&["Noto".to_string()], 400, FontStyle::Normal, '(');
+        let index = |c| noto.unwrap().font.lookup_glyph_index(c);
+        let glyphs = &words[1].glyphs;
+        assert_eq!(glyphs.len(), 5);
+        assert_eq!((glyphs[0], glyphs[4]), (index('('), index(')')));
+
+        // An override puts the words of a box in the direction of the box.
+        let words = visual_words(
+            "<p>x <span class=\"o\">abc def</span></p>",
+            ".o { direction: rtl; unicode-bidi: bidi-override; }",
+        );
+        assert_eq!(texts(&words), vec!["x", "def", "abc"]);
+    }
+}
@@ -0,0 +1,592 @@
+//! Flex layout, following CSS Flexible Box Layout Level 1.
+//!
+//! https://www.w3.org/TR/css-flexbox-1/
+
+use super::*;
+
+// The properties of a flex container that apply to all of its items.
+struct FlexContainer {
+    // The main axis is horizontal (`row`), rather than vertical (`column`).
+    row: bool,
+    // Items are placed from the end of the main axis.
+    reverse: bool,
+    wrap: bool,
+    // Lines are stacked from the end of the cross axis.
+    wrap_reverse: bool,
+    justify_content: String,
+    align_items: String,
+    align_content: String,
+    // Gaps between items on a line, and between lines.
+    main_gap: f32,
+    cross_gap: f32,
+}
+
+impl FlexContainer {
+    fn new(style: &StyledNode) -> FlexContainer {
+        let direction = keyword(style, "flex-direction", "row");
+        let wrap = keyword(style, "flex-wrap", "nowrap");
+        let row = !direction.starts_with("column");
+        let row_gap = style.value("row-gap").map_or(0.0, |gap| gap.to_px());
+        let column_gap = style.value("column-gap").map_or(0.0, |gap| gap.to_px());
+        FlexContainer {
+            row,
+            reverse: direction.ends_with("-reverse"),
+            wrap: wrap != "nowrap",
+            wrap_reverse: wrap == "wrap-reverse",
+            justify_content: keyword(style, "justify-content", "flex-start"),
+            align_items: keyword(style, "align-items", "stretch"),
+            align_content: keyword(style, "align-content", "stretch"),
+            main_gap: if row { column_gap } else { row_gap },
+            cross_gap: if row { row_gap } else { column_gap },
+        }
+    }
+}
+
+// A child of a flex container, while the container lays it out. Sizes along the main axis are
+// content sizes, the others are margin box sizes.
+struct FlexItem {
+    index: usize,
+    // The flex base size, and the hypothetical main size: the base size clamped by the min and
+    // max sizes.
+    base: f32,
+    hypothetical: f32,
+    // The used main size once flexible lengths are resolved.
+    target: f32,
+    frozen: bool,
+    grow: f32,
+    shrink: f32,
+    // Margins, borders and padding along the main axis.
+    main_edges: f32,
+    main_position: f32,
+    cross_size: f32,
+    cross_position: f32,
+}
+
+impl FlexItem {
+    fn outer_target(&self) -> f32 {
+        self.target + self.main_edges
+    }
+}
+
+struct FlexLine {
+    items: Vec<FlexItem>,
+    cross_size: f32,
+    cross_position: f32,
+}
+
+impl FlexLine {
+    // The main size the items take up at their hypothetical sizes, gaps included.
+    fn hypothetical_size(&self, gap: f32) -> f32 {
+        sum(self
+            .items
+            .iter()
+            .map(|item| item.hypothetical + item.main_edges))
+            + gap * self.items.len().saturating_sub(1) as f32
+    }
+
+    // Grow or shrink the items to fill the main size of the container.
+    // https://www.w3.org/TR/css-flexbox-1/#resolve-flexible-lengths
+    fn resolve_flexible_lengths(
+        &mut self,
+        main_size: f32,
+        gap: f32,
+        children: &[LayoutBox],
+        row: bool,
+    ) {
+        let available = main_size - gap * self.items.len().saturating_sub(1) as f32;
+        let growing = self.hypothetical_size(0.0) < available;
+        for item in &mut self.items {
+            let factor = if growing { item.grow } else { item.shrink };
+            item.target = item.hypothetical;
+            item.frozen = factor == 0.0
+                || (growing && item.base > item.hypothetical)
+                || (!growing && item.base < item.hypothetical);
+        }
+        let free_space = |items: &[FlexItem]| {
+            available
+                - sum(items.iter().map(|item| match item.frozen {
+                    true => item.outer_target(),
+                    false => item.base + item.main_edges,
+                }))
+        };
+        let initial_free_space = free_space(&self.items);
+
+        while self.items.iter().any(|item| !item.frozen) {
+            let mut free = free_space(&self.items);
+            let unfrozen = || self.items.iter().filter(|item| !item.frozen);
+            let factors = sum(unfrozen().map(|item| if growing { item.grow } else { item.shrink }));
+            // Flex factors summing to less than one only take that fraction of the space.
+            if factors < 1.0 && (initial_free_space * factors).abs() < free.abs() {
+                free = initial_free_space * factors;
+            }
+            // Shrinking is in proportion to the base size too, so small items don't vanish first.
+            let scaled_factors = sum(unfrozen().map(|item| item.shrink * item.base));
+
+            let mut total_violation = 0.0;
+            let mut violations = vec![0.0; self.items.len()];
+            for (item, violation) in self.items.iter_mut().zip(&mut violations) {
+                if item.frozen {
+                    continue;
+                }
+                item.target = item.base;
+                if growing && factors > 0.0 {
+                    item.target += free * item.grow / factors;
+                } else if !growing && scaled_factors > 0.0 {
+                    item.target += free * item.shrink * item.base / scaled_factors;
+                }
+                let clamped = children[item.index].clamp_axis(item.target, row);
+                *violation = clamped - item.target;
+                total_violation += *violation;
+                item.target = clamped;
+            }
+
+            // Freeze the items that hit their limits, and distribute the space again.
+            for (item, violation) in self.items.iter_mut().zip(violations) {
+                item.frozen |= total_violation == 0.0
+                    || (total_violation > 0.0 && violation > 0.0)
+                    || (total_violation < 0.0 && violation < 0.0);
+            }
+        }
+    }
+}
+
+// Where to start placing `count` items or lines in `free` space, and the space to add between
+// them, for a `justify-content` or `align-content` keyword.
+fn distribute(keyword: &str, free: f32, count: usize) -> (f32, f32) {
+    let count = count as f32;
+    match keyword {
+        "flex-end" | "end" => (free, 0.0),
+        "center" => (free / 2.0, 0.0),
+        "space-between" if free > 0.0 && count > 1.0 => (0.0, free / (count - 1.0)),
+        "space-around" if free > 0.0 => (free / count / 2.0, free / count),
+        "space-evenly" if free > 0.0 => (free / (count + 1.0), free / (count + 1.0)),
+        // Overflowing items are centered when the space would be distributed around them.
+        "space-around" | "space-evenly" => (free / 2.0, 0.0),
+        _ => (0.0, 0.0),
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    pub(super) fn layout_flex(
+        &mut self,
+        container_width: Value,
+        container_height: Value,
+        context_constraints_width: Value,
+        fonts: &'a FontRegistry,
+    ) {
+        let flex = FlexContainer::new(self.get_style_node());
+        let auto = Value::Keyword("auto".to_string());
+
+        // 1. self: width, edges and height -> (specific or filling the container: definite, auto)
+        let (self_as_container_width, self_as_context_constraints_width, is_self_no_filled_auto) =
+            self.resolve_width(&container_width, &context_constraints_width);
+        self.resolve_edges();
+        let self_as_container_height = self.resolve_height(&container_height);
+
+        let definite_width = (!is_self_no_filled_auto).then_some(self.dimensions.inner.width);
+        let definite_height = match self_as_container_height {
+            Value::Length(height, _) => Some(height),
+            _ => None,
+        };
+        let (definite_main, definite_cross) = match flex.row {
+            true => (definite_width, definite_height),
+            false => (definite_height, definite_width),
+        };
+
+        // 2. items: flex base size and hypothetical main size, in `order`
+        let mut items = Vec::new();
+        for index in self.children_in_order() {
+            let child = &mut self.children[index];
+            child.imposed_size = ImposedSize::default();
+            // Laid out at its content size, except for the width of stretched column items.
+            let item_container_width = match !flex.row && child.stretches(&flex) {
+                true => self_as_container_width.clone(),
+                false => auto.clone(),
+            };
+            child.layout_item(
+                &item_container_width,
+                &self_as_container_height,
+                &self_as_context_constraints_width,
+                fonts,
+            );
+
+            let style = child.item_style();
+            let number = |name: &str, default: f32| match style.and_then(|style| style.value(name))
+            {
+                Some(Value::Number(number)) if number >= 0.0 => number,
+                _ => default,
+            };
+            let base = child.flex_base_size(flex.row, definite_main, &self_as_container_height);
+            let hypothetical = child.clamp_axis(base, flex.row);
+            items.push(FlexItem {
+                index,
+                base,
+                hypothetical,
+                target: hypothetical,
+                frozen: false,
+                grow: number("flex-grow", 0.0),
+                shrink: number("flex-shrink", 1.0),
+                main_edges: child.axis_edges(flex.row),
+                main_position: 0.0,
+                cross_size: 0.0,
+                cross_position: 0.0,
+            });
+        }
+
+        // 3. lines: break the items where the next one doesn't fit
+        let available_main = definite_main.unwrap_or(match flex.row {
+            true => self_as_context_constraints_width.to_px(),
+            false => f32::INFINITY,
+        });
+        let mut lines: Vec<FlexLine> = Vec::new();
+        for item in items {
+            let outer = item.hypothetical + item.main_edges;
+            match lines.last_mut() {
+                Some(line)
+                    if !flex.wrap
+                        || line.hypothetical_size(flex.main_gap) + flex.main_gap + outer
+                            <= available_main =>
+                {
+                    line.items.push(item)
+                }
+                _ => lines.push(FlexLine {
+                    items: vec![item],
+                    cross_size: 0.0,
+                    cross_position: 0.0,
+                }),
+            }
+        }
+
+        // 4. self main: size -> (definite, auto by the longest line)
+        let main_size = definite_main.unwrap_or_else(|| {
+            let longest = lines
+                .iter()
+                .map(|line| line.hypothetical_size(flex.main_gap))
+                .fold(0.0, f32::max);
+            match flex.row {
+                true => self.clamp_width(longest.min(available_main)),
+                false => self.clamp_height(longest),
+            }
+        });
+
+        // 5. items main: flexible lengths, then lay out at the used main size
+        for line in &mut lines {
+            line.resolve_flexible_lengths(main_size, flex.main_gap, &self.children, flex.row);
+            for item in &mut line.items {
+                let child = &mut self.children[item.index];
+                match flex.row {
+                    true => child.imposed_size.width = Some(item.target),
+                    false => child.imposed_size.height = Some(item.target),
+                }
+                let item_container_width = match !flex.row && child.stretches(&flex) {
+                    true => self_as_container_width.clone(),
+                    false => auto.clone(),
+                };
+                child.layout_item(
+                    &item_container_width,
+                    &self_as_container_height,
+                    &self_as_context_constraints_width,
+                    fonts,
+                );
+                item.cross_size = child.axis_margin_box_size(!flex.row);
+            }
+            line.cross_size = line
+                .items
+                .iter()
+                .map(|item| item.cross_size)
+                .fold(0.0, f32::max);
+        }
+
+        // 6. self cross: size -> (definite, auto by the lines stacked)
+        let cross_gaps = flex.cross_gap * lines.len().saturating_sub(1) as f32;
+        let lines_cross_size = sum(lines.iter().map(|line| line.cross_size)) + cross_gaps;
+        let cross_size = definite_cross.unwrap_or(match flex.row {
+            true => self.clamp_height(lines_cross_size),
+            false => {
+                self.clamp_width(lines_cross_size.min(self_as_context_constraints_width.to_px()))
+            }
+        });
+        if !flex.wrap {
+            // The line of a single-line container is as large as the container.
+            for line in &mut lines {
+                line.cross_size = cross_size;
+            }
+        } else if matches!(&*flex.align_content, "stretch" | "normal")
+            && cross_size > lines_cross_size
+        {
+            let extra = (cross_size - lines_cross_size) / lines.len() as f32;
+            for line in &mut lines {
+                line.cross_size += extra;
+            }
+        }
+
+        // 7. items cross: stretch -> fill the line
+        let self_as_container_width = match flex.row {
+            true => Value::Length(main_size, Unit::Px),
+            false => Value::Length(cross_size, Unit::Px),
+        };
+        for line in &mut lines {
+            for item in &mut line.items {
+                let child = &mut self.children[item.index];
+                if !child.stretches(&flex) {
+                    continue;
+                }
+                let size =
+                    child.clamp_axis(line.cross_size - child.axis_edges(!flex.row), !flex.row);
+                match flex.row {
+                    true => child.imposed_size.height = Some(size),
+                    false => child.imposed_size.width = Some(size),
+                }
+                child.layout_item(
+                    &self_as_container_width,
+                    &self_as_container_height,
+                    &self_as_context_constraints_width,
+                    fonts,
+                );
+                item.cross_size = child.axis_margin_box_size(!flex.row);
+            }
+        }
+
+        // 8. main alignment: auto margins, then `justify-content`
+        for line in &mut lines {
+            let gaps = flex.main_gap * line.items.len().saturating_sub(1) as f32;
+            let mut free = main_size - gaps - sum(line.items.iter().map(FlexItem::outer_target));
+            let auto_margins: usize = line
+                .items
+                .iter()
+                .map(|item| self.children[item.index].auto_margin_count(flex.row))
+                .sum();
+            if auto_margins > 0 {
+                let space = free.max(0.0) / auto_margins as f32;
+                for item in &mut line.items {
+                    let child = &mut self.children[item.index];
+                    item.main_edges += space * child.auto_margin_count(flex.row) as f32;
+                    child.fill_auto_margins(flex.row, space);
+                }
+                free = free.min(0.0);
+            }
+            let justify_content = match (&*flex.justify_content, flex.reverse) {
+                ("start", true) => "flex-end",
+                ("end", true) => "flex-start",
+                (justify_content, _) => justify_content,
+            };
+            let (mut position, between) = distribute(justify_content, free, line.items.len());
+            for item in &mut line.items {
+                item.main_position = match flex.reverse {
+                    true => main_size - position - item.outer_target(),
+                    false => position,
+                };
+                position += item.outer_target() + flex.main_gap + between;
+            }
+        }
+
+        // 9. cross alignment: lines by `align-content`, items in them by `align-self`
+        let (mut position, between) = match flex.wrap {
+            true => distribute(
+                &flex.align_content,
+                cross_size - lines_cross_size,
+                lines.len(),
+            ),
+            false => (0.0, 0.0),
+        };
+        for line in &mut lines {
+            line.cross_position = match flex.wrap_reverse {
+                true => cross_size - position - line.cross_size,
+                false => position,
+            };
+            position += line.cross_size + flex.cross_gap + between;
+
+            for item in &mut line.items {
+                let child = &mut self.children[item.index];
+                let free = line.cross_size - item.cross_size;
+                let auto_margins = child.auto_margin_count(!flex.row);
+                let offset = if auto_margins > 0 {
+                    child.fill_auto_margins(!flex.row, free.max(0.0) / auto_margins as f32);
+                    0.0
+                } else {
+                    let offset = match &*child.align_self(&flex) {
+                        "flex-end" | "end" | "self-end" => free,
+                        "center" => free / 2.0,
+                        _ => 0.0,
+                    };
+                    match flex.wrap_reverse {
+                        true => free - offset,
+                        false => offset,
+                    }
+                };
+                item.cross_position = line.cross_position + offset;
+            }
+        }
+
+        // 10. fixing children: position -> (main, cross)
+        for line in &lines {
+            for item in &line.items {
+                let offset = &mut self.children[item.index].dimensions.box_offset;
+                match flex.row {
+                    true => (offset.left, offset.top) = (item.main_position, item.cross_position),
+                    false => (offset.top, offset.left) = (item.main_position, item.cross_position),
+                }
+            }
+        }
+        match flex.row {
+            true => {
+                (self.dimensions.inner.width, self.dimensions.inner.height) =
+                    (main_size, cross_size)
+            }
+            false => {
+                (self.dimensions.inner.height, self.dimensions.inner.width) =
+                    (main_size, cross_size)
+            }
+        }
+    }
+
+    // The content size `flex-basis` gives along the main axis, or else `width` or `height`, or
+    // else the size of the content, as laid out already.
+    fn flex_base_size(
+        &self,
+        row: bool,
+        definite_main: Option<f32>,
+        container_height: &Value,
+    ) -> f32 {
+        let Some(style) = self.item_style() else {
+            return self.axis_content_size(row);
+        };
+        let basis = match style.value("flex-basis") {
+            Some(Value::Percentage(percentage)) => definite_main
+                .map(|main| Value::Length(main * percentage / 100.0, Unit::Px))
+                .unwrap_or(Value::Keyword("auto".to_string())),
+            Some(basis) => basis,
+            None => Value::Keyword("auto".to_string()),
+        };
+        let basis = match basis.is_specific_length() {
+            true => basis,
+            false if row => style.value("width").unwrap_or(basis),
+            false => self.specified_height(container_height),
+        };
+        match (basis.is_specific_length(), row) {
+            (true, true) => self.content_width(basis.to_px()),
+            (true, false) => self.content_height(basis.to_px()),
+            (false, _) => self.axis_content_size(row),
+        }
+    }
+
+    // The `align-self` of an item, with `auto` taking the container's `align-items`.
+    fn align_self(&self, flex: &FlexContainer) -> String {
+        match self
+            .item_style()
+            .map(|style| keyword(style, "align-self", "auto"))
+        {
+            Some(align_self) if align_self != "auto" => align_self,
+            _ => flex.align_items.clone(),
+        }
+    }
+
+    // Does the item stretch to the cross size of its line? Only when its cross size and margins
+    // are `auto`.
+    fn stretches(&self, flex: &FlexContainer) -> bool {
+        let auto_size = match self.item_style() {
+            None => true,
+            Some(_) if flex.row => self
+                .specified_height(&Value::Keyword("auto".to_string()))
+                .is_auto(),
+            Some(_) => self.is_width_auto(),
+        };
+        matches!(&*self.align_self(flex), "stretch" | "normal")
+            && auto_size
+            && self.auto_margin_count(!flex.row) == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{css, html};
+    use super::super::tests::{layout, rect};
+    use super::*;
+
+    #[test]
+    fn test_flex_row() {
+        let root = html::parse(
+            "<div><div class=\"row\"><p class=\"a\"></p><p class=\"b\"></p><p class=\"c\"></p></div><div class=\"reverse\"><p class=\"x\"></p><p class=\"x y\"></p></div><div class=\"push\"><p class=\"dot\"></p><p class=\"dot end\"></p></div></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div { display: block; }
+.row { display: flex; width: 300px; height: 100px; gap: 10px; }
+.a { flex-basis: 50px; flex-grow: 1; }
+.b { width: 100px; }
+.c { flex-basis: 20px; flex-grow: 2; max-width: 60px; height: 30px; }
+.reverse { display: flex; flex-direction: row-reverse; width: 100px; }
+.x { width: 80px; height: 10px; }
+.y { order: -1; flex-shrink: 3; }
+.push { display: flex; width: 100px; align-items: center; }
+.dot { width: 10px; height: 10px; }
+.end { margin-left: auto; height: 20px; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let layout_tree = layout(&styled_tree, &fonts);
+        let [row, reverse, push] = &layout_tree.children[..] else {
+            panic!("expected three flex containers");
+        };
+
+        // The free space goes to `a` once `c` hits its max width; items without a height stretch.
+        let [a, b, c] = &row.children[..] else {
+            panic!("expected three items");
+        };
+        assert_eq!(rect(a), (0.0, 0.0, 120.0, 100.0));
+        assert_eq!(rect(b), (130.0, 0.0, 100.0, 100.0));
+        assert_eq!(rect(c), (240.0, 0.0, 60.0, 30.0));
+
+        // `y` comes first, from the right, and shrinks three times as much.
+        let [x, y] = &reverse.children[..] else {
+            panic!("expected two items");
+        };
+        assert_eq!(rect(y), (65.0, 100.0, 35.0, 10.0));
+        assert_eq!(rect(x), (0.0, 100.0, 65.0, 10.0));
+        assert_eq!(reverse.dimensions.inner.height, 10.0);
+
+        // An auto margin takes the free space, before `justify-content`.
+        assert_eq!(rect(&push.children[0]), (0.0, 115.0, 10.0, 10.0));
+        assert_eq!(rect(&push.children[1]), (90.0, 110.0, 10.0, 20.0));
+    }
+
+    #[test]
+    fn test_flex_column_wrap() {
+        let root = html::parse(
+            "<div><div class=\"column\"><p class=\"square\"></p><p class=\"square\"></p><p class=\"square\"></p></div><span class=\"inline\"><p class=\"narrow\"></p>text<p class=\"narrow\"></p></span></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div { display: block; }
+.column { display: flex; flex-direction: column; flex-wrap: wrap; width: 200px; height: 100px; align-items: center; justify-content: center; }
+.square { width: 40px; height: 40px; }
+.inline { display: inline-flex; column-gap: 5px; }
+.narrow { width: 30px; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let layout_tree = layout(&styled_tree, &fonts);
+
+        // Two lines share the width, each item is centered in its line.
+        let column = &layout_tree.children[0];
+        let [first, second, third] = &column.children[..] else {
+            panic!("expected three items");
+        };
+        assert_eq!(rect(first), (30.0, 10.0, 40.0, 40.0));
+        assert_eq!(rect(second), (30.0, 50.0, 40.0, 40.0));
+        assert_eq!(rect(third), (130.0, 30.0, 40.0, 40.0));
+
+        // An inline flex container is as wide as its items, and the text between them is an
+        // anonymous item (with no width, since no font is loaded).
+        let inline = &layout_tree.children[1].children[0];
+        assert_eq!(inline.children.len(), 3);
+        assert!(matches!(
+            inline.children[1].box_type,
+            BoxType::AnonymousBlock(_)
+        ));
+        assert_eq!(inline.dimensions.inner.width, 70.0);
+        assert_eq!(rect(&inline.children[2]), (40.0, 100.0, 30.0, 0.0));
+    }
+}
@@ -0,0 +1,255 @@
+//! Floats and clearance, following CSS 2.2 § 9.5.
+//!
+//! https://www.w3.org/TR/CSS22/visuren.html#floats
+
+use super::*;
+
+// A float placed in a block formatting context, with its margin box.
+#[derive(Clone, Copy, Debug)]
+struct PlacedFloat {
+    side: Float,
+    rect: Rect,
+}
+
+impl PlacedFloat {
+    fn bottom(&self) -> f32 {
+        self.rect.y + self.rect.height
+    }
+
+    // Whether the float is beside any part of a box from `top` down to `top + height`.
+    fn overlaps(&self, top: f32, height: f32) -> bool {
+        (self.rect.y <= top || self.rect.y < top + height) && self.bottom() > top
+    }
+}
+
+// The floats of a block formatting context, in the order they were placed. Positions are
+// relative to the content box of the box that establishes the context.
+#[derive(Clone, Default, Debug)]
+pub(super) struct FloatContext {
+    floats: Vec<PlacedFloat>,
+}
+
+impl FloatContext {
+    pub(super) fn len(&self) -> usize {
+        self.floats.len()
+    }
+
+    // Forget the floats placed after the first `len`, like when content is laid out again.
+    pub(super) fn truncate(&mut self, len: usize) {
+        self.floats.truncate(len);
+    }
+
+    // The left and right edges of the space that floats leave between `left` and `right`, for a
+    // box from `top` down to `top + height`.
+    pub(super) fn band(&self, top: f32, height: f32, (left, right): (f32, f32)) -> (f32, f32) {
+        let mut band = (left, right);
+        for float in self
+            .floats
+            .iter()
+            .filter(|float| float.overlaps(top, height))
+        {
+            match float.side {
+                Float::Left => band.0 = band.0.max(float.rect.x + float.rect.width),
+                _ => band.1 = band.1.min(float.rect.x),
+            }
+        }
+        band
+    }
+
+    // The highest position at or below `top` where a box of `size` fits beside the floats, with
+    // the band it fits in. Where nothing is wide enough, that's below all the floats in the way.
+    pub(super) fn fit(
+        &self,
+        mut top: f32,
+        (width, height): (f32, f32),
+        extent: (f32, f32),
+    ) -> (f32, f32, f32) {
+        loop {
+            let (left, right) = self.band(top, height, extent);
+            let next = self
+                .floats
+                .iter()
+                .filter(|float| float.overlaps(top, height))
+                .map(PlacedFloat::bottom)
+                .reduce(f32::min);
+            match next {
+                Some(bottom) if right - left < width => top = bottom,
+                _ => return (top, left, right),
+            }
+        }
+    }
+
+    // Place a float with a margin box of `size` as high as it goes from `top`, and as far to its
+    // side as it goes between `left` and `right`. Returns where its margin box goes.
+    // https://www.w3.org/TR/CSS22/visuren.html#float-position
+    pub(super) fn place(
+        &mut self,
+        side: Float,
+        size: (f32, f32),
+        top: f32,
+        extent: (f32, f32),
+    ) -> (f32, f32) {
+        // A float doesn't go higher than any earlier one.
+        let top = self
+            .floats
+            .last()
+            .map_or(top, |float| top.max(float.rect.y));
+        let (top, left, right) = self.fit(top, size, extent);
+        let x = match side {
+            Float::Left => left,
+            _ => right - size.0,
+        };
+        self.floats.push(PlacedFloat {
+            side,
+            rect: Rect {
+                x,
+                y: top,
+                width: size.0,
+                height: size.1,
+            },
+        });
+        (x, top)
+    }
+
+    // The bottom of the floats that `clear` moves a box below, if there are any.
+    pub(super) fn clearance(&self, clear: Clear) -> Option<f32> {
+        self.floats
+            .iter()
+            .filter(|float| match clear {
+                Clear::None => false,
+                Clear::Left => float.side == Float::Left,
+                Clear::Right => float.side == Float::Right,
+                Clear::Both => true,
+            })
+            .map(PlacedFloat::bottom)
+            .reduce(f32::max)
+    }
+
+    // The bottom of the lowest float, which the root of the context grows to contain.
+    pub(super) fn bottom(&self) -> f32 {
+        self.floats
+            .iter()
+            .map(PlacedFloat::bottom)
+            .fold(0.0, f32::max)
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    // Lay out a float as the root of its own formatting context, shrunk to fit in `width`.
+    pub(super) fn layout_float(
+        &mut self,
+        container_height: &Value,
+        width: f32,
+        fonts: &'a FontRegistry,
+    ) {
+        self.layout_box(
+            Value::Keyword("auto".to_string()),
+            container_height.clone(),
+            Value::Length(width, Unit::Px),
+            fonts,
+        );
+    }
+
+    // Place a laid out float in `floats`, no higher than `top` and between the edges of
+    // `extent`. `origin` is where the content box of the parent is.
+    pub(super) fn place_float(
+        &mut self,
+        floats: &mut FloatContext,
+        top: f32,
+        origin: (f32, f32),
+        extent: (f32, f32),
+    ) {
+        let style = self.get_style_node();
+        let top = match floats.clearance(style.clear()) {
+            Some(bottom) => top.max(bottom),
+            None => top,
+        };
+        let size = (
+            self.axis_margin_box_size(true),
+            self.axis_margin_box_size(false),
+        );
+        let (x, y) = floats.place(style.float(), size, top, extent);
+        self.dimensions.box_offset.left = x - origin.0;
+        self.dimensions.box_offset.top = y - origin.1;
+    }
+
+    // Whether a block-level box establishes a block formatting context of its own. In flow, such
+    // a box keeps clear of the floats around it.
+    // https://www.w3.org/TR/CSS22/visuren.html#block-formatting
+    pub(super) fn establishes_context(&self) -> bool {
+        match self.box_type {
+            BoxType::BlockNode(style) => {
+                self.is_float()
+                    || self.is_out_of_flow()
+                    || keyword(style, "overflow", "visible") != "visible"
+            }
+            BoxType::AnonymousBlock(_) => false,
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{css, html};
+    use super::super::tests::{layout, layout_with_fonts, rect, text_runs};
+    use super::*;
+
+    #[test]
+    fn test_floats() {
+        let root = html::parse(
+            "<div><p class=\"left\"></p><p class=\"left\"></p><p class=\"right\"></p><p class=\"clear\"></p><p class=\"left tall\"></p><div class=\"context\"></div><p class=\"after\"></p></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div { display: block; }
+p { display: block; }
+.left { float: left; width: 100px; height: 20px; margin-right: 10px; }
+.right { float: right; width: 50px; height: 30px; }
+.clear { clear: left; height: 10px; }
+.tall { height: 50px; }
+.context { overflow: hidden; height: 10px; }
+.after { height: 10px; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let layout_tree = layout(&styled_tree, &fonts);
+
+        let children = &layout_tree.children;
+        // Floats stack side by side.
+        assert_eq!(rect(&children[0]), (0.0, 0.0, 100.0, 20.0));
+        assert_eq!(rect(&children[1]), (110.0, 0.0, 100.0, 20.0));
+        assert_eq!(rect(&children[2]), (350.0, 0.0, 50.0, 30.0));
+        // Below the left floats, still beside the right one.
+        assert_eq!(rect(&children[3]), (0.0, 20.0, 400.0, 10.0));
+        assert_eq!(rect(&children[4]), (0.0, 30.0, 100.0, 50.0));
+        // A new formatting context goes beside the float; other blocks go under it.
+        assert_eq!(rect(&children[5]), (110.0, 30.0, 290.0, 10.0));
+        assert_eq!(rect(&children[6]), (0.0, 40.0, 400.0, 10.0));
+        // The root contains its floats.
+        assert_eq!(rect(&layout_tree).3, 80.0);
+    }
+
+    #[test]
+    fn test_lines_beside_floats() {
+        let layout_tree = layout_with_fonts(
+            "<div class=\"para\"><span class=\"image\"></span>Lorem ipsum dolor sit amet, consectetur adipiscing elit.</div>",
+            ".para { display: block; font-family: Noto; font-size: 20px; }
+.image { float: left; width: 100px; height: 30px; }",
+            200.0,
+        );
+
+        let anonymous = &layout_tree.children[0];
+        assert_eq!(rect(&anonymous.children[0]), (0.0, 0.0, 100.0, 30.0));
+        let runs = text_runs(anonymous);
+        assert_eq!(runs[0].left, 100.0);
+        // Lines beside the float are shorter, and start after it; those below it don't.
+        for run in &runs {
+            if run.top < 30.0 {
+                assert!(run.left >= 100.0 && run.left + run.width <= 200.0);
+            }
+        }
+        assert!(runs.iter().any(|run| run.top >= 30.0 && run.left == 0.0));
+    }
+}
@@ -0,0 +1,987 @@
+//! Grid layout, following CSS Grid Layout Level 1.
+//!
+//! https://www.w3.org/TR/css-grid-1/
+
+use super::*;
+use std::collections::{HashMap, HashSet};
+
+// The most times a track list repeats, the furthest line an item can name and the most tracks it
+// can span. Enough for any real grid, without running out of memory or time on huge numbers.
+// https://www.w3.org/TR/css-grid-1/#overlarge-grids
+const MAX_LINES: usize = 1000;
+
+// One end of the sizing function of a track.
+#[derive(Clone, Copy, Debug)]
+enum Breadth {
+    // A length, or a percentage of a definite grid size.
+    Fixed(f32),
+    // A share of the free space, in `fr`.
+    Flex(f32),
+    // `auto`, `min-content` or `max-content`: as large as the items need.
+    Auto,
+}
+
+impl Breadth {
+    fn parse(value: &Value, basis: Option<f32>) -> Option<Breadth> {
+        match value {
+            Value::Length(fr, Unit::Fr) => Some(Breadth::Flex(*fr)),
+            length if length.is_specific_length() => Some(Breadth::Fixed(length.to_px())),
+            Value::Percentage(percentage) => Some(match basis {
+                Some(basis) => Breadth::Fixed(basis * percentage / 100.0),
+                None => Breadth::Auto,
+            }),
+            Value::Keyword(keyword) => match keyword.as_str() {
+                "auto" | "min-content" | "max-content" => Some(Breadth::Auto),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TrackSize {
+    min: Breadth,
+    max: Breadth,
+}
+
+impl TrackSize {
+    const AUTO: TrackSize = TrackSize {
+        min: Breadth::Auto,
+        max: Breadth::Auto,
+    };
+
+    // A track size like `100px`, `1fr` or `minmax(100px, 1fr)`.
+    fn parse(value: &Value, basis: Option<f32>) -> Option<TrackSize> {
+        match value {
+            Value::Function(name, args) if name == "minmax" => match &args[..] {
+                [min, max] => Some(TrackSize {
+                    min: match Breadth::parse(min, basis)? {
+                        Breadth::Flex(_) => return None,
+                        min => min,
+                    },
+                    max: Breadth::parse(max, basis)?,
+                }),
+                _ => None,
+            },
+            // `1fr` is short for `minmax(auto, 1fr)`.
+            _ => match Breadth::parse(value, basis)? {
+                Breadth::Flex(fr) => Some(TrackSize {
+                    min: Breadth::Auto,
+                    max: Breadth::Flex(fr),
+                }),
+                breadth => Some(TrackSize {
+                    min: breadth,
+                    max: breadth,
+                }),
+            },
+        }
+    }
+
+    fn flex_factor(&self) -> Option<f32> {
+        match self.max {
+            Breadth::Flex(fr) => Some(fr),
+            _ => None,
+        }
+    }
+}
+
+// Parse a track list like `100px repeat(2, 1fr)`, or `None` if it's invalid. `basis` is the grid
+// size that percentages refer to, and that `auto-fill` repetitions fill.
+fn parse_track_list(value: Option<Value>, basis: Option<f32>, gap: f32) -> Option<Vec<TrackSize>> {
+    let values = match value {
+        Some(Value::List(values)) => values,
+        Some(Value::Keyword(keyword)) if keyword == "none" => Vec::new(),
+        Some(value) => vec![value],
+        None => Vec::new(),
+    };
+    let mut tracks = Vec::new();
+    for value in &values {
+        match value {
+            Value::Function(name, args) if name == "repeat" => {
+                let [count, repeated] = &args[..] else {
+                    return None;
+                };
+                let repeated = match repeated {
+                    Value::List(values) => values
+                        .iter()
+                        .map(|value| TrackSize::parse(value, basis))
+                        .collect::<Option<Vec<_>>>()?,
+                    value => vec![TrackSize::parse(value, basis)?],
+                };
+                let count = match count {
+                    Value::Number(count) if *count >= 1.0 => *count as usize,
+                    // Empty `auto-fit` tracks aren't collapsed: it's the same as `auto-fill`.
+                    Value::Keyword(keyword) if keyword == "auto-fill" || keyword == "auto-fit" => {
+                        auto_repetitions(&repeated, basis, gap)
+                    }
+                    _ => return None,
+                };
+                for _ in 0..count.min(MAX_LINES) {
+                    tracks.extend(&repeated);
+                }
+            }
+            value => tracks.push(TrackSize::parse(value, basis)?),
+        }
+    }
+    Some(tracks)
+}
+
+// How many times `tracks` fit in the grid size, and at least once.
+fn auto_repetitions(tracks: &[TrackSize], basis: Option<f32>, gap: f32) -> usize {
+    let size = sum(tracks.iter().map(|track| match (track.max, track.min) {
+        (Breadth::Fixed(size), _) | (_, Breadth::Fixed(size)) => size,
+        _ => 0.0,
+    })) + gap * tracks.len() as f32;
+    match basis {
+        Some(basis) if size > 0.0 => (((basis + gap) / size).floor() as usize).max(1),
+        _ => 1,
+    }
+}
+
+// A named area of `grid-template-areas`, as ranges of row and column indices.
+#[derive(Clone, Copy, Debug)]
+struct Area {
+    rows: (usize, usize),
+    columns: (usize, usize),
+}
+
+// The areas named by the strings of `grid-template-areas`, one string per row. A `.` is an
+// unnamed cell.
+fn parse_areas(value: Option<Value>) -> HashMap<String, Area> {
+    let rows = match value {
+        Some(Value::List(rows)) => rows,
+        Some(row @ Value::Str(_)) => vec![row],
+        _ => Vec::new(),
+    };
+    let mut areas: HashMap<String, Area> = HashMap::new();
+    for (row, value) in rows.iter().enumerate() {
+        let Value::Str(names) = value else {
+            continue;
+        };
+        for (column, name) in names.split_whitespace().enumerate() {
+            if name.chars().all(|c| c == '.') {
+                continue;
+            }
+            let cell = Area {
+                rows: (row, row + 1),
+                columns: (column, column + 1),
+            };
+            let area = areas.entry(name.to_string()).or_insert(cell);
+            area.rows = (area.rows.0.min(row), area.rows.1.max(row + 1));
+            area.columns = (area.columns.0.min(column), area.columns.1.max(column + 1));
+        }
+    }
+    areas
+}
+
+// One end of an item's placement on an axis.
+#[derive(Clone, Debug)]
+enum GridLine {
+    Auto,
+    // A line number, counted from the end of the explicit grid if it's negative.
+    Line(i32),
+    Span(usize),
+    // The edge of a named area.
+    Area(String),
+}
+
+impl GridLine {
+    fn parse(value: &Value) -> GridLine {
+        match value {
+            Value::Number(line) if *line != 0.0 => {
+                let max = MAX_LINES as i32;
+                GridLine::Line((*line as i32).clamp(-max, max))
+            }
+            Value::Keyword(keyword) if keyword == "span" => GridLine::Span(1),
+            Value::Keyword(keyword) if keyword == "auto" => GridLine::Auto,
+            Value::Keyword(name) => GridLine::Area(name.clone()),
+            Value::List(values) => match &values[..] {
+                [Value::Keyword(span), Value::Number(count)]
+                | [Value::Number(count), Value::Keyword(span)]
+                    if span == "span" && *count >= 1.0 =>
+                {
+                    GridLine::Span((*count as usize).min(MAX_LINES))
+                }
+                _ => GridLine::Auto,
+            },
+            _ => GridLine::Auto,
+        }
+    }
+}
+
+// The values of a property like `grid-area: 1 / 2 / span 3`, split at the slashes.
+fn slash_separated(value: Option<Value>) -> Vec<Value> {
+    let values = match value {
+        Some(Value::List(values)) => values,
+        Some(value) => vec![value],
+        None => return Vec::new(),
+    };
+    values
+        .split(|value| matches!(value, Value::Keyword(slash) if slash == "/"))
+        .map(|part| match part {
+            [value] => value.clone(),
+            part => Value::List(part.to_vec()),
+        })
+        .collect()
+}
+
+// The lines an item is placed between on the `row` or `column` axis, from the longhands like
+// `grid-row-start`, the `grid-row` shorthand or `grid-area`. An omitted end repeats the name of
+// an area given for the start, or is `auto`.
+fn placement_lines(style: &StyledNode, axis: &str) -> (GridLine, GridLine) {
+    let area = slash_separated(style.value("grid-area"));
+    let lines = slash_separated(style.value(&format!("grid-{}", axis)));
+    let index = if axis == "row" { 0 } else { 1 };
+    let name = |value: Option<&Value>| match value {
+        Some(Value::Keyword(name)) if !matches!(name.as_str(), "auto" | "span") => {
+            Some(Value::Keyword(name.clone()))
+        }
+        _ => None,
+    };
+
+    let start = style
+        .value(&format!("grid-{}-start", axis))
+        .or_else(|| lines.first().cloned())
+        .or_else(|| area.get(index).cloned())
+        .or_else(|| name(area.first()));
+    let end = style
+        .value(&format!("grid-{}-end", axis))
+        .or_else(|| lines.get(1).cloned())
+        .or_else(|| match lines.is_empty() {
+            true => area.get(index + 2).cloned(),
+            false => None,
+        })
+        .or_else(|| name(start.as_ref()));
+    let line = |value: Option<Value>| value.as_ref().map_or(GridLine::Auto, GridLine::parse);
+    (line(start), line(end))
+}
+
+// Where an item goes on an axis: its first track if that's fixed, and the number of tracks it
+// spans.
+type Span = (Option<usize>, usize);
+
+fn resolve_lines(
+    start: GridLine,
+    end: GridLine,
+    areas: &HashMap<String, Area>,
+    rows: bool,
+    explicit_tracks: usize,
+) -> Span {
+    // A line as an index from 0, or a span.
+    enum Edge {
+        Auto,
+        Line(usize),
+        Span(usize),
+    }
+    let edge = |line: GridLine, is_start: bool| match line {
+        GridLine::Line(line) if line > 0 => Edge::Line(line as usize - 1),
+        GridLine::Line(line) => Edge::Line((explicit_tracks as i32 + 1 + line).max(0) as usize),
+        GridLine::Span(count) => Edge::Span(count),
+        GridLine::Area(name) => match areas.get(&name) {
+            Some(area) => {
+                let (area_start, area_end) = if rows { area.rows } else { area.columns };
+                Edge::Line(if is_start { area_start } else { area_end })
+            }
+            None => Edge::Auto,
+        },
+        GridLine::Auto => Edge::Auto,
+    };
+    match (edge(start, true), edge(end, false)) {
+        (Edge::Line(start), Edge::Line(end)) => (Some(start.min(end)), start.abs_diff(end).max(1)),
+        (Edge::Line(start), Edge::Span(count)) => (Some(start), count),
+        (Edge::Line(start), Edge::Auto) => (Some(start), 1),
+        (Edge::Span(count), Edge::Line(end)) => (Some(end.saturating_sub(count)), count),
+        (Edge::Auto, Edge::Line(end)) => (Some(end.saturating_sub(1)), 1),
+        (Edge::Span(count), _) | (_, Edge::Span(count)) => (None, count),
+        (Edge::Auto, Edge::Auto) => (None, 1),
+    }
+}
+
+// A range of track indices.
+type Tracks = (usize, usize);
+
+// The tracks an item occupies.
+#[derive(Clone, Copy, Debug, Default)]
+struct Placement {
+    rows: Tracks,
+    columns: Tracks,
+}
+
+// Place the items on the grid: the items with a fixed position on both axes first, then the
+// items locked to a row, then the others in order. Columns are filled before rows, or the other
+// way around for `grid-auto-flow: column`.
+// https://www.w3.org/TR/css-grid-1/#auto-placement-algo
+fn place_items(
+    requests: &[(Span, Span)],
+    explicit_columns: usize,
+    explicit_rows: usize,
+    column_flow: bool,
+    dense: bool,
+) -> Vec<Placement> {
+    // Work in terms of major tracks (rows) and minor tracks (columns), swapped for a column flow.
+    let swap = |(rows, columns): (Span, Span)| match column_flow {
+        true => (columns, rows),
+        false => (rows, columns),
+    };
+    let requests: Vec<(Span, Span)> = requests.iter().map(|&request| swap(request)).collect();
+    let mut minor_tracks = if column_flow {
+        explicit_rows
+    } else {
+        explicit_columns
+    };
+    for &(_, (start, span)) in &requests {
+        minor_tracks = minor_tracks.max(start.unwrap_or(0) + span);
+    }
+
+    let mut occupied: HashSet<(usize, usize)> = HashSet::new();
+    let fits =
+        |occupied: &HashSet<(usize, usize)>, major: (usize, usize), minor: (usize, usize)| {
+            (major.0..major.1).all(|i| (minor.0..minor.1).all(|j| !occupied.contains(&(i, j))))
+        };
+    let mut placements: Vec<Option<(Tracks, Tracks)>> = vec![None; requests.len()];
+    let mut place = |occupied: &mut HashSet<(usize, usize)>,
+                     index: usize,
+                     major: (usize, usize),
+                     minor: (usize, usize)| {
+        for i in major.0..major.1 {
+            for j in minor.0..minor.1 {
+                occupied.insert((i, j));
+            }
+        }
+        placements[index] = Some((major, minor));
+    };
+
+    // 1. fixed on both axes
+    for (index, &((major, major_span), (minor, minor_span))) in requests.iter().enumerate() {
+        if let (Some(major), Some(minor)) = (major, minor) {
+            place(
+                &mut occupied,
+                index,
+                (major, major + major_span),
+                (minor, minor + minor_span),
+            );
+        }
+    }
+
+    // 2. locked to a major track: the first minor position that's free, after the previous item
+    // locked to the same track unless the packing is dense
+    let mut cursors: HashMap<usize, usize> = HashMap::new();
+    for (index, &((major, major_span), (minor, minor_span))) in requests.iter().enumerate() {
+        let (Some(major), None) = (major, minor) else {
+            continue;
+        };
+        let major = (major, major + major_span);
+        let mut minor = if dense {
+            0
+        } else {
+            cursors.get(&major.0).copied().unwrap_or(0)
+        };
+        while !fits(&occupied, major, (minor, minor + minor_span)) {
+            minor += 1;
+        }
+        place(&mut occupied, index, major, (minor, minor + minor_span));
+        cursors.insert(major.0, minor + minor_span);
+        minor_tracks = minor_tracks.max(minor + minor_span);
+    }
+
+    // 3. the others, from a cursor that moves through the grid unless the packing is dense
+    let mut cursor = (0, 0);
+    for (index, &((major, major_span), (minor, minor_span))) in requests.iter().enumerate() {
+        if major.is_some() {
+            continue;
+        }
+        if dense {
+            cursor = (0, 0);
+        }
+        match minor {
+            Some(minor) => {
+                if minor < cursor.1 {
+                    cursor.0 += 1;
+                }
+                cursor.1 = minor;
+                while !fits(
+                    &occupied,
+                    (cursor.0, cursor.0 + major_span),
+                    (minor, minor + minor_span),
+                ) {
+                    cursor.0 += 1;
+                }
+            }
+            None => loop {
+                if cursor.1 + minor_span > minor_tracks {
+                    cursor = (cursor.0 + 1, 0);
+                } else if fits(
+                    &occupied,
+                    (cursor.0, cursor.0 + major_span),
+                    (cursor.1, cursor.1 + minor_span),
+                ) {
+                    break;
+                } else {
+                    cursor.1 += 1;
+                }
+            },
+        }
+        let major = (cursor.0, cursor.0 + major_span);
+        let minor = (cursor.1, cursor.1 + minor_span);
+        place(&mut occupied, index, major, minor);
+        cursor.1 = minor.1;
+    }
+
+    placements
+        .into_iter()
+        .map(|placement| {
+            let (major, minor) = placement.unwrap();
+            let (rows, columns) = if column_flow {
+                (minor, major)
+            } else {
+                (major, minor)
+            };
+            Placement { rows, columns }
+        })
+        .collect()
+}
+
+// What an item needs on one axis: the tracks it spans, and its min-content and max-content
+// contributions, margins included.
+struct Contribution {
+    tracks: (usize, usize),
+    min_content: f32,
+    max_content: f32,
+}
+
+// Size the tracks of one axis to fit the items, and to fill the `available` size if it's
+// definite.
+// https://www.w3.org/TR/css-grid-1/#algo-track-sizing
+fn size_tracks(
+    sizes: &[TrackSize],
+    gap: f32,
+    available: Option<f32>,
+    contributions: &[Contribution],
+) -> Vec<f32> {
+    let count = sizes.len();
+    let gaps = |tracks: usize| gap * tracks.saturating_sub(1) as f32;
+    let mut base: Vec<f32> = sizes
+        .iter()
+        .map(|size| match size.min {
+            Breadth::Fixed(size) => size,
+            _ => 0.0,
+        })
+        .collect();
+    let mut limit: Vec<f32> = sizes
+        .iter()
+        .zip(&base)
+        .map(|(size, &base)| match size.max {
+            Breadth::Fixed(size) => size.max(base),
+            _ => f32::INFINITY,
+        })
+        .collect();
+
+    // 1. intrinsic sizes: from the items in a single track, then the items spanning more tracks,
+    // not counting the ones spanning flexible tracks
+    let mut spanning: Vec<&Contribution> = contributions.iter().collect();
+    spanning.sort_by_key(|contribution| contribution.tracks.1 - contribution.tracks.0);
+    for contribution in spanning {
+        let tracks = contribution.tracks.0..contribution.tracks.1;
+        if tracks.len() == 1 {
+            let i = tracks.start;
+            if matches!(sizes[i].min, Breadth::Auto) {
+                base[i] = base[i].max(contribution.min_content);
+            }
+            if matches!(sizes[i].max, Breadth::Auto) {
+                limit[i] = match limit[i].is_finite() {
+                    true => limit[i].max(contribution.max_content),
+                    false => contribution.max_content,
+                };
+            }
+            continue;
+        }
+        if tracks.clone().any(|i| sizes[i].flex_factor().is_some()) {
+            continue;
+        }
+        let intrinsic_min: Vec<usize> = tracks
+            .clone()
+            .filter(|&i| matches!(sizes[i].min, Breadth::Auto))
+            .collect();
+        let extra =
+            contribution.min_content - sum(tracks.clone().map(|i| base[i])) - gaps(tracks.len());
+        if extra > 0.0 && !intrinsic_min.is_empty() {
+            for &i in &intrinsic_min {
+                base[i] += extra / intrinsic_min.len() as f32;
+            }
+        }
+        let intrinsic_max: Vec<usize> = tracks
+            .clone()
+            .filter(|&i| matches!(sizes[i].max, Breadth::Auto))
+            .collect();
+        let finite_limit = |limit: &[f32], i: usize| match limit[i].is_finite() {
+            true => limit[i],
+            false => base[i],
+        };
+        let extra = contribution.max_content
+            - sum(tracks.clone().map(|i| finite_limit(&limit, i)))
+            - gaps(tracks.len());
+        if extra > 0.0 && !intrinsic_max.is_empty() {
+            for &i in &intrinsic_max {
+                limit[i] = finite_limit(&limit, i) + extra / intrinsic_max.len() as f32;
+            }
+        }
+    }
+    for i in 0..count {
+        if !limit[i].is_finite() || limit[i] < base[i] {
+            limit[i] = base[i];
+        }
+    }
+
+    // 2. maximize: grow the inflexible tracks to their limits, sharing the free space equally
+    let inflexible: Vec<usize> = (0..count)
+        .filter(|&i| sizes[i].flex_factor().is_none())
+        .collect();
+    match available {
+        Some(available) => {
+            let mut free = available - sum(base.iter().copied()) - gaps(count);
+            let mut growing: Vec<usize> = inflexible
+                .iter()
+                .copied()
+                .filter(|&i| limit[i] > base[i])
+                .collect();
+            while free > 0.0 && !growing.is_empty() {
+                let share = free / growing.len() as f32;
+                if growing.iter().all(|&i| limit[i] - base[i] >= share) {
+                    for &i in &growing {
+                        base[i] += share;
+                    }
+                    break;
+                }
+                // The tracks that can't take a full share reach their limits.
+                for &i in &growing {
+                    if limit[i] - base[i] < share {
+                        free -= limit[i] - base[i];
+                        base[i] = limit[i];
+                    }
+                }
+                growing.retain(|&i| limit[i] > base[i]);
+            }
+        }
+        // Under a max-content constraint, the free space is infinite.
+        None => {
+            for &i in &inflexible {
+                base[i] = limit[i];
+            }
+        }
+    }
+
+    // 3. flexible tracks: one `fr` is the leftover space divided by the flex factors
+    let flexible: Vec<usize> = (0..count)
+        .filter(|&i| sizes[i].flex_factor().is_some())
+        .collect();
+    if !flexible.is_empty() {
+        let factor = |i: usize| sizes[i].flex_factor().unwrap();
+        let fr = match available {
+            Some(available) => {
+                let mut flexible = flexible.clone();
+                loop {
+                    let leftover = available
+                        - gaps(count)
+                        - sum((0..count)
+                            .filter(|i| !flexible.contains(i))
+                            .map(|i| base[i]));
+                    // Flex factors summing to less than one only take that fraction of the space.
+                    let fr = leftover.max(0.0) / sum(flexible.iter().map(|&i| factor(i))).max(1.0);
+                    // A track whose base size is larger than its share is sized as inflexible.
+                    let tracks = flexible.len();
+                    flexible.retain(|&i| base[i] <= fr * factor(i));
+                    if flexible.len() == tracks {
+                        break fr;
+                    }
+                }
+            }
+            // Without a definite size, one `fr` is as large as the tracks and their items need.
+            None => {
+                let mut fr = flexible
+                    .iter()
+                    .map(|&i| base[i] / factor(i).max(1.0))
+                    .fold(0.0, f32::max);
+                for contribution in contributions {
+                    let (start, end) = contribution.tracks;
+                    if let (1, Some(factor)) = (end - start, sizes[start].flex_factor()) {
+                        fr = fr.max(contribution.max_content / factor.max(1.0));
+                    }
+                }
+                fr
+            }
+        };
+        for &i in &flexible {
+            base[i] = base[i].max(fr * factor(i));
+        }
+    }
+
+    // 4. stretch the `auto` tracks into the space that's left
+    if let Some(available) = available {
+        let free = available - sum(base.iter().copied()) - gaps(count);
+        let auto: Vec<usize> = (0..count)
+            .filter(|&i| matches!(sizes[i].max, Breadth::Auto))
+            .collect();
+        if free > 0.0 && !auto.is_empty() {
+            for &i in &auto {
+                base[i] += free / auto.len() as f32;
+            }
+        }
+    }
+    base
+}
+
+// The start of each track, and the size of a range of tracks with the gaps between them.
+fn track_positions(sizes: &[f32], gap: f32) -> Vec<f32> {
+    let mut position = 0.0;
+    sizes
+        .iter()
+        .map(|size| {
+            let start = position;
+            position += size + gap;
+            start
+        })
+        .collect()
+}
+
+fn area_size(sizes: &[f32], positions: &[f32], (start, end): (usize, usize)) -> f32 {
+    positions[end - 1] + sizes[end - 1] - positions[start]
+}
+
+// The offset of an item with `free` space left in its area, for an alignment keyword.
+fn align_offset(keyword: &str, free: f32) -> f32 {
+    match keyword {
+        "end" | "flex-end" | "self-end" | "right" => free,
+        "center" => free / 2.0,
+        _ => 0.0,
+    }
+}
+
+// The track sizes of `grid-auto-rows` or `grid-auto-columns`, repeated for the implicit tracks.
+fn implicit_tracks(value: Option<Value>, basis: Option<f32>) -> Vec<TrackSize> {
+    parse_track_list(value, basis, 0.0)
+        .filter(|tracks| !tracks.is_empty())
+        .unwrap_or(vec![TrackSize::AUTO])
+}
+
+impl<'a> LayoutBox<'a> {
+    pub(super) fn layout_grid(
+        &mut self,
+        container_width: Value,
+        container_height: Value,
+        context_constraints_width: Value,
+        fonts: &'a FontRegistry,
+    ) {
+        let auto = Value::Keyword("auto".to_string());
+
+        // 1. self: width, edges and height -> (specific or filling the container: definite, auto)
+        let (_, self_as_context_constraints_width, is_self_no_filled_auto) =
+            self.resolve_width(&container_width, &context_constraints_width);
+        self.resolve_edges();
+        let self_as_container_height = self.resolve_height(&container_height);
+        let definite_width = (!is_self_no_filled_auto).then_some(self.dimensions.inner.width);
+        let definite_height = match self_as_container_height {
+            Value::Length(height, _) => Some(height),
+            _ => None,
+        };
+
+        // 2. explicit grid: tracks -> (`grid-template-*`, named areas)
+        let style = self.get_style_node();
+        let row_gap = style.value("row-gap").map_or(0.0, |gap| gap.to_px());
+        let column_gap = style.value("column-gap").map_or(0.0, |gap| gap.to_px());
+        let areas = parse_areas(style.value("grid-template-areas"));
+        let mut columns = parse_track_list(
+            style.value("grid-template-columns"),
+            definite_width,
+            column_gap,
+        )
+        .unwrap_or_default();
+        let mut rows =
+            parse_track_list(style.value("grid-template-rows"), definite_height, row_gap)
+                .unwrap_or_default();
+        let auto_columns = implicit_tracks(style.value("grid-auto-columns"), definite_width);
+        let auto_rows = implicit_tracks(style.value("grid-auto-rows"), definite_height);
+        let extend = |tracks: &mut Vec<TrackSize>, count: usize, implicit: &[TrackSize]| {
+            for i in tracks.len()..count {
+                tracks.push(implicit[i % implicit.len()]);
+            }
+        };
+        let area_rows = areas.values().map(|area| area.rows.1).max().unwrap_or(0);
+        let area_columns = areas.values().map(|area| area.columns.1).max().unwrap_or(0);
+        extend(&mut rows, area_rows, &auto_rows);
+        extend(&mut columns, area_columns, &auto_columns);
+
+        // 3. placement: items -> (fixed lines, named areas, auto-placed in `order`)
+        let flow = match style.value("grid-auto-flow") {
+            Some(Value::List(keywords)) => keywords,
+            Some(keyword) => vec![keyword],
+            None => Vec::new(),
+        };
+        let has_flow = |name: &str| {
+            flow.iter()
+                .any(|keyword| *keyword == Value::Keyword(name.to_string()))
+        };
+        let order = self.children_in_order();
+        let requests: Vec<(Span, Span)> = order
+            .iter()
+            .map(|&index| match self.children[index].item_style() {
+                Some(style) => {
+                    let (start, end) = placement_lines(style, "row");
+                    let row = resolve_lines(start, end, &areas, true, rows.len());
+                    let (start, end) = placement_lines(style, "column");
+                    let column = resolve_lines(start, end, &areas, false, columns.len());
+                    (row, column)
+                }
+                None => ((None, 1), (None, 1)),
+            })
+            .collect();
+        let placements = place_items(
+            &requests,
+            columns.len(),
+            rows.len(),
+            has_flow("column"),
+            has_flow("dense"),
+        );
+        let row_count = placements.iter().map(|p| p.rows.1).max().unwrap_or(0);
+        let column_count = placements.iter().map(|p| p.columns.1).max().unwrap_or(0);
+        extend(&mut rows, row_count, &auto_rows);
+        extend(&mut columns, column_count, &auto_columns);
+
+        // 4. columns: widths -> (by the items' min-content and max-content widths)
+        let mut contributions = Vec::new();
+        for (&index, placement) in order.iter().zip(&placements) {
+            let child = &mut self.children[index];
+            child.imposed_size = ImposedSize::default();
+            let mut width = |constraint: f32| {
+                child.layout_item(&auto, &auto, &Value::Length(constraint, Unit::Px), fonts);
+                child.axis_margin_box_size(true)
+            };
+            contributions.push(Contribution {
+                tracks: placement.columns,
+                min_content: width(0.0),
+                max_content: width(f32::INFINITY),
+            });
+        }
+        let width = match definite_width {
+            Some(width) => width,
+            None => {
+                let column_sizes = size_tracks(&columns, column_gap, None, &contributions);
+                let width = sum(column_sizes.into_iter())
+                    + column_gap * columns.len().saturating_sub(1) as f32;
+                self.clamp_width(width.min(self_as_context_constraints_width.to_px()))
+            }
+        };
+        let column_sizes = size_tracks(&columns, column_gap, Some(width), &contributions);
+        let column_positions = track_positions(&column_sizes, column_gap);
+
+        // 5. rows: heights -> (by the items' heights in their columns)
+        let mut contributions = Vec::new();
+        for (&index, placement) in order.iter().zip(&placements) {
+            let area_width = area_size(&column_sizes, &column_positions, placement.columns);
+            let child = &mut self.children[index];
+            let stretched = child.self_alignment("justify-self", style, "justify-items")
+                == "stretch"
+                && child.item_style().is_none_or(|_| child.is_width_auto())
+                && child.auto_margin_count(true) == 0;
+            child.imposed_size.width =
+                stretched.then(|| child.clamp_axis(area_width - child.axis_edges(true), true));
+            child.layout_item(&auto, &auto, &Value::Length(area_width, Unit::Px), fonts);
+            let height = child.axis_margin_box_size(false);
+            contributions.push(Contribution {
+                tracks: placement.rows,
+                min_content: height,
+                max_content: height,
+            });
+        }
+        let height = match definite_height {
+            Some(height) => height,
+            None => {
+                let row_sizes = size_tracks(&rows, row_gap, None, &contributions);
+                let height =
+                    sum(row_sizes.into_iter()) + row_gap * rows.len().saturating_sub(1) as f32;
+                self.clamp_height(height)
+            }
+        };
+        let row_sizes = size_tracks(&rows, row_gap, Some(height), &contributions);
+        let row_positions = track_positions(&row_sizes, row_gap);
+
+        // 6. fixing children: stretch and align in their areas
+        for (&index, placement) in order.iter().zip(&placements) {
+            let area_width = area_size(&column_sizes, &column_positions, placement.columns);
+            let area_height = area_size(&row_sizes, &row_positions, placement.rows);
+            let child = &mut self.children[index];
+            let stretched = child.self_alignment("align-self", style, "align-items") == "stretch"
+                && child.item_style().is_none_or(|_| {
+                    child
+                        .specified_height(&Value::Keyword("auto".to_string()))
+                        .is_auto()
+                })
+                && child.auto_margin_count(false) == 0;
+            if stretched {
+                child.imposed_size.height =
+                    Some(child.clamp_axis(area_height - child.axis_edges(false), false));
+            }
+            child.layout_item(
+                &auto,
+                &Value::Length(area_height, Unit::Px),
+                &Value::Length(area_width, Unit::Px),
+                fonts,
+            );
+
+            let mut offsets = [0.0; 2];
+            for (horizontal, offset) in [true, false].into_iter().zip(&mut offsets) {
+                let area = if horizontal { area_width } else { area_height };
+                let free = area - child.axis_margin_box_size(horizontal);
+                let auto_margins = child.auto_margin_count(horizontal);
+                if auto_margins > 0 {
+                    child.fill_auto_margins(horizontal, free.max(0.0) / auto_margins as f32);
+                } else if horizontal {
+                    *offset = align_offset(
+                        &child.self_alignment("justify-self", style, "justify-items"),
+                        free,
+                    );
+                } else {
+                    *offset = align_offset(
+                        &child.self_alignment("align-self", style, "align-items"),
+                        free,
+                    );
+                }
+            }
+            child.dimensions.box_offset.left = column_positions[placement.columns.0] + offsets[0];
+            child.dimensions.box_offset.top = row_positions[placement.rows.0] + offsets[1];
+        }
+        self.dimensions.inner.width = width;
+        self.dimensions.inner.height = height;
+    }
+
+    // The `justify-self` or `align-self` of an item, with `auto` taking the container's
+    // `justify-items` or `align-items`, which are `stretch` by default.
+    fn self_alignment(&self, property: &str, container: &StyledNode, fallback: &str) -> String {
+        match self
+            .item_style()
+            .map(|style| keyword(style, property, "auto"))
+        {
+            Some(alignment) if alignment != "auto" => alignment,
+            _ => match keyword(container, fallback, "stretch").as_str() {
+                "normal" | "legacy" => "stretch".to_string(),
+                alignment => alignment.to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{css, html};
+    use super::super::tests::{layout, rect};
+    use super::*;
+
+    #[test]
+    fn test_repeat_limit() {
+        let tracks = |repeat: &str| {
+            let css = css::parse(format!("p {{ grid-template-columns: {repeat}; }}"));
+            let value = css.rules[0].declarations[0].value.clone();
+            parse_track_list(Some(value), Some(400.0), 0.0).map(|tracks| tracks.len())
+        };
+        assert_eq!(tracks("repeat(3, 10px)"), Some(3));
+        assert_eq!(tracks("repeat(1000000000, 10px)"), Some(1000));
+        assert_eq!(tracks("repeat(auto-fill, 0.001px)"), Some(1000));
+    }
+
+    #[test]
+    fn test_line_limit() {
+        let lines = |placement: &str| {
+            let css = css::parse(format!("p {{ grid-column: {placement}; }}"));
+            GridLine::parse(&css.rules[0].declarations[0].value)
+        };
+        assert!(matches!(lines("3"), GridLine::Line(3)));
+        assert!(matches!(lines("100000000"), GridLine::Line(1000)));
+        assert!(matches!(lines("-100000000"), GridLine::Line(-1000)));
+        assert!(matches!(lines("span 100000000"), GridLine::Span(1000)));
+
+        // An item placed that far out still lays out quickly.
+        let root = html::parse(
+            "<div class=\"grid\"><p class=\"far\"></p><p class=\"wide\"></p></div>".to_string(),
+        );
+        let css = css::parse(
+            ".grid { display: grid; grid-template-columns: 10px; }
+.far { grid-column: 100000000; grid-row: -100000000; }
+.wide { grid-column: span 100000000; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let layout_tree = layout(&styled_tree, &fonts);
+        assert_eq!(layout_tree.children.len(), 2);
+    }
+
+    #[test]
+    fn test_grid_tracks_and_placement() {
+        let root = html::parse(
+            "<div><div class=\"grid\"><p class=\"wide\"></p><p class=\"tall\"></p><p></p><p></p><p class=\"short\"></p></div></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div { display: block; }
+.grid { display: grid; width: 320px; grid-template-columns: 100px repeat(2, 1fr); grid-template-rows: 50px minmax(20px, auto); gap: 10px; }
+.wide { grid-column: span 2; }
+.tall { grid-row: 1 / 3; grid-column: -2; }
+.short { height: 10px; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let layout_tree = layout(&styled_tree, &fonts);
+
+        let grid = &layout_tree.children[0];
+        let [wide, tall, first, second, short] = &grid.children[..] else {
+            panic!("expected five items");
+        };
+        // The fixed item is placed first, and the others flow around it, into an implicit row.
+        assert_eq!(rect(wide), (0.0, 0.0, 210.0, 50.0));
+        assert_eq!(rect(tall), (220.0, 0.0, 100.0, 80.0));
+        assert_eq!(rect(first), (0.0, 60.0, 100.0, 20.0));
+        assert_eq!(rect(second), (110.0, 60.0, 100.0, 20.0));
+        assert_eq!(rect(short), (0.0, 90.0, 100.0, 10.0));
+        assert_eq!(grid.dimensions.inner.height, 100.0);
+    }
+
+    #[test]
+    fn test_grid_areas_and_alignment() {
+        let root = html::parse(
+            "<div><div class=\"areas\"><p class=\"main\"></p><p class=\"side\"></p><p class=\"head\"></p></div><div class=\"fill\"><p></p><p></p><p></p><p></p></div><span class=\"inline\"><p></p><p class=\"fixed\"></p></span></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div { display: block; }
+.areas { display: grid; width: 200px; grid-template-columns: 50px 1fr; grid-template-rows: 30px 40px; grid-template-areas: \"head head\" \"side main\"; justify-items: center; }
+.head { grid-area: head; }
+.side { grid-area: side; width: 20px; }
+.main { grid-area: main; height: 10px; align-self: end; justify-self: stretch; }
+.fill { display: grid; width: 200px; grid-template-columns: repeat(auto-fill, 60px); column-gap: 10px; grid-auto-rows: 15px; }
+.inline { display: inline-grid; grid-template-columns: 30px auto; column-gap: 5px; }
+.fixed { width: 40px; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let layout_tree = layout(&styled_tree, &fonts);
+
+        let [main, side, head] = &layout_tree.children[0].children[..] else {
+            panic!("expected three items");
+        };
+        assert_eq!(rect(head), (100.0, 0.0, 0.0, 30.0));
+        assert_eq!(rect(side), (15.0, 30.0, 20.0, 40.0));
+        assert_eq!(rect(main), (50.0, 60.0, 150.0, 10.0));
+
+        // Three columns fit, so the fourth item wraps.
+        let fill = &layout_tree.children[1];
+        assert_eq!(rect(&fill.children[2]), (140.0, 70.0, 60.0, 15.0));
+        assert_eq!(rect(&fill.children[3]), (0.0, 85.0, 60.0, 15.0));
+
+        // An inline grid is as wide as its tracks.
+        let inline = &layout_tree.children[2].children[0];
+        assert_eq!(inline.dimensions.inner.width, 75.0);
+        assert_eq!(rect(&inline.children[1]), (35.0, 100.0, 40.0, 0.0));
+    }
+}
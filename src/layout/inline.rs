@@ -0,0 +1,976 @@
+//! Inline layout: words, inline boxes and atomic inlines on line boxes, following CSS 2.2 § 9.4.2
+//! and § 10.8.
+//!
+//! https://www.w3.org/TR/CSS22/visuren.html#inline-formatting
+
+use super::*;
+use unicode_bidi::{BidiInfo, Level};
+
+// What the boxes in an inline formatting context put on its lines, in order. Boxes are found by
+// their path in the anonymous block.
+pub(super) enum InlineItem<'a> {
+    // A collapsible space, as wide as given.
+    Space(f32),
+    Word(TextRun<'a>),
+    // The start and end of an inline box whose content flows on the lines, with its edges.
+    Start(Vec<usize>, &'a StyledNode<'a>, Dimensions),
+    End,
+    // A box that's placed as a whole: an atomic inline, a float or an absolutely positioned box.
+    Box(Vec<usize>),
+}
+
+// An inline box that's started and not ended yet on the lines being laid out.
+struct OpenInline {
+    path: Vec<usize>,
+    edges: Dimensions,
+    // How far the content area reaches above and below the baseline, and the inline box with
+    // its leading.
+    content: (f32, f32),
+    leading: (f32, f32),
+    // Every fragment gets the edges of the box (`box-decoration-break: clone`).
+    clone: bool,
+    // The fragment on the current line, in the elements, once there's one.
+    element: Option<usize>,
+    // Whether the box has a fragment on an earlier line.
+    broken: bool,
+}
+
+impl OpenInline {
+    fn new(path: Vec<usize>, style: &StyledNode, edges: Dimensions, fonts: &FontRegistry) -> Self {
+        let font = style.first_font(fonts);
+        let metrics = font.map_or(FontMetrics::default(), |font| {
+            font.metrics(style.font_size())
+        });
+        OpenInline {
+            path,
+            edges,
+            content: (metrics.ascent, metrics.descent),
+            leading: inline_box_metrics(style, font),
+            clone: keyword(style, "box-decoration-break", "slice") == "clone",
+            element: None,
+            broken: false,
+        }
+    }
+
+    // Whether the next fragment has the start edges of the box: only the first one does, unless
+    // they're cloned.
+    fn has_start(&self) -> bool {
+        !self.broken || self.clone
+    }
+
+    // The width of the margin, border and padding before the content of the next fragment.
+    fn start_edges(&self) -> f32 {
+        let d = &self.edges;
+        match self.has_start() {
+            true => d.margin.left + d.border.left + d.padding.left,
+            false => 0.0,
+        }
+    }
+
+    // The width of the border and padding after the content of a fragment with the end edges.
+    fn end_edges(&self) -> f32 {
+        self.edges.padding.right + self.edges.border.right
+    }
+
+    // Start a fragment at `left`, on a line whose baseline is at `baseline` for now.
+    fn fragment(&mut self, left: f32, baseline: f32) -> InlineFragment {
+        let d = &self.edges;
+        let first = self.has_start();
+        self.broken = true;
+        InlineFragment {
+            path: self.path.clone(),
+            left: left + if first { d.margin.left } else { 0.0 },
+            top: baseline - self.content.0 - d.padding.top - d.border.top,
+            width: 0.0,
+            height: self.content.0
+                + self.content.1
+                + d.padding.top
+                + d.padding.bottom
+                + d.border.top
+                + d.border.bottom,
+            first,
+            last: false,
+        }
+    }
+
+    // End the fragment on the current line where the content stops, at `right`. The `last`
+    // fragment has the end edges of the box.
+    fn close(&mut self, elements: &mut [InlineFormattingContextRun], right: f32, last: bool) {
+        let Some(element) = self.element.take() else {
+            return;
+        };
+        if let InlineFormattingContextRun::Fragment(fragment) = &mut elements[element] {
+            fragment.last = last || self.clone;
+            fragment.width = right - fragment.left;
+            if fragment.last {
+                fragment.width += self.end_edges();
+            }
+        }
+    }
+}
+
+// The lines of an anonymous block while they're laid out. Positions are relative to its content
+// box, which is at `origin` in the block formatting context of `floats`.
+struct Lines<'a, 'c> {
+    floats: &'c mut FloatContext,
+    origin: (f32, f32),
+    extent: (f32, f32),
+    text_align: String,
+    // Every line starts with a strut: a zero-width inline box in the font of the block.
+    // https://www.w3.org/TR/CSS22/visudet.html#strut
+    strut: (f32, f32),
+    // How far the current line is indented. Only the first line of a block is.
+    // https://www.w3.org/TR/css-text-3/#text-indent-property
+    indent: f32,
+
+    elements: Vec<InlineFormattingContextRun<'a>>,
+    // Whether each element has a collapsed space before it on its line, where `justify`
+    // stretches the line.
+    spaced: Vec<bool>,
+    // The bidi embedding level of each element, and where it is on its line and how wide, for
+    // reordering the line. The level of what's placed next.
+    levels: Vec<Level>,
+    spans: Vec<(f32, f32)>,
+    level: Level,
+    // Atoms to move over and down once the lines are done.
+    shifted_atoms: Vec<(Vec<usize>, f32, f32)>,
+    open: Vec<OpenInline>,
+    max_width: f32,
+    // Distance from the top of the block to the baseline of the last line.
+    baseline: Option<f32>,
+
+    // The current line: its first element and its top, where it starts from the left of the
+    // content box and how long it is beside the floats, how much of it is used, and how far it
+    // reaches above and below its baseline.
+    start: usize,
+    top: f32,
+    left: f32,
+    width: f32,
+    used: f32,
+    ascent: f32,
+    descent: f32,
+    // Width of a collapsed space still to be inserted before the next item on the line.
+    pending_space: f32,
+    // Whether the line can wrap before the next word: after a space or an atom, but not inside a
+    // word split into runs.
+    wrap: bool,
+}
+
+impl<'a, 'c> Lines<'a, 'c> {
+    fn new(
+        ifc: &InlineFormattingContext<'a>,
+        rtl: bool,
+        width: f32,
+        floats: &'c mut FloatContext,
+        origin: (f32, f32),
+        fonts: &FontRegistry,
+    ) -> Self {
+        let mut text_align = ifc.style.map_or("start".to_string(), |style| {
+            keyword(style, "text-align", "start")
+        });
+        // The start of a right-to-left paragraph is on the right.
+        if rtl {
+            text_align = match text_align.as_str() {
+                "start" => "right".to_string(),
+                "end" => "left".to_string(),
+                _ => text_align,
+            };
+        }
+        let strut = ifc.style.map_or((0.0, 0.0), |style| {
+            inline_box_metrics(style, style.first_font(fonts))
+        });
+        let indent = match ifc.style.and_then(|style| style.value("text-indent")) {
+            Some(_) if !ifc.first_line => 0.0,
+            Some(Value::Percentage(percentage)) if width.is_finite() => width * percentage / 100.0,
+            Some(value) if value.is_specific_length() => value.to_px(),
+            _ => 0.0,
+        };
+        Lines {
+            floats,
+            origin,
+            extent: (origin.0, origin.0 + width),
+            text_align,
+            strut,
+            indent,
+            elements: Vec::new(),
+            spaced: Vec::new(),
+            levels: Vec::new(),
+            spans: Vec::new(),
+            level: Level::ltr(),
+            shifted_atoms: Vec::new(),
+            open: Vec::new(),
+            max_width: 0.0,
+            baseline: None,
+            start: 0,
+            top: 0.0,
+            left: indent,
+            width: width - indent,
+            used: 0.0,
+            ascent: f32::NEG_INFINITY,
+            descent: f32::NEG_INFINITY,
+            pending_space: 0.0,
+            wrap: false,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.start == self.elements.len()
+    }
+
+    fn height(&self) -> f32 {
+        match self.is_empty() {
+            true => 0.0,
+            false => self.ascent + self.descent,
+        }
+    }
+
+    // The start edges of the open inline boxes that have no fragment on the line yet, which go
+    // before what comes next.
+    fn start_edges(&self) -> f32 {
+        sum(self
+            .open
+            .iter()
+            .filter(|open| open.element.is_none())
+            .map(OpenInline::start_edges))
+    }
+
+    // Whether `width` more fits on the line.
+    fn fits(&self, width: f32) -> bool {
+        self.is_empty() || self.used + self.pending_space + self.start_edges() + width <= self.width
+    }
+
+    // Start a new line for something of `size`, if the current one is empty: beside the floats,
+    // or below those it can't fit beside.
+    fn make_room(&mut self, (width, height): (f32, f32)) {
+        if !self.is_empty() {
+            return;
+        }
+        let (top, left, right) = self.floats.fit(
+            self.origin.1 + self.top,
+            (self.start_edges() + width, height),
+            self.extent,
+        );
+        self.top = top - self.origin.1;
+        self.left = left - self.origin.0 + self.indent;
+        self.width = right - left - self.indent;
+        // Spaces at the start of a line are removed.
+        self.pending_space = 0.0;
+        self.ascent = self.ascent.max(self.strut.0);
+        self.descent = self.descent.max(self.strut.1);
+    }
+
+    // Where the next item goes on the line, after the pending space. The open inline boxes that
+    // don't have a fragment on the line yet start one there, and the item goes after their start
+    // edges. Returns where it goes, and whether there's a space before it.
+    fn advance(&mut self) -> (f32, bool) {
+        let mut left = self.left + self.used + self.pending_space;
+        let mut space = self.pending_space > 0.0;
+        self.pending_space = 0.0;
+        for open in self.open.iter_mut().filter(|open| open.element.is_none()) {
+            let edges = open.start_edges();
+            let fragment = open.fragment(left, self.top);
+            open.element = Some(self.elements.len());
+            self.elements
+                .push(InlineFormattingContextRun::Fragment(fragment));
+            self.spaced.push(space);
+            self.levels.push(self.level);
+            self.spans.push((left, 0.0));
+            space = false;
+            left += edges;
+            self.ascent = self.ascent.max(open.leading.0);
+            self.descent = self.descent.max(open.leading.1);
+        }
+        (left, space)
+    }
+
+    // Put an element at `left` on the line, reaching `ascent` and `descent` around its baseline.
+    fn push(
+        &mut self,
+        element: InlineFormattingContextRun<'a>,
+        (left, width): (f32, f32),
+        (ascent, descent): (f32, f32),
+        space: bool,
+    ) {
+        self.used = left + width - self.left;
+        self.ascent = self.ascent.max(ascent);
+        self.descent = self.descent.max(descent);
+        self.elements.push(element);
+        self.spaced.push(space);
+        self.levels.push(self.level);
+        self.spans.push((left, width));
+    }
+
+    // Place a word, or the first run of a word split into runs that are `width` wide together,
+    // which go on the same line.
+    fn place_word(&mut self, mut run: TextRun<'a>, width: f32) {
+        if self.wrap && !self.fits(width) {
+            // wrap at the space before this word
+            self.break_line();
+        }
+        self.wrap = false;
+        self.make_room((width, run.height));
+        let (left, space) = self.advance();
+        run.left = left;
+        // From the baseline for now, which is placed when the line is done.
+        run.top = self.top - run.ascent;
+        let extent = (run.ascent, run.height - run.ascent);
+        let width = run.width;
+        self.push(
+            InlineFormattingContextRun::TextRun(run),
+            (left, width),
+            extent,
+            space,
+        );
+    }
+
+    // Place the laid out atomic inline at `path`, with its baseline on the line's.
+    fn place_atom(&mut self, path: Vec<usize>, child: &mut LayoutBox, fonts: &FontRegistry) {
+        let size = (
+            child.axis_margin_box_size(true),
+            child.axis_margin_box_size(false),
+        );
+        if !self.fits(size.0) {
+            // wrap
+            self.break_line();
+        }
+        self.wrap = true;
+        self.make_room(size);
+        let (left, space) = self.advance();
+        // 3. width -> (auto by children sum but limit by context)
+        child.dimensions.box_offset.left = left;
+        // 4. height -> lines Σ (max by children), from the baseline for now
+        let ascent = child.atom_baseline(fonts);
+        child.dimensions.box_offset.top = self.top - ascent;
+        self.push(
+            InlineFormattingContextRun::Atom(path),
+            (left, size.0),
+            (ascent, size.1 - ascent),
+            space,
+        );
+    }
+
+    // Place a laid out float on the current line if it fits beside what's there already,
+    // otherwise below it.
+    fn place_float(&mut self, child: &mut LayoutBox) {
+        let line_top = self.origin.1 + self.top;
+        let on_line = self.is_empty() || self.used + child.axis_margin_box_size(true) <= self.width;
+        let top = match on_line {
+            true => line_top,
+            false => line_top + self.height(),
+        };
+        child.place_float(self.floats, top, self.origin, self.extent);
+        if on_line && !self.is_empty() {
+            // The line gets shorter, and what's on it moves over.
+            let (left, right) = self.floats.band(line_top, self.height(), self.extent);
+            let shift = left - self.origin.0 + self.indent - self.left;
+            for element in self.start..self.elements.len() {
+                self.shift(element, (shift, 0.0));
+            }
+            self.left += shift;
+            self.width = right - left - self.indent;
+        }
+    }
+
+    fn start_inline(
+        &mut self,
+        path: Vec<usize>,
+        style: &StyledNode,
+        edges: Dimensions,
+        fonts: &FontRegistry,
+    ) {
+        self.open.push(OpenInline::new(path, style, edges, fonts));
+    }
+
+    fn end_inline(&mut self) {
+        let Some(open) = self.open.last() else {
+            return;
+        };
+        let end = open.end_edges() + open.edges.margin.right;
+        if open.element.is_none() {
+            // An empty box still has a fragment where it is.
+            if !self.fits(end) {
+                self.break_line();
+            }
+            self.make_room((end, 0.0));
+            let (left, _) = self.advance();
+            self.used = left - self.left;
+        }
+        let mut open = self.open.pop().unwrap();
+        open.close(&mut self.elements, self.left + self.used, true);
+        self.used += end;
+    }
+
+    fn shift(&mut self, element: usize, (left, top): (f32, f32)) {
+        self.spans[element].0 += left;
+        match &mut self.elements[element] {
+            InlineFormattingContextRun::TextRun(run) => {
+                run.left += left;
+                run.top += top;
+            }
+            InlineFormattingContextRun::Fragment(fragment) => {
+                fragment.left += left;
+                fragment.top += top;
+            }
+            InlineFormattingContextRun::Atom(path) => {
+                self.shifted_atoms.push((path.clone(), left, top))
+            }
+        }
+    }
+
+    // End the current line, and start the next one below it.
+    fn break_line(&mut self) {
+        let right = self.left + self.used;
+        for open in &mut self.open {
+            open.close(&mut self.elements, right, false);
+        }
+        self.finish_line(false);
+        self.top += self.height();
+        self.max_width = self.extent.1 - self.extent.0;
+        self.start = self.elements.len();
+        self.used = 0.0;
+        self.indent = 0.0;
+        self.pending_space = 0.0;
+        (self.ascent, self.descent) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+    }
+
+    // Move the elements of the current line down to its baseline, and over to where `text-align`
+    // puts them in the space left at its end. `justify` stretches the spaces between them,
+    // unless the line is the `last` one.
+    // https://www.w3.org/TR/css-text-3/#text-align-property
+    fn finish_line(&mut self, last: bool) {
+        if self.is_empty() {
+            return;
+        }
+        self.baseline = Some(self.top + self.ascent);
+        if self.levels[self.start..]
+            .iter()
+            .any(|level| level.number() > 0)
+        {
+            self.reorder();
+        }
+        let free = (self.width - self.used).max(0.0);
+        let gaps = self.spaced[self.start..]
+            .iter()
+            .filter(|&&spaced| spaced)
+            .count();
+        let (mut shift, gap) = match self.text_align.as_str() {
+            _ if !free.is_finite() => (0.0, 0.0),
+            "right" | "end" => (free, 0.0),
+            "center" => (free / 2.0, 0.0),
+            "justify" if !last && gaps > 0 => (0.0, free / gaps as f32),
+            _ => (0.0, 0.0),
+        };
+        for element in self.start..self.elements.len() {
+            if self.spaced[element] {
+                shift += gap;
+            }
+            self.shift(element, (shift, self.ascent));
+        }
+    }
+
+    // Put the elements of the current line in visual order: runs of words and atoms at higher
+    // levels are reversed, as the bidi algorithm reorders them, and the fragments of inline boxes
+    // stretch over what ends up in them. The room between words and atoms, for the spaces and
+    // edges there, is reordered with them, at the lower level of the two.
+    // https://www.unicode.org/reports/tr9/#L2
+    fn reorder(&mut self) {
+        let units: Vec<usize> = (self.start..self.elements.len())
+            .filter(|&element| element_path(&self.elements[element]).is_some())
+            .collect();
+        // The room before each unit, and the unit.
+        let mut pieces = Vec::with_capacity(units.len() * 2);
+        let mut levels = Vec::with_capacity(units.len() * 2);
+        let mut right = self.left;
+        for (i, &unit) in units.iter().enumerate() {
+            let (left, width) = self.spans[unit];
+            let level = self.levels[unit];
+            let before = i.checked_sub(1).map_or(level, |i| self.levels[units[i]]);
+            pieces.extend([left - right, width]);
+            levels.extend([before.min(level), level]);
+            right = left + width;
+        }
+        let mut x = self.left;
+        let mut lefts = vec![0.0; units.len()];
+        for i in BidiInfo::reorder_visual(&levels) {
+            if i % 2 == 1 {
+                lefts[i / 2] = x;
+            }
+            x += pieces[i];
+        }
+
+        // The edges of a fragment stay where they are around what's in it.
+        for element in self.start..self.elements.len() {
+            let InlineFormattingContextRun::Fragment(fragment) = &self.elements[element] else {
+                continue;
+            };
+            let mut old = (f32::INFINITY, f32::NEG_INFINITY);
+            let mut new = old;
+            for (i, &unit) in units.iter().enumerate() {
+                let path = element_path(&self.elements[unit]).unwrap();
+                if path.starts_with(&fragment.path) {
+                    let (left, width) = self.spans[unit];
+                    old = (old.0.min(left), old.1.max(left + width));
+                    new = (new.0.min(lefts[i]), new.1.max(lefts[i] + width));
+                }
+            }
+            if !old.0.is_finite() {
+                continue;
+            }
+            let lead = old.0 - fragment.left;
+            let trail = fragment.left + fragment.width - old.1;
+            let shift = new.0 - lead - fragment.left;
+            if let InlineFormattingContextRun::Fragment(fragment) = &mut self.elements[element] {
+                fragment.width = new.1 - new.0 + lead + trail;
+            }
+            self.shift(element, (shift, 0.0));
+        }
+        for (i, &unit) in units.iter().enumerate() {
+            let shift = lefts[i] - self.spans[unit].0;
+            self.shift(unit, (shift, 0.0));
+        }
+    }
+
+    // Finish the last line. Returns the width and height the lines take up.
+    fn finish(&mut self) -> (f32, f32) {
+        self.finish_line(true);
+        let height = self.top + self.height();
+        (self.max_width.max(self.left + self.used), height)
+    }
+}
+
+// The path of the box of a word or atom in the anonymous block.
+fn element_path<'b>(element: &'b InlineFormattingContextRun) -> Option<&'b [usize]> {
+    match element {
+        InlineFormattingContextRun::TextRun(run) => Some(&run.path),
+        InlineFormattingContextRun::Atom(path) => Some(path),
+        InlineFormattingContextRun::Fragment(_) => None,
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    // Lay out the lines of an anonymous block, whose content box is at `origin` in the block
+    // formatting context of `floats`. Lines are shortened to go beside the floats.
+    pub(super) fn layout_anonymous(
+        &mut self,
+        _container_width: Value,
+        container_height: Value,
+        context_constraints_width: Value,
+        fonts: &'a FontRegistry,
+        floats: &mut FloatContext,
+        origin: (f32, f32),
+    ) {
+        // 1. width -> (empty auto)
+
+        let self_as_container_width = Value::Keyword("auto".to_string());
+        let ifc_constraints_width = context_constraints_width.to_px();
+
+        // 2. line break recursive -> horizontal(inline run, inline block)
+
+        let BoxType::AnonymousBlock(ref ifc) = self.box_type else {
+            unreachable!()
+        };
+        let mut items = Vec::new();
+        for (i, child) in self.children.iter_mut().enumerate() {
+            child.inline_items(&mut vec![i], fonts, &mut items);
+        }
+        let (items, rtl) = bidi::resolve_levels(ifc.style, items);
+        let mut lines = Lines::new(ifc, rtl, ifc_constraints_width, floats, origin, fonts);
+
+        // What each item adds to the line before the next place it can wrap: a word its width,
+        // and an inline box its edges. Where the line wraps inside boxes with
+        // `box-decoration-break: clone`, they get their end edges there too.
+        let mut widths = vec![0.0; items.len()];
+        let mut open = Vec::new();
+        for (i, (item, _)) in items.iter().enumerate() {
+            widths[i] = match item {
+                InlineItem::Word(run) => run.width,
+                InlineItem::Start(_, style, d) => {
+                    let clone = keyword(style, "box-decoration-break", "slice") == "clone";
+                    open.push((d.padding.right + d.border.right, d.margin.right, clone));
+                    d.margin.left + d.border.left + d.padding.left
+                }
+                InlineItem::End => open.pop().map_or(0.0, |(end, margin, _)| end + margin),
+                InlineItem::Space(_) | InlineItem::Box(_) => {
+                    sum(open.iter().filter(|open| open.2).map(|open| open.0))
+                }
+            };
+        }
+        // The width of the runs from each word on, to the next place the line can wrap.
+        let mut unbroken = vec![0.0; items.len()];
+        let mut width = 0.0;
+        for (i, (item, _)) in items.iter().enumerate().rev() {
+            width = match item {
+                InlineItem::Space(_) | InlineItem::Box(_) => widths[i],
+                _ => width + widths[i],
+            };
+            unbroken[i] = width;
+        }
+
+        for ((item, level), width) in items.into_iter().zip(unbroken) {
+            lines.level = level;
+            match item {
+                InlineItem::Space(width) => {
+                    lines.pending_space = lines.pending_space.max(width);
+                    lines.wrap = true;
+                }
+                InlineItem::Word(run) => lines.place_word(run, width),
+                InlineItem::Start(path, style, edges) => {
+                    lines.start_inline(path, style, edges, fonts)
+                }
+                InlineItem::End => lines.end_inline(),
+                InlineItem::Box(path) => {
+                    let child = self.descendant_mut(&path);
+                    if child.is_out_of_flow() {
+                        // The static position: where the box would go on the line.
+                        child.dimensions.box_offset.left =
+                            lines.left + lines.used + lines.pending_space;
+                        child.dimensions.box_offset.top = lines.top;
+                    } else if child.is_float() {
+                        child.layout_float(&container_height, ifc_constraints_width, fonts);
+                        lines.place_float(child);
+                    } else {
+                        // Anonymous blocks are skipped when resolving percentage heights.
+                        child.layout_box(
+                            self_as_container_width.clone(),
+                            container_height.clone(),
+                            context_constraints_width.clone(),
+                            fonts,
+                        );
+                        lines.place_atom(path, child, fonts);
+                    }
+                }
+            }
+        }
+
+        // final line
+        let (width, height) = lines.finish();
+        let (elements, baseline) = (lines.elements, lines.baseline);
+        for (path, left, top) in lines.shifted_atoms {
+            let offset = &mut self.descendant_mut(&path).dimensions.box_offset;
+            offset.left += left;
+            offset.top += top;
+        }
+
+        self.dimensions.inner.width = width;
+        self.dimensions.inner.height = height;
+
+        match &mut self.box_type {
+            BoxType::AnonymousBlock(ifc) => {
+                ifc.elements = elements;
+                ifc.baseline = baseline;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // Add what the box at `path` in an anonymous block puts on the lines to `items`. An inline
+    // box with content flows on the lines with it. Its own content box is where the anonymous
+    // block's is, so what's in it is placed relative to the lines too.
+    fn inline_items(
+        &mut self,
+        path: &mut Vec<usize>,
+        fonts: &'a FontRegistry,
+        items: &mut Vec<InlineItem<'a>>,
+    ) {
+        match self.box_type {
+            BoxType::InlineNode(style) if !self.children.is_empty() => {
+                self.resolve_edges();
+                let d = &mut self.dimensions;
+                if self.split.0 {
+                    (d.margin.left, d.border.left, d.padding.left) = (0.0, 0.0, 0.0);
+                }
+                if self.split.1 {
+                    (d.margin.right, d.border.right, d.padding.right) = (0.0, 0.0, 0.0);
+                }
+                d.box_offset.left = -d.inner.left;
+                d.box_offset.top = -d.inner.top;
+                items.push(InlineItem::Start(path.clone(), style, self.dimensions));
+                for (i, child) in self.children.iter_mut().enumerate() {
+                    path.push(i);
+                    child.inline_items(path, fonts, items);
+                    path.pop();
+                }
+                items.push(InlineItem::End);
+            }
+            _ if self.is_segmentable() => {
+                for (space_before, run) in self.text_runs(path, fonts) {
+                    if space_before {
+                        items.push(InlineItem::Space(run.space_width()));
+                    }
+                    items.push(InlineItem::Word(run));
+                }
+                if self.ends_with_space() {
+                    items.push(InlineItem::Space(self.space_width(fonts)));
+                }
+            }
+            _ => items.push(InlineItem::Box(path.clone())),
+        }
+    }
+
+    // Split an inline box around the block-level boxes in it, and in the inline boxes in it, into
+    // the blocks and pieces of the box that hold the inline content before, between and after
+    // them. The start edges of the box go on its first piece and the end edges on its last.
+    // https://www.w3.org/TR/CSS22/visuren.html#anonymous-block-level
+    pub(super) fn split_around_blocks(mut self) -> Vec<LayoutBox<'a>> {
+        if !matches!(self.box_type, BoxType::InlineNode(_)) {
+            return vec![self];
+        }
+        let mut pieces = vec![];
+        let mut piece = LayoutBox::new(self.box_type.clone());
+        for child in std::mem::take(&mut self.children) {
+            for child in child.split_around_blocks() {
+                if child.is_block_level() {
+                    let next = LayoutBox::new(self.box_type.clone());
+                    pieces.push(std::mem::replace(&mut piece, next));
+                    pieces.push(child);
+                } else {
+                    piece.children.push(child);
+                }
+            }
+        }
+        if pieces.is_empty() {
+            self.children = piece.children;
+            return vec![self];
+        }
+        pieces.push(piece);
+        let last = pieces.len() - 1;
+        for (i, piece) in pieces.iter_mut().enumerate().step_by(2) {
+            piece.split = (i > 0, i < last);
+        }
+        // Pieces with nothing in them are left out.
+        pieces.retain(|piece| piece.is_block_level() || !piece.children.is_empty());
+        pieces
+    }
+
+    // Distance from the top of the margin box of an atomic inline to its baseline: that of its
+    // last line box, or its bottom margin edge if it has none or doesn't show its overflow.
+    // https://www.w3.org/TR/CSS22/visudet.html#propdef-vertical-align
+    fn atom_baseline(&self, fonts: &FontRegistry) -> f32 {
+        let clips = !matches!(self.box_type, BoxType::InlineNode(_))
+            && keyword(self.get_style_node(), "overflow", "visible") != "visible";
+        match self.last_baseline(fonts) {
+            Some(baseline) if !clips => baseline,
+            _ => self.axis_margin_box_size(false),
+        }
+    }
+
+    // Distance from the top of the margin box to the baseline of the last line box inside.
+    fn last_baseline(&self, fonts: &FontRegistry) -> Option<f32> {
+        let baseline = match self.box_type {
+            BoxType::AnonymousBlock(ref ifc) => ifc.baseline,
+            // Text laid out on a single line.
+            _ if self.is_segmentable() => {
+                let style = self.get_style_node();
+                Some(inline_box_metrics(style, style.first_font(fonts)).0)
+            }
+            _ => self
+                .children
+                .iter()
+                .rev()
+                .filter(|child| !child.is_float() && !child.is_out_of_flow())
+                .find_map(|child| {
+                    let baseline = child.last_baseline(fonts)?;
+                    Some(child.dimensions.box_offset.top + baseline)
+                }),
+        };
+        baseline.map(|baseline| self.dimensions.inner.top + baseline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{layout_with_fonts, text_runs};
+    use super::*;
+
+    // The fragments of the inline boxes on the lines of the first anonymous block.
+    fn fragments<'b>(layout_box: &'b LayoutBox) -> Vec<&'b InlineFragment> {
+        let BoxType::AnonymousBlock(ref ifc) = layout_box.children[0].box_type else {
+            panic!("text should be wrapped in an anonymous block");
+        };
+        ifc.elements
+            .iter()
+            .filter_map(|element| match element {
+                InlineFormattingContextRun::Fragment(fragment) => Some(fragment),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_inline_fragments() {
+        let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit.";
+        let layout_tree = layout_with_fonts(
+            &format!(
+                "<div class=\"doc\"><p>Hello <span>{text}</span></p><p><span class=\"clone\">{text}</span></p></div>"
+            ),
+            ".doc { display: block; font-family: Noto; font-size: 20px; }
+p { display: block; }
+span { margin: 0 3px; border-width: 1px; padding: 2px 4px; }
+.clone { box-decoration-break: clone; }",
+            200.0,
+        );
+
+        // The box is split into a fragment on each line, with its edges only at its start and end.
+        let slice = fragments(&layout_tree.children[0]);
+        assert!(slice.len() > 2);
+        let edges: Vec<(bool, bool)> = slice.iter().map(|f| (f.first, f.last)).collect();
+        assert_eq!(edges[0], (true, false));
+        assert!(
+            edges[1..edges.len() - 1]
+                .iter()
+                .all(|&edge| edge == (false, false))
+        );
+        assert_eq!(edges[edges.len() - 1], (false, true));
+        assert!(slice[1..].iter().all(|fragment| fragment.left == 0.0));
+        // Vertical padding and borders go around the text without making the lines taller.
+        let line = &layout_tree.children[0].children[0];
+        let BoxType::AnonymousBlock(ref ifc) = line.box_type else {
+            unreachable!()
+        };
+        let InlineFormattingContextRun::TextRun(ref hello) = ifc.elements[0] else {
+            panic!("the text should start the line");
+        };
+        assert_eq!(
+            line.dimensions.inner.height,
+            hello.height * slice.len() as f32
+        );
+        assert!(slice[0].height > hello.height);
+        assert!(slice[0].top < hello.top);
+        // The text in the box goes after its start edges.
+        let InlineFormattingContextRun::TextRun(ref lorem) = ifc.elements[2] else {
+            panic!("the text of the box should follow its fragment");
+        };
+        assert_eq!(
+            slice[0].left,
+            hello.left + hello.width + hello.space_width() + 3.0
+        );
+        assert_eq!(lorem.left, slice[0].left + 5.0);
+
+        // Cloned edges are on every fragment.
+        let clone = fragments(&layout_tree.children[1]);
+        assert!(clone.iter().all(|fragment| fragment.first && fragment.last));
+        assert!(clone.iter().all(|fragment| fragment.left == 3.0));
+    }
+
+    #[test]
+    fn test_end_edges_wrap() {
+        // The words on the lines of a paragraph `width` wide, with their right edge and top.
+        let words = |html: &str, width: f32| -> Vec<(String, f32, f32)> {
+            let layout_tree = layout_with_fonts(
+                html,
+                "p { display: block; font-family: Noto; font-size: 20px; }
+.end { padding-right: 15px; border-right-width: 5px; }
+.clone { box-decoration-break: clone; }",
+                width,
+            );
+            text_runs(&layout_tree.children[0])
+                .iter()
+                .map(|run| (run.text.clone(), run.left + run.width, run.top))
+                .collect()
+        };
+        // Room for the words, but not for the end edges of the box after them.
+        let plain = words("<p>aa <span>bb</span></p>", 400.0);
+        let width = plain[1].1 + 10.0;
+
+        // The last word of a box goes on the next line if the box's end edges don't fit after it.
+        let end = words("<p>aa <span class=\"end\">bb</span></p>", width);
+        assert!(end[1].2 > end[0].2);
+        // So does a word before a break in a box with cloned edges, which end every fragment.
+        let clone = words("<p><span class=\"end clone\">aa bb cc</span></p>", width);
+        assert!(clone[1].2 > clone[0].2);
+        let slice = words("<p><span class=\"end\">aa bb cc</span></p>", width);
+        assert_eq!(slice[1].2, slice[0].2);
+    }
+
+    #[test]
+    fn test_nested_inlines() {
+        let layout_tree = layout_with_fonts(
+            "<p>Hello <b>big <i>wide world</i> here</b> now and then</p>",
+            "p { display: block; font-family: Noto; font-size: 20px; }",
+            120.0,
+        );
+
+        // The words of every box are runs of the one anonymous block, found by their path in it.
+        let runs: Vec<(&str, &[usize], f32)> = text_runs(&layout_tree.children[0])
+            .into_iter()
+            .map(|run| (run.text.as_str(), &run.path[..], run.top))
+            .collect();
+        let words: Vec<(&str, &[usize])> =
+            runs.iter().map(|&(text, path, _)| (text, path)).collect();
+        assert_eq!(
+            words,
+            vec![
+                ("Hello", &[0][..]),
+                ("big", &[1, 0][..]),
+                ("wide", &[1, 1, 0][..]),
+                ("world", &[1, 1, 0][..]),
+                ("here", &[1, 2][..]),
+                ("now", &[2][..]),
+                ("and", &[2][..]),
+                ("then", &[2][..]),
+            ]
+        );
+        // Lines wrap across the boundaries of the boxes, and hold words from several of them.
+        let tops: Vec<f32> = runs.iter().map(|&(_, _, top)| top).collect();
+        assert!(tops.windows(2).any(|pair| pair[0] < pair[1]));
+        assert!(
+            runs.windows(2)
+                .any(|pair| pair[0].2 == pair[1].2 && pair[0].1 != pair[1].1)
+        );
+        // Each box has fragments on the lines it's on, in the order the boxes start.
+        let paths: Vec<&[usize]> = fragments(&layout_tree)
+            .iter()
+            .map(|fragment| &fragment.path[..])
+            .collect();
+        assert_eq!(paths.first(), Some(&&[1][..]));
+        assert!(paths.contains(&&[1, 1][..]));
+    }
+
+    #[test]
+    fn test_block_in_inline() {
+        let layout_tree = layout_with_fonts(
+            "<div><p>Hello <span>one <em>two<div>block</div>three</em> four</span> tail</p></div>",
+            "div, p { display: block; font-family: Noto; font-size: 20px; }
+span { margin: 0 3px; border-width: 1px; padding: 2px 4px; }",
+            400.0,
+        );
+
+        // The inline boxes are split around the block, which goes between the lines before and
+        // after it.
+        let p = &layout_tree.children[0];
+        let kinds: Vec<&str> = p
+            .children
+            .iter()
+            .map(|child| match child.box_type {
+                BoxType::AnonymousBlock(_) => "anonymous",
+                BoxType::BlockNode(_) => "block",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(kinds, vec!["anonymous", "block", "anonymous"]);
+        let before = &p.children[0].children[1];
+        assert_eq!(before.split, (false, true));
+        assert_eq!(before.children[1].split, (false, true));
+        let after = &p.children[2].children[0];
+        assert_eq!(after.split, (true, false));
+        assert_eq!(after.children[0].split, (true, false));
+        assert_eq!(after.children.len(), 2);
+
+        let first_line = p.children[0].dimensions.margin_box().height;
+        assert_eq!(p.children[1].dimensions.box_offset.top, first_line);
+
+        // The first piece of the span has no end edges, and the last one no start edges.
+        let span = fragments(p)[0];
+        let two = text_runs(&p.children[0])[2];
+        assert_eq!(span.left + span.width, two.left + two.width);
+        let BoxType::AnonymousBlock(ref ifc) = p.children[2].box_type else {
+            unreachable!()
+        };
+        let Some(InlineFormattingContextRun::Fragment(span)) = ifc.elements.first() else {
+            panic!("the line after the block should start with the span");
+        };
+        assert_eq!(span.left, 0.0);
+        let three = text_runs(&p.children[2])[0];
+        assert_eq!((three.text.as_str(), three.left), ("three", 0.0));
+    }
+}
@@ -0,0 +1,205 @@
+//! Lists and their markers, following CSS Lists and Counters Level 3.
+//!
+//! https://www.w3.org/TR/css-lists-3/
+
+use super::*;
+
+impl<'a> LayoutBox<'a> {
+    // Give a list item the marker for its `list-item` counter, unless its `list-style-type` is
+    // `none`. An outside marker goes on a line of its own before the content, and an inside one at
+    // the start of the first line.
+    pub(super) fn add_marker(&mut self, default_type: &str) {
+        let style = self.get_style_node();
+        let list_style_type = style
+            .value("list-style-type")
+            .unwrap_or(Value::Keyword(default_type.to_string()));
+        let Some(text) = marker_text(&list_style_type, style.counter("list-item")) else {
+            return;
+        };
+        let marker = LayoutBox::new(BoxType::MarkerNode(style, text));
+        let inside = keyword(style, "list-style-position", "outside") == "inside";
+        match self.children.first_mut() {
+            Some(
+                line @ LayoutBox {
+                    box_type: BoxType::AnonymousBlock(_),
+                    ..
+                },
+            ) if inside => line.children.insert(0, marker),
+            _ => {
+                let ifc = InlineFormattingContext {
+                    style: inside.then_some(style),
+                    first_line: inside,
+                    ..Default::default()
+                };
+                let mut line = LayoutBox::new(BoxType::AnonymousBlock(ifc));
+                line.children.push(marker);
+                self.children.insert(0, line);
+            }
+        }
+    }
+
+    // Whether this is the line of an outside marker.
+    pub(super) fn is_outside_marker(&self) -> bool {
+        match (&self.box_type, &self.children[..]) {
+            (
+                BoxType::AnonymousBlock(_),
+                [
+                    LayoutBox {
+                        box_type: BoxType::MarkerNode(style, _),
+                        ..
+                    },
+                ],
+            ) => keyword(style, "list-style-position", "outside") != "inside",
+            _ => false,
+        }
+    }
+
+    // Lay out the line of an outside marker, and put it in the margin before the first line of
+    // the list item, a space away from it. It takes up no room in flow.
+    pub(super) fn layout_outside_marker(
+        &mut self,
+        container_height: &Value,
+        fonts: &'a FontRegistry,
+    ) {
+        self.layout_anonymous(
+            Value::Keyword("auto".to_string()),
+            container_height.clone(),
+            Value::Length(f32::INFINITY, Unit::Px),
+            fonts,
+            &mut FloatContext::default(),
+            (0.0, 0.0),
+        );
+        let space = self.children[0].space_width(fonts);
+        self.dimensions.box_offset = BoxOffset {
+            left: -(self.dimensions.inner.width + space),
+            top: 0.0,
+        };
+    }
+}
+
+// The `list-style-type` of items of `list` that don't set one: numbers in an `ol`, discs otherwise.
+pub(super) fn default_list_style_type(list: &StyledNode) -> &'static str {
+    match list.node.node_type {
+        NodeType::Element(ref element) if element.tag_name.eq_ignore_ascii_case("ol") => "decimal",
+        _ => "disc",
+    }
+}
+
+// The text of the marker of a list item numbered `ordinal`, with the space after it, or `None`
+// for `list-style-type: none`. A string is used as it is.
+fn marker_text(list_style_type: &Value, ordinal: i32) -> Option<String> {
+    match list_style_type {
+        Value::Str(text) => Some(text.clone()),
+        Value::Keyword(name) => match name.as_str() {
+            "none" => None,
+            "disc" | "circle" | "square" => Some(format!("{} ", counter_text(ordinal, name))),
+            _ => Some(format!("{}. ", counter_text(ordinal, name))),
+        },
+        _ => Some(format!("{} ", counter_text(ordinal, "disc"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{css, html};
+    use super::super::tests::{layout_with_fonts, text_runs};
+    use super::*;
+
+    fn marker(item: &LayoutBox) -> String {
+        let marker = match item.children[0].children.first() {
+            Some(marker) => marker,
+            None => return String::new(),
+        };
+        match marker.box_type {
+            BoxType::MarkerNode(_, ref text) => text.clone(),
+            _ => String::new(),
+        }
+    }
+
+    #[test]
+    fn test_marker_text() {
+        let keyword = |name: &str| Value::Keyword(name.to_string());
+        assert_eq!(
+            marker_text(&keyword("decimal"), -2),
+            Some("-2. ".to_string())
+        );
+        assert_eq!(
+            marker_text(&keyword("decimal-leading-zero"), 7),
+            Some("07. ".to_string())
+        );
+        assert_eq!(
+            marker_text(&keyword("lower-alpha"), 28),
+            Some("ab. ".to_string())
+        );
+        assert_eq!(
+            marker_text(&keyword("upper-roman"), 1994),
+            Some("MCMXCIV. ".to_string())
+        );
+        assert_eq!(
+            marker_text(&keyword("lower-roman"), 0),
+            Some("0. ".to_string())
+        );
+        assert_eq!(marker_text(&keyword("square"), 3), Some("▪ ".to_string()));
+        assert_eq!(marker_text(&keyword("none"), 1), None);
+        assert_eq!(
+            marker_text(&Value::Str("- ".to_string()), 1),
+            Some("- ".to_string())
+        );
+    }
+
+    #[test]
+    fn test_list_items() {
+        let root = html::parse(
+            "<div><ol reversed><li>a</li><li>b</li><li class=\"none\">c</li></ol><ol start=\"4\" class=\"roman\"><li>d</li><li>e</li></ol><ul><li>f</li></ul></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div { display: block; }
+.roman { list-style-type: upper-roman; }
+.none { list-style-type: none; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let layout_tree = build_layout_tree(&styled_tree);
+
+        let lists = &layout_tree.children;
+        let markers: Vec<String> = lists
+            .iter()
+            .flat_map(|list| list.children.iter().map(marker))
+            .collect();
+        assert_eq!(markers, ["3. ", "2. ", "", "IV. ", "V. ", "• "]);
+    }
+
+    #[test]
+    fn test_marker_position() {
+        let layout_tree = layout_with_fonts(
+            "<ol><li>Lorem</li><li class=\"inside\">Ipsum</li></ol>",
+            "ol { font-family: Noto; font-size: 20px; padding-left: 40px; }
+.inside { list-style-position: inside; }",
+            400.0,
+        );
+
+        let runs = |line: &LayoutBox| -> Vec<(String, f32)> {
+            text_runs(line)
+                .iter()
+                .map(|run| (run.text.clone(), run.left))
+                .collect()
+        };
+        // Outside, the marker hangs in the padding, and the text starts the line.
+        let outside = &layout_tree.children[0];
+        let marker = &outside.children[0];
+        assert!(marker.dimensions.box_offset.left < -marker.dimensions.inner.width);
+        assert_eq!(runs(marker)[0].0, "1.");
+        assert_eq!(runs(&outside.children[1])[0], ("Lorem".to_string(), 0.0));
+        assert_eq!(
+            outside.dimensions.inner.height,
+            marker.dimensions.inner.height
+        );
+        // Inside, it's the first thing on the line.
+        let inside = &layout_tree.children[1];
+        let line = runs(&inside.children[0]);
+        assert_eq!(line[0], ("2.".to_string(), 0.0));
+        assert_eq!(line[1].0, "Ipsum");
+        assert!(line[1].1 > 0.0);
+    }
+}
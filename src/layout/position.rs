@@ -0,0 +1,302 @@
+//! Positioned layout, following CSS Positioned Layout Level 3.
+//!
+//! https://www.w3.org/TR/css-position-3/
+
+use super::*;
+
+// The `top`, `right`, `bottom` and `left` properties in px, or `None` for `auto`.
+#[derive(Clone, Copy, Default, Debug)]
+struct Insets {
+    top: Option<f32>,
+    right: Option<f32>,
+    bottom: Option<f32>,
+    left: Option<f32>,
+}
+
+impl<'a> LayoutBox<'a> {
+    // Place the positioned descendants of the root, once everything in flow is laid out: shift
+    // relatively positioned and sticky boxes, and lay out absolutely positioned ones against
+    // their containing blocks. The viewport is `width` by `height`.
+    pub(super) fn layout_positioned(&mut self, width: f32, height: f32, fonts: &'a FontRegistry) {
+        let d = self.dimensions;
+        // Positions here are relative to the content box of the root, whose margin box is at the
+        // origin of the viewport.
+        let viewport = Rect {
+            x: -d.inner.left,
+            y: -d.inner.top,
+            width,
+            height,
+        };
+        let content = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: d.inner.width,
+            height: d.inner.height,
+        };
+        // Without a positioned ancestor, the initial containing block is the viewport.
+        let containing_block = match self.is_positioned() {
+            true => content.expanded_by(d.padding),
+            false => viewport,
+        };
+        self.position_children(content, containing_block, viewport, fonts);
+    }
+
+    // Position the children of a box whose content box is `content`. `containing_block` is the
+    // padding box of the nearest positioned ancestor.
+    fn position_children(
+        &mut self,
+        content: Rect,
+        containing_block: Rect,
+        viewport: Rect,
+        fonts: &'a FontRegistry,
+    ) {
+        let has_items = matches!(
+            self.box_type,
+            BoxType::FlexNode(_)
+                | BoxType::InlineFlexNode(_)
+                | BoxType::GridNode(_)
+                | BoxType::InlineGridNode(_)
+        );
+        for child in &mut self.children {
+            match child.position() {
+                Position::Static => {}
+                Position::Relative => child.offset_relative(content),
+                Position::Sticky => child.stick(content, viewport),
+                position @ (Position::Absolute | Position::Fixed) => {
+                    if has_items {
+                        // As if it were the only item: at the start of the content box.
+                        child.dimensions.box_offset = BoxOffset::default();
+                    }
+                    let containing_block = match position {
+                        Position::Fixed => viewport,
+                        _ => containing_block,
+                    };
+                    child.layout_absolute(content, containing_block, fonts);
+                }
+            }
+            let d = child.dimensions;
+            let child_content = Rect {
+                x: content.x + d.get_left(),
+                y: content.y + d.get_top(),
+                width: d.inner.width,
+                height: d.inner.height,
+            };
+            let child_containing_block = match child.is_positioned() {
+                true => child_content.expanded_by(d.padding),
+                false => containing_block,
+            };
+            child.position_children(child_content, child_containing_block, viewport, fonts);
+        }
+    }
+
+    // The insets of the box, with percentages of the width or height of `containing_block`.
+    fn insets(&self, containing_block: Rect) -> Insets {
+        let style = self.get_style_node();
+        let inset = |name: &str, size: f32| match style.value(name) {
+            Some(Value::Percentage(percentage)) => Some(size * percentage / 100.0),
+            Some(value) if value.is_specific_length() => Some(value.to_px()),
+            _ => None,
+        };
+        Insets {
+            top: inset("top", containing_block.height),
+            right: inset("right", containing_block.width),
+            bottom: inset("bottom", containing_block.height),
+            left: inset("left", containing_block.width),
+        }
+    }
+
+    // Shift a relatively positioned box from where it is in flow, without moving anything else.
+    // `top` wins over `bottom`, and `left` over `right`.
+    // https://www.w3.org/TR/css-position-3/#relpos-insets
+    fn offset_relative(&mut self, containing_block: Rect) {
+        let insets = self.insets(containing_block);
+        let offset = &mut self.dimensions.box_offset;
+        offset.left += insets
+            .left
+            .or(insets.right.map(|right| -right))
+            .unwrap_or(0.0);
+        offset.top += insets
+            .top
+            .or(insets.bottom.map(|bottom| -bottom))
+            .unwrap_or(0.0);
+    }
+
+    // Shift a sticky box so that it's at least its insets away from the edges of the viewport,
+    // which is never scrolled, as far as it can go without leaving its containing block.
+    // https://www.w3.org/TR/css-position-3/#stickypos-insets
+    fn stick(&mut self, containing_block: Rect, viewport: Rect) {
+        let insets = self.insets(viewport);
+        let (width, height) = (
+            self.axis_margin_box_size(true),
+            self.axis_margin_box_size(false),
+        );
+        let offset = &mut self.dimensions.box_offset;
+        offset.left += sticky_shift(
+            (containing_block.x + offset.left, width),
+            (viewport.x, viewport.width),
+            (containing_block.x, containing_block.width),
+            (insets.left, insets.right),
+        );
+        offset.top += sticky_shift(
+            (containing_block.y + offset.top, height),
+            (viewport.y, viewport.height),
+            (containing_block.y, containing_block.height),
+            (insets.top, insets.bottom),
+        );
+    }
+
+    // Lay out an absolutely positioned box against its containing block, and place it by its
+    // insets. Along an axis where both insets are `auto`, the box keeps its static position,
+    // which flow layout left in `box_offset`. `parent` is the content box of the parent.
+    // https://www.w3.org/TR/css-position-3/#abs-non-replaced-width
+    fn layout_absolute(&mut self, parent: Rect, containing_block: Rect, fonts: &'a FontRegistry) {
+        let insets = self.insets(containing_block);
+        let static_x = parent.x + self.dimensions.box_offset.left;
+        let static_y = parent.y + self.dimensions.box_offset.top;
+
+        // 1. width -> (specific, filling between the insets, shrink-to-fit)
+        let available =
+            containing_block.width - insets.left.unwrap_or(0.0) - insets.right.unwrap_or(0.0);
+        let container_width = match (insets.left, insets.right) {
+            (Some(_), Some(_)) => Value::Length(available, Unit::Px),
+            _ => Value::Keyword("auto".to_string()),
+        };
+        let container_height = Value::Length(containing_block.height, Unit::Px);
+        self.imposed_size = ImposedSize::default();
+        self.layout_box(
+            container_width.clone(),
+            container_height.clone(),
+            Value::Length(available, Unit::Px),
+            fonts,
+        );
+
+        // 2. height -> (specific, filling between the insets, by content)
+        if let (Some(top), Some(bottom)) = (insets.top, insets.bottom)
+            && !self
+                .specified_height(&container_height)
+                .is_specific_length()
+        {
+            // `auto` margins are zero here.
+            let edges = self.axis_edges(false);
+            let height = containing_block.height - top - bottom - edges;
+            self.imposed_size.height = Some(self.clamp_height(height).max(0.0));
+            self.layout_box(
+                container_width,
+                container_height,
+                Value::Length(available, Unit::Px),
+                fonts,
+            );
+        }
+
+        // 3. position -> (inset, `auto` margins share the rest, static position)
+        let x = self.place_absolute(
+            true,
+            (containing_block.x, containing_block.width),
+            (insets.left, insets.right),
+            static_x,
+        );
+        let y = self.place_absolute(
+            false,
+            (containing_block.y, containing_block.height),
+            (insets.top, insets.bottom),
+            static_y,
+        );
+        self.dimensions.box_offset.left = x - parent.x;
+        self.dimensions.box_offset.top = y - parent.y;
+    }
+
+    // The start of the margin box of an absolutely positioned box along an axis, given the start
+    // and size of the containing block and the insets on both sides.
+    fn place_absolute(
+        &mut self,
+        horizontal: bool,
+        (start, size): (f32, f32),
+        insets: (Option<f32>, Option<f32>),
+        static_position: f32,
+    ) -> f32 {
+        match insets {
+            (Some(inset_start), Some(inset_end)) => {
+                let free = size - inset_start - inset_end - self.axis_margin_box_size(horizontal);
+                let count = self.auto_margin_count(horizontal);
+                if count > 0 && free > 0.0 {
+                    self.fill_auto_margins(horizontal, free / count as f32);
+                }
+                start + inset_start
+            }
+            (Some(inset_start), None) => start + inset_start,
+            (None, Some(inset_end)) => {
+                start + size - inset_end - self.axis_margin_box_size(horizontal)
+            }
+            (None, None) => static_position,
+        }
+    }
+}
+
+// How far a sticky box moves along an axis, from its `(start, size)` span, the span of the
+// scrollport, the span of its containing block and the insets at the start and end.
+fn sticky_shift(
+    (start, size): (f32, f32),
+    (port_start, port_size): (f32, f32),
+    (block_start, block_size): (f32, f32),
+    (inset_start, inset_end): (Option<f32>, Option<f32>),
+) -> f32 {
+    let mut shift = 0.0;
+    if let Some(inset) = inset_start {
+        let room = block_start + block_size - (start + size);
+        shift = (port_start + inset - start).min(room).max(0.0);
+    }
+    if let Some(inset) = inset_end {
+        let room = start - block_start;
+        shift -= (start + size - (port_start + port_size - inset))
+            .min(room)
+            .max(0.0);
+    }
+    shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{css, html};
+    use super::super::tests::{layout, rect};
+    use super::*;
+
+    #[test]
+    fn test_positioned_layout() {
+        let root = html::parse(
+            "<div><div class=\"container\"><p class=\"flow\"></p><p class=\"corner\"></p><p class=\"static\"></p><p class=\"center\"></p></div><div class=\"shifted\"></div><div class=\"fixed\"></div><div class=\"sticky\"></div></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div { display: block; }
+p { display: block; }
+.container { position: relative; margin-left: 20px; padding: 10px; width: 200px; height: 100px; }
+.flow { height: 30px; }
+.corner { position: absolute; right: 0; bottom: 0; width: 50px; height: 20px; }
+.static { position: absolute; left: 5px; }
+.center { position: absolute; top: 0; right: 0; bottom: 0; left: 0; margin: auto; width: 100px; height: 40px; }
+.shifted { position: relative; top: 5px; left: -5px; height: 10px; }
+.fixed { position: fixed; top: 0; left: 0; right: 0; height: 10px; }
+.sticky { position: sticky; bottom: 300px; height: 10px; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let layout_tree = layout(&styled_tree, &fonts);
+
+        // Absolutely positioned boxes take up no space in flow.
+        assert_eq!(rect(&layout_tree).3, 140.0);
+        let container = &layout_tree.children[0];
+        assert_eq!(rect(container), (30.0, 10.0, 200.0, 100.0));
+        assert_eq!(rect(&container.children[0]), (30.0, 10.0, 200.0, 30.0));
+        // Against the padding box of the containing block.
+        assert_eq!(rect(&container.children[1]), (190.0, 100.0, 50.0, 20.0));
+        // `top` and `bottom` are `auto`: below the box before it, where it would be in flow.
+        assert_eq!(rect(&container.children[2]), (25.0, 40.0, 0.0, 0.0));
+        assert_eq!(rect(&container.children[3]), (80.0, 40.0, 100.0, 40.0));
+
+        assert_eq!(rect(&layout_tree.children[1]), (-5.0, 125.0, 400.0, 10.0));
+        assert_eq!(rect(&layout_tree.children[2]), (0.0, 0.0, 400.0, 10.0));
+        // Pushed up to stay 300px above the bottom of the viewport.
+        assert_eq!(rect(&layout_tree.children[3]), (0.0, 90.0, 400.0, 10.0));
+    }
+}
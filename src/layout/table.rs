@@ -0,0 +1,694 @@
+//! Table layout, following CSS 2.2 § 17.
+//!
+//! https://www.w3.org/TR/CSS22/tables.html
+
+use super::*;
+
+// A row of the table grid. Cells that aren't in a row box make up anonymous rows, whose `path`
+// is the path of the table or row group around them.
+#[derive(Clone, Debug)]
+struct Row {
+    path: Vec<usize>,
+    cells: Vec<Vec<usize>>,
+}
+
+// A column or column group box, over the columns from `first` on.
+#[derive(Clone, Debug)]
+struct Column {
+    path: Vec<usize>,
+    first: usize,
+    span: usize,
+    width: Option<f32>,
+}
+
+// A cell in the slots of the grid, from the first row and column of its spans to the ends.
+#[derive(Clone, Debug)]
+struct Cell {
+    path: Vec<usize>,
+    rows: (usize, usize),
+    columns: (usize, usize),
+    // The borders the cell draws in the collapsing border model.
+    border: Option<EdgeSizes>,
+}
+
+impl<'a> LayoutBox<'a> {
+    pub(super) fn layout_table(
+        &mut self,
+        container_width: Value,
+        container_height: Value,
+        context_constraints_width: Value,
+        fonts: &'a FontRegistry,
+    ) {
+        let auto = Value::Keyword("auto".to_string());
+
+        // 1. self: width, edges and height -> (specific, a percentage of the container, auto)
+        let (_, self_as_context_constraints_width, is_self_no_filled_auto) =
+            self.resolve_width(&container_width, &context_constraints_width);
+        self.resolve_edges();
+        let style = self.get_style_node();
+        let available = self_as_context_constraints_width.to_px();
+        let definite_width = match (self.imposed_size.width, style.value("width")) {
+            (Some(width), _) => Some(width),
+            (None, Some(Value::Percentage(percentage))) if container_width.is_specific_length() => {
+                let width = container_width.to_px() * percentage / 100.0;
+                Some(self.clamp_width(self.content_width(width)))
+            }
+            (None, Some(width)) if width.is_specific_length() && !is_self_no_filled_auto => {
+                Some(self.dimensions.inner.width)
+            }
+            _ => None,
+        };
+        let definite_height = match self.resolve_height(&container_height) {
+            Value::Length(height, _) => Some(height),
+            _ => None,
+        };
+        let collapse = keyword(style, "border-collapse", "separate") == "collapse";
+        let (h_spacing, v_spacing) = match style.value("border-spacing") {
+            _ if collapse => (0.0, 0.0),
+            Some(Value::List(lengths)) if lengths.len() == 2 => {
+                (lengths[0].to_px(), lengths[1].to_px())
+            }
+            Some(length) => (length.to_px(), length.to_px()),
+            None => (0.0, 0.0),
+        };
+
+        // 2. grid: cells -> slots (rows in display order, `colspan` and `rowspan`)
+        let (captions, columns, rows) = self.table_parts();
+        let mut cells = Vec::new();
+        let mut slots: Vec<Vec<Option<usize>>> = vec![Vec::new(); rows.len()];
+        for (r, row) in rows.iter().enumerate() {
+            let mut column = 0;
+            for path in &row.cells {
+                let cell = self.descendant(path);
+                while slots[r].get(column).is_some_and(Option::is_some) {
+                    column += 1;
+                }
+                let column_span = cell.span_attribute("colspan").clamp(1, 1000);
+                // `rowspan="0"` spans the rest of the rows.
+                let row_span = match cell.span_attribute("rowspan") {
+                    0 => rows.len() - r,
+                    span => span.min(rows.len() - r),
+                };
+                for row_slots in &mut slots[r..r + row_span] {
+                    if row_slots.len() < column + column_span {
+                        row_slots.resize(column + column_span, None);
+                    }
+                    row_slots[column..column + column_span].fill(Some(cells.len()));
+                }
+                cells.push(Cell {
+                    path: path.clone(),
+                    rows: (r, r + row_span),
+                    columns: (column, column + column_span),
+                    border: None,
+                });
+                column += column_span;
+            }
+        }
+        let column_count = cells
+            .iter()
+            .map(|cell| cell.columns.1)
+            .chain(columns.iter().map(|column| column.first + column.span))
+            .max()
+            .unwrap_or(0);
+        for row_slots in &mut slots {
+            row_slots.resize(column_count, None);
+        }
+
+        // 3. borders -> (separated, collapsed)
+        if collapse {
+            // Each border between two cells is drawn once, by the cell above it or to the left of
+            // it, as wide as the wider of the two.
+            let own: Vec<EdgeSizes> = cells
+                .iter()
+                .map(|cell| {
+                    let cell_box = self.descendant_mut(&cell.path);
+                    cell_box.resolve_cell_edges(None);
+                    cell_box.dimensions.border
+                })
+                .collect();
+            for (i, cell) in cells.iter_mut().enumerate() {
+                let mut border = own[i];
+                let (first_row, end_row) = cell.rows;
+                let (first_column, end_column) = cell.columns;
+                for row_slots in &slots[first_row..end_row] {
+                    if first_column > 0 && row_slots[first_column - 1].is_some() {
+                        border.left = 0.0;
+                    }
+                    if let Some(Some(j)) = row_slots.get(end_column) {
+                        border.right = border.right.max(own[*j].left);
+                    }
+                }
+                for column in first_column..end_column {
+                    if first_row > 0 && slots[first_row - 1][column].is_some() {
+                        border.top = 0.0;
+                    }
+                    if let Some(Some(j)) = slots.get(end_row).map(|row_slots| row_slots[column]) {
+                        border.bottom = border.bottom.max(own[j].top);
+                    }
+                }
+                cell.border = Some(border);
+            }
+        }
+        let spacing = |count: usize| match count {
+            0 => 0.0,
+            count => h_spacing * (count + 1) as f32,
+        };
+
+        // 4. columns: widths -> (fixed: the columns and the first row; auto: by the cells'
+        // min-content and max-content widths)
+        let fixed = keyword(style, "table-layout", "auto") == "fixed" && definite_width.is_some();
+        let mut widths: Vec<Option<f32>> = vec![None; column_count];
+        for column in &columns {
+            if let Some(width) = column.width {
+                widths[column.first..column.first + column.span].fill(Some(width));
+            }
+        }
+        let (width, column_widths) = if let (true, Some(width)) = (fixed, definite_width) {
+            for cell in cells.iter().filter(|cell| cell.rows.0 == 0) {
+                let (first, end) = cell.columns;
+                let cell_box = self.descendant_mut(&cell.path);
+                let Some(cell_width) = cell_box
+                    .item_style()
+                    .and_then(|style| style.value("width"))
+                    .filter(Value::is_specific_length)
+                else {
+                    continue;
+                };
+                cell_box.resolve_cell_edges(cell.border);
+                let cell_width = cell_box.clamp_width(cell_box.content_width(cell_width.to_px()))
+                    + cell_box.axis_edges(true);
+                let unset = widths[first..end].iter().filter(|w| w.is_none()).count();
+                let share = (cell_width - h_spacing * (end - first - 1) as f32) / unset as f32;
+                for column_width in widths[first..end].iter_mut().filter(|w| w.is_none()) {
+                    *column_width = Some(share.max(0.0));
+                }
+            }
+            let set = sum(widths.iter().flatten().copied());
+            let unset = widths.iter().filter(|w| w.is_none()).count();
+            let share = (width - spacing(column_count) - set).max(0.0) / unset.max(1) as f32;
+            let column_widths: Vec<f32> = widths.iter().map(|w| w.unwrap_or(share)).collect();
+            let width = width.max(sum(column_widths.iter().copied()) + spacing(column_count));
+            (width, column_widths)
+        } else {
+            let mut min: Vec<f32> = widths.iter().map(|w| w.unwrap_or(0.0)).collect();
+            let mut max = min.clone();
+            let mut contributions = Vec::new();
+            for cell in &cells {
+                let cell_box = self.descendant_mut(&cell.path);
+                cell_box.imposed_size = ImposedSize::default();
+                let mut width = |constraint: f32| {
+                    cell_box.layout_item(&auto, &auto, &Value::Length(constraint, Unit::Px), fonts);
+                    cell_box.resolve_cell_edges(cell.border);
+                    cell_box.axis_margin_box_size(true)
+                };
+                contributions.push((cell.columns, width(0.0), width(f32::INFINITY)));
+            }
+            // Cells spanning fewer columns go first; wider ones then widen their columns evenly
+            // by what's still missing.
+            contributions.sort_by_key(|((first, end), _, _)| end - first);
+            for ((first, end), min_width, max_width) in contributions {
+                let gaps = h_spacing * (end - first - 1) as f32;
+                for (sizes, size) in [(&mut min, min_width), (&mut max, max_width)] {
+                    let missing = size - gaps - sum(sizes[first..end].iter().copied());
+                    if missing > 0.0 {
+                        for column_size in &mut sizes[first..end] {
+                            *column_size += missing / (end - first) as f32;
+                        }
+                    }
+                }
+            }
+            for (max, min) in max.iter_mut().zip(&min) {
+                *max = max.max(*min);
+            }
+            let min_width = sum(min.iter().copied()) + spacing(column_count);
+            let max_width = sum(max.iter().copied()) + spacing(column_count);
+            let width = match definite_width {
+                Some(width) => width.max(min_width),
+                None => self.clamp_width(max_width.min(available)).max(min_width),
+            };
+            (
+                width,
+                distribute_width(&min, &max, width - spacing(column_count)),
+            )
+        };
+        let column_positions = positions(&column_widths, h_spacing);
+
+        // 5. rows: heights -> (the rows, then by the cells' heights in their columns)
+        let mut row_heights: Vec<f32> = rows
+            .iter()
+            .map(|row| match self.descendant(&row.path).box_type {
+                BoxType::TableRowNode(style) => match style.value("height") {
+                    Some(height) if height.is_specific_length() => height.to_px(),
+                    _ => 0.0,
+                },
+                _ => 0.0,
+            })
+            .collect();
+        let mut contributions = Vec::new();
+        for cell in &cells {
+            let cell_width = span_size(&column_widths, &column_positions, cell.columns);
+            let cell_box = self.descendant_mut(&cell.path);
+            cell_box.resolve_cell_edges(cell.border);
+            cell_box.imposed_size = ImposedSize {
+                width: Some((cell_width - cell_box.axis_edges(true)).max(0.0)),
+                height: None,
+            };
+            cell_box.layout_item(&auto, &auto, &Value::Length(cell_width, Unit::Px), fonts);
+            cell_box.resolve_cell_edges(cell.border);
+            contributions.push((cell.rows, cell_box.axis_margin_box_size(false)));
+        }
+        contributions.sort_by_key(|((first, end), _)| end - first);
+        for ((first, end), height) in contributions {
+            let gaps = v_spacing * (end - first - 1) as f32;
+            let missing = height - gaps - sum(row_heights[first..end].iter().copied());
+            // The last row a cell spans makes room for the rest of it.
+            if missing > 0.0 {
+                row_heights[end - 1] += missing;
+            }
+        }
+        let vertical_spacing = match rows.len() {
+            0 => 0.0,
+            count => v_spacing * (count + 1) as f32,
+        };
+
+        // 6. captions: above or below the grid, as wide as it is
+        let mut caption_heights = (0.0, 0.0);
+        for &index in &captions {
+            let caption = &mut self.children[index];
+            caption.imposed_size = ImposedSize::default();
+            caption.layout_item(
+                &Value::Length(width, Unit::Px),
+                &auto,
+                &Value::Length(width, Unit::Px),
+                fonts,
+            );
+            caption.resolve_horizontal_margins(width);
+            let height = caption.axis_margin_box_size(false);
+            match keyword(caption.get_style_node(), "caption-side", "top").as_str() {
+                "bottom" => caption_heights.1 += height,
+                _ => caption_heights.0 += height,
+            }
+        }
+
+        // 7. height: a taller table shares the extra height out between the rows
+        let grid_height = sum(row_heights.iter().copied()) + vertical_spacing;
+        let content_height = caption_heights.0 + grid_height + caption_heights.1;
+        if let Some(height) = definite_height
+            && height > content_height
+            && !rows.is_empty()
+        {
+            let extra = (height - content_height) / rows.len() as f32;
+            for row_height in &mut row_heights {
+                *row_height += extra;
+            }
+        }
+        let grid_height = sum(row_heights.iter().copied()) + vertical_spacing;
+        let row_positions: Vec<f32> = positions(&row_heights, v_spacing)
+            .into_iter()
+            .map(|top| caption_heights.0 + top)
+            .collect();
+
+        // 8. fixing children: positions relative to their parents, and the cells' heights
+        let mut top = 0.0;
+        let mut bottom = caption_heights.0 + grid_height;
+        for &index in &captions {
+            let caption = &mut self.children[index];
+            let position = match keyword(caption.get_style_node(), "caption-side", "top").as_str() {
+                "bottom" => &mut bottom,
+                _ => &mut top,
+            };
+            caption.dimensions.box_offset = BoxOffset {
+                left: 0.0,
+                top: *position,
+            };
+            *position += caption.axis_margin_box_size(false);
+        }
+        let inner_width = (width - 2.0 * h_spacing).max(0.0);
+        let inner_top = caption_heights.0 + v_spacing.min(grid_height);
+        let inner_height = (grid_height - 2.0 * v_spacing).max(0.0);
+        // Row groups, then rows, then cells, so that parents are placed before their children.
+        for (index, child) in self.children.iter_mut().enumerate() {
+            if let BoxType::TableRowGroupNode(_) = child.box_type {
+                let in_group = |row: &Row| row.path[0] == index;
+                let rect = match (
+                    rows.iter().position(in_group),
+                    rows.iter().rposition(in_group),
+                ) {
+                    (Some(first), Some(last)) => (
+                        row_positions[first],
+                        row_positions[last] + row_heights[last] - row_positions[first],
+                    ),
+                    _ => (inner_top, 0.0),
+                };
+                child.place_table_part(h_spacing, rect.0, inner_width, rect.1);
+            }
+        }
+        for (r, row) in rows.iter().enumerate() {
+            if let BoxType::TableRowNode(_) = self.descendant(&row.path).box_type {
+                let origin = self.descendant_origin(&row.path[..row.path.len() - 1]);
+                self.descendant_mut(&row.path).place_table_part(
+                    h_spacing - origin.0,
+                    row_positions[r] - origin.1,
+                    inner_width,
+                    row_heights[r],
+                );
+            }
+        }
+        for cell in &cells {
+            let origin = self.descendant_origin(&cell.path[..cell.path.len() - 1]);
+            let height = span_size(&row_heights, &row_positions, cell.rows);
+            let cell_box = self.descendant_mut(&cell.path);
+            cell_box.dimensions.box_offset = BoxOffset {
+                left: column_positions[cell.columns.0] - origin.0,
+                top: row_positions[cell.rows.0] - origin.1,
+            };
+            let content_height = (height - cell_box.axis_edges(false)).max(0.0);
+            cell_box.dimensions.inner.height = content_height;
+            // The cell's content is aligned in the height of the rows it spans.
+            let used = cell_box
+                .children
+                .iter()
+                .filter(|child| !child.is_out_of_flow())
+                .map(|child| child.dimensions.box_offset.top + child.axis_margin_box_size(false))
+                .fold(0.0, f32::max);
+            let free = (content_height - used).max(0.0);
+            let shift = match cell_box.item_style() {
+                Some(style) => match keyword(style, "vertical-align", "baseline").as_str() {
+                    "middle" => free / 2.0,
+                    "bottom" => free,
+                    _ => 0.0,
+                },
+                None => 0.0,
+            };
+            for child in &mut cell_box.children {
+                child.dimensions.box_offset.top += shift;
+            }
+        }
+        for column in &columns {
+            let origin = self.descendant_origin(&column.path[..column.path.len() - 1]);
+            let span = (column.first, column.first + column.span);
+            let (left, width) = match span.1 <= column_count && span.0 < span.1 {
+                true => (
+                    column_positions[span.0],
+                    span_size(&column_widths, &column_positions, span),
+                ),
+                false => (h_spacing, 0.0),
+            };
+            self.descendant_mut(&column.path).place_table_part(
+                left - origin.0,
+                inner_top - origin.1,
+                width,
+                inner_height,
+            );
+        }
+
+        self.dimensions.inner.width = width;
+        self.dimensions.inner.height = match definite_height {
+            Some(height) => height.max(content_height),
+            None => content_height,
+        };
+    }
+
+    // The captions, columns and rows of the table. Rows are in the order they're displayed in:
+    // header groups, then the body, then footer groups.
+    fn table_parts(&self) -> (Vec<usize>, Vec<Column>, Vec<Row>) {
+        let mut captions = Vec::new();
+        let mut columns = Vec::new();
+        let (mut header, mut body, mut footer) = (Vec::new(), Vec::new(), Vec::new());
+        let mut in_anonymous_row = false;
+        for (index, child) in self.children.iter().enumerate() {
+            if child.is_out_of_flow() {
+                continue;
+            }
+            match child.box_type {
+                BoxType::TableCaptionNode(_) => {
+                    captions.push(index);
+                    in_anonymous_row = false;
+                }
+                BoxType::TableColumnNode(_) => {
+                    child.table_columns(vec![index], &mut columns);
+                    in_anonymous_row = false;
+                }
+                BoxType::TableRowGroupNode(style) => {
+                    in_anonymous_row = false;
+                    let rows = match style.display_keyword().as_str() {
+                        "table-header-group" => &mut header,
+                        "table-footer-group" => &mut footer,
+                        _ => &mut body,
+                    };
+                    let mut in_anonymous_row = false;
+                    for (row_index, row) in child.children.iter().enumerate() {
+                        if !row.is_out_of_flow() {
+                            row.add_to_rows(vec![index, row_index], rows, &mut in_anonymous_row);
+                        }
+                    }
+                }
+                _ => child.add_to_rows(vec![index], &mut body, &mut in_anonymous_row),
+            }
+        }
+        header.append(&mut body);
+        header.append(&mut footer);
+        (captions, columns, header)
+    }
+
+    // Add a row at `path` to `rows`, or a cell to the anonymous row it's in.
+    fn add_to_rows(&self, path: Vec<usize>, rows: &mut Vec<Row>, in_anonymous_row: &mut bool) {
+        if let BoxType::TableRowNode(_) = self.box_type {
+            let cells = (0..self.children.len())
+                .filter(|&i| !self.children[i].is_out_of_flow())
+                .map(|i| [&path[..], &[i]].concat())
+                .collect();
+            rows.push(Row { path, cells });
+            *in_anonymous_row = false;
+            return;
+        }
+        if !*in_anonymous_row {
+            rows.push(Row {
+                path: path[..path.len() - 1].to_vec(),
+                cells: Vec::new(),
+            });
+            *in_anonymous_row = true;
+        }
+        if let Some(row) = rows.last_mut() {
+            row.cells.push(path);
+        }
+    }
+
+    // Add a column, or a column group and its columns, from `span` attributes and widths.
+    fn table_columns(&self, path: Vec<usize>, columns: &mut Vec<Column>) {
+        let first = columns
+            .iter()
+            .filter(|column| column.path.len() == 1)
+            .map(|column| column.first + column.span)
+            .next_back()
+            .unwrap_or(0);
+        let width = self
+            .get_style_node()
+            .value("width")
+            .filter(Value::is_specific_length)
+            .map(|width| width.to_px());
+        let children: Vec<usize> = (0..self.children.len())
+            .filter(|&i| matches!(self.children[i].box_type, BoxType::TableColumnNode(_)))
+            .collect();
+        let spans: Vec<usize> = children
+            .iter()
+            .map(|&i| self.children[i].span_attribute("span").clamp(1, 1000))
+            .collect();
+        if children.is_empty() {
+            let span = self.span_attribute("span").clamp(1, 1000);
+            columns.push(Column {
+                path,
+                first,
+                span,
+                width,
+            });
+            return;
+        }
+        // A group's own width goes to columns without one.
+        columns.push(Column {
+            path: path.clone(),
+            first,
+            span: spans.iter().sum(),
+            width: None,
+        });
+        let mut next = first;
+        for (&i, span) in children.iter().zip(spans) {
+            let column_width = self.children[i]
+                .get_style_node()
+                .value("width")
+                .filter(Value::is_specific_length)
+                .map(|width| width.to_px());
+            columns.push(Column {
+                path: [&path[..], &[i]].concat(),
+                first: next,
+                span,
+                width: column_width.or(width),
+            });
+            next += span;
+        }
+    }
+
+    // The value of a `colspan`, `rowspan` or `span` attribute (defaults to 1).
+    fn span_attribute(&self, name: &str) -> usize {
+        let Some(style) = self.item_style() else {
+            return 1;
+        };
+        match style.node.node_type {
+            NodeType::Element(ref element) => element
+                .attrs
+                .get(name)
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(1),
+            _ => 1,
+        }
+    }
+
+    // Set the edges of a cell: cells have no margins, and in the collapsing border model they
+    // draw the collapsed `border`.
+    fn resolve_cell_edges(&mut self, border: Option<EdgeSizes>) {
+        if self.item_style().is_none() {
+            return;
+        }
+        self.resolve_edges();
+        let d = &mut self.dimensions;
+        d.margin = EdgeSizes::default();
+        if let Some(border) = border {
+            d.border = border;
+        }
+        d.inner.left = d.border.left + d.padding.left;
+        d.inner.top = d.border.top + d.padding.top;
+    }
+
+    // Place a row, row group, column or column group, which have no edges of their own.
+    fn place_table_part(&mut self, left: f32, top: f32, width: f32, height: f32) {
+        self.dimensions = Dimensions {
+            box_offset: BoxOffset { left, top },
+            ..Dimensions::default()
+        };
+        self.dimensions.inner.width = width;
+        self.dimensions.inner.height = height;
+    }
+
+    // Where the content box of the descendant at `path` is, relative to this box's content box.
+    fn descendant_origin(&self, path: &[usize]) -> (f32, f32) {
+        let mut origin = (0.0, 0.0);
+        let mut node = self;
+        for &i in path {
+            node = &node.children[i];
+            origin.0 += node.dimensions.get_left();
+            origin.1 += node.dimensions.get_top();
+        }
+        origin
+    }
+}
+
+// Share `width` out between columns with `min` and `max` widths: up to their max widths in
+// proportion to them, then beyond. With too little room, between their min and max widths.
+// https://www.w3.org/TR/CSS22/tables.html#auto-table-layout
+fn distribute_width(min: &[f32], max: &[f32], width: f32) -> Vec<f32> {
+    let min_total = sum(min.iter().copied());
+    let max_total = sum(max.iter().copied());
+    if width >= max_total {
+        return match max_total > 0.0 {
+            true => max.iter().map(|max| max * width / max_total).collect(),
+            false => vec![width / max.len().max(1) as f32; max.len()],
+        };
+    }
+    if width <= min_total || max_total <= min_total {
+        return min.to_vec();
+    }
+    let ratio = (width - min_total) / (max_total - min_total);
+    min.iter()
+        .zip(max)
+        .map(|(min, max)| min + (max - min) * ratio)
+        .collect()
+}
+
+// The starts of rows or columns of `sizes`, with `spacing` before each one.
+fn positions(sizes: &[f32], spacing: f32) -> Vec<f32> {
+    let mut position = spacing;
+    sizes
+        .iter()
+        .map(|size| {
+            let start = position;
+            position += size + spacing;
+            start
+        })
+        .collect()
+}
+
+fn span_size(sizes: &[f32], positions: &[f32], (start, end): (usize, usize)) -> f32 {
+    positions[end - 1] + sizes[end - 1] - positions[start]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{css, html};
+    use super::super::tests::{layout, rect};
+    use super::*;
+
+    #[test]
+    fn test_auto_table_layout() {
+        let root = html::parse(
+            "<div><table class=\"table\"><tr><td class=\"h\" colspan=\"2\"></td><td class=\"tall\" rowspan=\"2\"></td></tr><tr><td class=\"a\"></td><td class=\"b\"></td></tr></table></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div { display: block; }
+.table { border-spacing: 10px; }
+td { padding: 5px; }
+.h { height: 20px; }
+.tall { width: 40px; height: 100px; }
+.a { width: 50px; }
+.b { width: 30px; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let layout_tree = layout(&styled_tree, &fonts);
+
+        // As wide as the columns want: 60px, 40px and 50px, with spacing between them.
+        let table = &layout_tree.children[0];
+        assert_eq!(rect(table), (0.0, 0.0, 190.0, 130.0));
+        let rows = &table.children;
+        assert_eq!(rect(&rows[0]), (10.0, 10.0, 170.0, 30.0));
+        // The second row makes room for the rest of the cell spanning both rows.
+        assert_eq!(rect(&rows[1]), (10.0, 50.0, 170.0, 70.0));
+        assert_eq!(rect(&rows[0].children[0]), (15.0, 15.0, 100.0, 20.0));
+        assert_eq!(rect(&rows[0].children[1]), (135.0, 15.0, 40.0, 100.0));
+        assert_eq!(rect(&rows[1].children[0]), (15.0, 55.0, 50.0, 60.0));
+        assert_eq!(rect(&rows[1].children[1]), (85.0, 55.0, 30.0, 60.0));
+    }
+
+    #[test]
+    fn test_fixed_table_layout() {
+        let root = html::parse(
+            "<div><table class=\"table\"><caption class=\"caption\"></caption><col class=\"first\"><tbody><tr><td class=\"x\"></td><td class=\"middle\"><div class=\"dot\"></div></td><td></td></tr></tbody></table></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div { display: block; }
+.table { table-layout: fixed; width: 300px; border-collapse: collapse; border-spacing: 10px; }
+.caption { height: 15px; }
+.first { width: 100px; }
+td { border-width: 2px; height: 40px; }
+.middle { vertical-align: middle; }
+.dot { height: 10px; }"
+                .to_owned(),
+        );
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let layout_tree = layout(&styled_tree, &fonts);
+
+        let table = &layout_tree.children[0];
+        // The caption goes above the grid; collapsed borders leave no spacing.
+        assert_eq!(rect(&table.children[0]), (0.0, 0.0, 300.0, 15.0));
+        assert_eq!(rect(&table.children[1]), (0.0, 15.0, 100.0, 44.0));
+        let row = &table.children[2].children[0];
+        // Borders between cells are drawn once: by the cell to the left.
+        assert_eq!(rect(&row.children[0]), (2.0, 17.0, 96.0, 40.0));
+        assert_eq!(rect(&row.children[1]), (100.0, 17.0, 98.0, 40.0));
+        assert_eq!(rect(&row.children[1].children[0]).1, 32.0);
+        assert_eq!(rect(table).3, 59.0);
+    }
+}
@@ -1,7 +1,9 @@
 pub mod css;
 pub mod dom;
+pub mod font;
 pub mod html;
 pub mod layout;
+pub mod loader;
 pub mod paint;
 pub mod style;
 
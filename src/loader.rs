@@ -0,0 +1,312 @@
+//! Loading of external resources such as stylesheets.
+//!
+//! Resources are fetched through the `ResourceLoader` trait, so documents can be rendered from
+//! the filesystem or, in tests, from memory.
+
+use super::css::{self, Stylesheet};
+use super::dom::{Node, NodeType};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+pub trait ResourceLoader {
+    /// Fetch the resource at `url`, which has already been resolved against its referrer.
+    fn load(&self, url: &str) -> io::Result<Vec<u8>>;
+}
+
+/// Loads resources from files below a root directory. URLs that would reach outside it, with
+/// `..` segments, a scheme or a drive, are refused.
+pub struct FileSystemLoader {
+    root: PathBuf,
+}
+
+impl FileSystemLoader {
+    pub fn new(root: impl Into<PathBuf>) -> FileSystemLoader {
+        FileSystemLoader { root: root.into() }
+    }
+}
+
+impl ResourceLoader for FileSystemLoader {
+    fn load(&self, url: &str) -> io::Result<Vec<u8>> {
+        let path = Path::new(url.trim_start_matches('/'));
+        let inside = path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if url.contains("://") || !inside {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{url} is outside the loader root"),
+            ));
+        }
+        fs::read(self.root.join(path))
+    }
+}
+
+/// Serves resources from a map of URL to contents.
+#[derive(Default)]
+pub struct InMemoryLoader {
+    resources: HashMap<String, Vec<u8>>,
+}
+
+impl InMemoryLoader {
+    pub fn new() -> InMemoryLoader {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, url: &str, contents: impl Into<Vec<u8>>) {
+        self.resources.insert(url.to_string(), contents.into());
+    }
+}
+
+impl ResourceLoader for InMemoryLoader {
+    fn load(&self, url: &str) -> io::Result<Vec<u8>> {
+        self.resources
+            .get(url)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, url.to_string()))
+    }
+}
+
+/// Resolve `url` against the URL of the resource that refers to it.
+///
+/// Only path-style URLs are supported: absolute paths are kept, relative ones are joined to the
+/// directory of `base`, with `.` and `..` segments removed.
+pub fn resolve_url(base: &str, url: &str) -> String {
+    if url.starts_with('/') || url.contains("://") {
+        return url.to_string();
+    }
+    let directory = match base.rfind('/') {
+        Some(i) => &base[..i],
+        None => "",
+    };
+    let mut segments: Vec<&str> = directory.split('/').filter(|s| !s.is_empty()).collect();
+    for segment in url.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    let path = segments.join("/");
+    if base.starts_with('/') {
+        format!("/{}", path)
+    } else {
+        path
+    }
+}
+
+/// Load the stylesheet at `url` and everything it imports.
+///
+/// Imported rules are placed before the rules of the importing sheet, in import order, so they
+/// lose to it in the cascade. Imports that fail to load or that would form a cycle are skipped.
+pub fn load_stylesheet(loader: &dyn ResourceLoader, url: &str) -> io::Result<Stylesheet> {
+    load_stylesheet_inner(loader, url, &mut Vec::new())
+}
+
+fn load_stylesheet_inner(
+    loader: &dyn ResourceLoader,
+    url: &str,
+    loading: &mut Vec<String>,
+) -> io::Result<Stylesheet> {
+    let source = String::from_utf8_lossy(&loader.load(url)?).into_owned();
+    let stylesheet = css::parse(source);
+
+    loading.push(url.to_string());
+    let mut rules = Vec::new();
+    let mut font_faces = Vec::new();
+    for import in &stylesheet.imports {
+        let import_url = resolve_url(url, &import.url);
+        if loading.contains(&import_url) {
+            continue;
+        }
+        let Ok(imported) = load_stylesheet_inner(loader, &import_url, loading) else {
+            continue;
+        };
+        font_faces.extend(imported.font_faces);
+        for mut rule in imported.rules {
+            if !import.media.is_empty() {
+                rule.media.insert(0, import.media.clone());
+            }
+            rules.push(rule);
+        }
+    }
+    loading.pop();
+
+    rules.extend(stylesheet.rules);
+    // Font sources are relative to the stylesheet that declares them.
+    font_faces.extend(stylesheet.font_faces.into_iter().map(|mut font_face| {
+        for source in &mut font_face.sources {
+            *source = resolve_url(url, source);
+        }
+        font_face
+    }));
+    Ok(Stylesheet {
+        rules,
+        imports: Vec::new(),
+        font_faces,
+    })
+}
+
+/// Collect the stylesheets linked from a document with `<link rel="stylesheet" href="...">`,
+/// merged in document order. Links that fail to load are skipped.
+pub fn document_stylesheet(root: &Node, loader: &dyn ResourceLoader) -> Stylesheet {
+    let mut stylesheet = Stylesheet {
+        rules: Vec::new(),
+        imports: Vec::new(),
+        font_faces: Vec::new(),
+    };
+    collect_linked_stylesheets(root, loader, &mut stylesheet);
+    stylesheet
+}
+
+fn collect_linked_stylesheets(node: &Node, loader: &dyn ResourceLoader, into: &mut Stylesheet) {
+    if let NodeType::Element(ref elem) = node.node_type {
+        let is_stylesheet = elem.tag_name.eq_ignore_ascii_case("link")
+            && elem.attrs.get("rel").is_some_and(|rel| {
+                rel.split_whitespace()
+                    .any(|r| r.eq_ignore_ascii_case("stylesheet"))
+            });
+        // Links are relative to the document, at the root.
+        let href = elem.attrs.get("href").filter(|_| is_stylesheet);
+        if let Some(href) = href
+            && let Ok(linked) = load_stylesheet(loader, &resolve_url("", href))
+        {
+            let media = elem
+                .attrs
+                .get("media")
+                .map(|media| css::parse_media(media.clone()))
+                .unwrap_or_default();
+            for mut rule in linked.rules {
+                if !media.is_empty() {
+                    rule.media.insert(0, media.clone());
+                }
+                into.rules.push(rule);
+            }
+            into.font_faces.extend(linked.font_faces);
+        }
+    }
+    for child in &node.children {
+        collect_linked_stylesheets(child, loader, into);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::css::{Unit, Value};
+    use super::super::html;
+    use super::super::style::style_tree;
+    use super::*;
+
+    #[test]
+    fn test_resolve_url() {
+        assert_eq!(resolve_url("css/main.css", "base.css"), "css/base.css");
+        assert_eq!(resolve_url("css/main.css", "../fonts/a.css"), "fonts/a.css");
+        assert_eq!(resolve_url("main.css", "./theme/x.css"), "theme/x.css");
+        assert_eq!(resolve_url("/css/main.css", "x.css"), "/css/x.css");
+        assert_eq!(resolve_url("css/main.css", "/abs.css"), "/abs.css");
+    }
+
+    #[test]
+    fn test_imports_and_links_cascade_in_order() {
+        let mut loader = InMemoryLoader::new();
+        loader.insert(
+            "css/main.css",
+            "@import url(reset.css);
+@import \"print.css\" print;
+@import url(main.css);
+.box { padding: 2px; }",
+        );
+        loader.insert(
+            "css/reset.css",
+            ".box { padding: 1px; margin: 1px; width: 10px; }",
+        );
+        loader.insert("css/print.css", ".box { margin: 9px; }");
+        loader.insert("theme.css", ".box { width: 20px; }");
+
+        let root = html::parse(
+            "<html>
+  <link rel=\"stylesheet\" href=\"css/main.css\">
+  <link rel=\"stylesheet\" href=\"missing.css\">
+  <link rel=\"stylesheet\" href=\"theme.css\">
+  <div class=\"box\"></div>
+</html>"
+                .to_string(),
+        );
+
+        let stylesheet = document_stylesheet(&root, &loader);
+        assert_eq!(stylesheet.rules.len(), 4);
+
+        let styled = style_tree(&root, &stylesheet);
+        let div = &styled.children[3];
+        // main.css wins over the reset it imports, the later link wins over both, and the
+        // print-only import doesn't apply on screen.
+        assert_eq!(div.value("padding"), Some(Value::Length(2.0, Unit::Px)));
+        assert_eq!(div.value("margin"), Some(Value::Length(1.0, Unit::Px)));
+        assert_eq!(div.value("width"), Some(Value::Length(20.0, Unit::Px)));
+    }
+
+    #[test]
+    fn test_file_system_loader() {
+        let dir = std::env::temp_dir().join(format!("robinson-loader-{}", std::process::id()));
+        fs::create_dir_all(dir.join("css")).unwrap();
+        fs::write(
+            dir.join("css/main.css"),
+            "@import url(base.css); p { margin: 2px; }",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("css/base.css"),
+            "@font-face { font-family: Base; src: url(../fonts/base.ttf); } p { padding: 3px; }",
+        )
+        .unwrap();
+
+        let loader = FileSystemLoader::new(&dir);
+        let stylesheet = load_stylesheet(&loader, "css/main.css").unwrap();
+        assert_eq!(stylesheet.rules.len(), 2);
+        assert_eq!(stylesheet.font_faces[0].sources, vec!["fonts/base.ttf"]);
+        assert!(load_stylesheet(&loader, "css/missing.css").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_system_loader_stays_in_root() {
+        let dir = std::env::temp_dir().join(format!("robinson-root-{}", std::process::id()));
+        let root = dir.join("site");
+        fs::create_dir_all(root.join("css")).unwrap();
+        fs::write(dir.join("secret.css"), "p { margin: 9px; }").unwrap();
+        fs::write(
+            root.join("css/main.css"),
+            "@import \"../../secret.css\"; p { padding: 1px; }",
+        )
+        .unwrap();
+
+        let loader = FileSystemLoader::new(&root);
+        assert!(loader.load("../secret.css").is_err());
+        assert!(loader.load("/../secret.css").is_err());
+        assert!(loader.load("css/../../secret.css").is_err());
+        assert!(
+            loader
+                .load(&format!("file://{}", dir.join("secret.css").display()))
+                .is_err()
+        );
+        assert!(loader.load("/css/main.css").is_ok());
+
+        // Links and imports that climb above the root stop at it.
+        let document = html::parse(
+            "<html>
+  <link rel=\"stylesheet\" href=\"../secret.css\">
+  <link rel=\"stylesheet\" href=\"/../secret.css\">
+  <link rel=\"stylesheet\" href=\"css/main.css\">
+</html>"
+                .to_string(),
+        );
+        let stylesheet = document_stylesheet(&document, &loader);
+        assert_eq!(stylesheet.rules.len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -5,33 +5,98 @@ type DisplayList = Vec<DisplayCommand>;
 
 enum DisplayCommand {
     SolidColor(Color, Rect),
+    // A glyph bitmap: one coverage byte per pixel of the rect, row by row.
+    Glyph(Color, Rect, Vec<u8>),
     // insert more commands here
 }
 
 fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
     let mut list = Vec::new();
-    render_layout_box(&mut list, layout_root);
+    render_stacking_context(&mut list, layout_root);
     return list;
 }
 
+// Paint a box that establishes a stacking context, back to front: its background and borders,
+// the positioned descendants with a negative `z-index`, the content in flow, then the other
+// positioned descendants by `z-index`, in tree order when they're equal.
+// https://www.w3.org/TR/CSS2/zindex.html
+fn render_stacking_context(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let mut layers = Vec::new();
+    collect_positioned(layout_box, &mut layers);
+    // The sort is stable, so tree order is kept.
+    layers.sort_by_key(|layer| layer.z_index().unwrap_or(0));
+    let (negative, rest): (Vec<_>, Vec<_>) = layers
+        .into_iter()
+        .partition(|layer| layer.z_index().is_some_and(|z_index| z_index < 0));
+
+    render_background(list, layout_box);
+    render_borders(list, layout_box);
+    for layer in negative {
+        render_stacking_context(list, layer);
+    }
+    render_children(list, layout_box);
+    for layer in rest {
+        match layer.z_index() {
+            Some(_) => render_stacking_context(list, layer),
+            // A positioned box with `z-index: auto` is painted like a stacking context, but its
+            // positioned descendants belong to the enclosing one.
+            None => render_layout_box(list, layer),
+        }
+    }
+}
+
+// The positioned descendants of a box that are painted in its stacking context.
+fn collect_positioned<'a, 'b>(layout_box: &'b LayoutBox<'a>, layers: &mut Vec<&'b LayoutBox<'a>>) {
+    for child in &layout_box.children {
+        if child.is_positioned() {
+            layers.push(child);
+            if child.z_index().is_some() {
+                continue;
+            }
+        }
+        collect_positioned(child, layers);
+    }
+}
+
+// Paint a box and its content in flow. Positioned descendants are left to their stacking
+// context.
 fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
     render_background(list, layout_box);
     render_borders(list, layout_box);
-    // TODO: render text
+    render_children(list, layout_box);
+}
 
+fn render_children(list: &mut DisplayList, layout_box: &LayoutBox) {
     match &layout_box.box_type {
         BoxType::AnonymousBlock(ifc) => {
-            for (i, element) in ifc.elements.iter().enumerate() {
+            // Floats go under the lines beside them.
+            for child in &layout_box.children {
+                if child.is_float() && !child.is_positioned() {
+                    render_layout_box(list, child);
+                }
+            }
+            for element in &ifc.elements {
                 match element {
-                    InlineFormattingContextRun::Atom(index) => {
-                        render_layout_box(list, &layout_box.children[*index]);
+                    InlineFormattingContextRun::TextRun(run) => {
+                        render_text(list, layout_box, run);
+                    }
+                    InlineFormattingContextRun::Atom(path) => {
+                        let child = layout_box.descendant(path);
+                        if !child.is_positioned() {
+                            render_layout_box(list, child);
+                        }
+                    }
+                    InlineFormattingContextRun::Fragment(fragment) => {
+                        render_fragment(list, layout_box, fragment);
                     }
                 }
             }
         }
         _ => {
             for child in &layout_box.children {
-                render_layout_box(list, child);
+                if !child.is_positioned() {
+                    render_layout_box(list, child);
+                }
             }
         }
     }
@@ -51,7 +116,21 @@ fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
     match layout_box.box_type {
         BoxType::BlockNode(style)
         | BoxType::InlineNode(style)
-        | BoxType::InlineBlockNode(style) => match style.value(name) {
+        | BoxType::InlineBlockNode(style)
+        | BoxType::TextNode(style)
+        | BoxType::FlexNode(style)
+        | BoxType::InlineFlexNode(style)
+        | BoxType::GridNode(style)
+        | BoxType::InlineGridNode(style)
+        | BoxType::TableNode(style)
+        | BoxType::InlineTableNode(style)
+        | BoxType::TableRowGroupNode(style)
+        | BoxType::TableRowNode(style)
+        | BoxType::TableCellNode(style)
+        | BoxType::TableCaptionNode(style)
+        | BoxType::TableColumnNode(style)
+        | BoxType::MarkerNode(style, _)
+        | BoxType::ContentNode(style, _) => match style.value(name) {
             Some(Value::ColorValue(color)) => Some(color),
             _ => None,
         },
@@ -59,6 +138,64 @@ fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
     }
 }
 
+// Rasterize the glyphs of a text run placed in the anonymous block `layout_box`.
+fn render_text(list: &mut DisplayList, layout_box: &LayoutBox, run: &TextRun) {
+    let Some(font) = run.font else {
+        return;
+    };
+    let color = get_color(layout_box.descendant(&run.path), "color").unwrap_or(Color {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 255,
+    });
+
+    let origin = layout_box.dimensions.box_abs;
+    let mut pen_x = origin.x + run.left;
+    let baseline = origin.y + run.top + run.ascent;
+    for glyph in &run.glyphs {
+        let (metrics, coverage) = font.font.rasterize_indexed(glyph.id, run.font_size);
+        let (x, y) = (pen_x + glyph.offset.0, baseline - glyph.offset.1);
+        list.push(DisplayCommand::Glyph(
+            color,
+            Rect {
+                x: (x + metrics.xmin as f32).round(),
+                y: (y - metrics.ymin as f32 - metrics.height as f32).round(),
+                width: metrics.width as f32,
+                height: metrics.height as f32,
+            },
+            coverage,
+        ));
+        pen_x += glyph.advance;
+    }
+}
+
+// Paint the background and borders of the part of an inline box on one line of the anonymous
+// block `layout_box`. Its left and right borders are only on the fragments that have those edges.
+fn render_fragment(list: &mut DisplayList, layout_box: &LayoutBox, fragment: &InlineFragment) {
+    let inline_box = layout_box.descendant(&fragment.path);
+    let origin = layout_box.dimensions.box_abs;
+    let border_box = Rect {
+        x: origin.x + fragment.left,
+        y: origin.y + fragment.top,
+        width: fragment.width,
+        height: fragment.height,
+    };
+    if let Some(color) = get_color(inline_box, "background") {
+        list.push(DisplayCommand::SolidColor(color, border_box));
+    }
+    if let Some(color) = get_color(inline_box, "border-color") {
+        let mut border = inline_box.dimensions.border;
+        if !fragment.first {
+            border.left = 0.0;
+        }
+        if !fragment.last {
+            border.right = 0.0;
+        }
+        render_border_edges(list, color, border_box, border);
+    }
+}
+
 fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
     let color = match get_color(layout_box, "border-color") {
         Some(color) => color,
@@ -66,15 +203,18 @@ fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
     };
 
     let d = &layout_box.dimensions;
-    let border_box = d.border_box();
+    render_border_edges(list, color, d.border_box(), d.border);
+}
 
+// Paint the borders of `border` width inside the edges of `border_box`.
+fn render_border_edges(list: &mut DisplayList, color: Color, border_box: Rect, border: EdgeSizes) {
     // Left border
     list.push(DisplayCommand::SolidColor(
         color,
         Rect {
             x: border_box.x,
             y: border_box.y,
-            width: d.border.left,
+            width: border.left,
             height: border_box.height,
         },
     ));
@@ -83,9 +223,9 @@ fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
     list.push(DisplayCommand::SolidColor(
         color,
         Rect {
-            x: border_box.x + border_box.width - d.border.right,
+            x: border_box.x + border_box.width - border.right,
             y: border_box.y,
-            width: d.border.right,
+            width: border.right,
             height: border_box.height,
         },
     ));
@@ -97,7 +237,7 @@ fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
             x: border_box.x,
             y: border_box.y,
             width: border_box.width,
-            height: d.border.top,
+            height: border.top,
         },
     ));
 
@@ -106,9 +246,9 @@ fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
         color,
         Rect {
             x: border_box.x,
-            y: border_box.y + border_box.height - d.border.bottom,
+            y: border_box.y + border_box.height - border.bottom,
             width: border_box.width,
-            height: d.border.bottom,
+            height: border.bottom,
         },
     ));
 }
@@ -152,10 +292,34 @@ impl Canvas {
                     }
                 }
             }
+            DisplayCommand::Glyph(color, rect, coverage) => {
+                let width = rect.width as usize;
+                for (i, &alpha) in coverage.iter().enumerate() {
+                    let x = rect.x as isize + (i % width) as isize;
+                    let y = rect.y as isize + (i / width) as isize;
+                    if x < 0 || y < 0 || x >= self.width as isize || y >= self.height as isize {
+                        continue;
+                    }
+                    let pixel = &mut self.pixels[x as usize + y as usize * self.width];
+                    *pixel = blend(*pixel, *color, alpha as f32 / 255.0);
+                }
+            }
         }
     }
 }
 
+// Composite `color` over `base` with the given coverage, taking the color's own alpha into account.
+fn blend(base: Color, color: Color, coverage: f32) -> Color {
+    let alpha = coverage * color.a as f32 / 255.0;
+    let mix = |b: u8, c: u8| (b as f32 + (c as f32 - b as f32) * alpha).round() as u8;
+    Color {
+        r: mix(base.r, color.r),
+        g: mix(base.g, color.g),
+        b: mix(base.b, color.b),
+        a: base.a.max((alpha * 255.0).round() as u8),
+    }
+}
+
 // Paint a tree of LayoutBoxes to an array of pixels.
 fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
     let display_list = build_display_list(layout_root);
@@ -168,8 +332,10 @@ fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
 
 mod tests {
     use super::super::css;
+    use super::super::font::FontRegistry;
     use super::super::html;
     use super::super::layout;
+    use super::super::loader::FileSystemLoader;
     use super::super::style::*;
     use super::*;
 
@@ -210,11 +376,15 @@ mod tests {
 
         let styled_tree = style_tree(&root, &css);
 
+        let fonts = FontRegistry::new();
+
         let mut layout_tree = build_layout_tree(&styled_tree);
 
         layout_tree.layout(
             Value::Length(800.0, Unit::Px),
             Value::Length(800.0, Unit::Px),
+            Value::Length(800.0, Unit::Px),
+            &fonts,
         );
         layout_tree.calc_abs();
 
@@ -305,11 +475,15 @@ mod tests {
 
         let styled_tree = style_tree(&root, &css);
 
+        let fonts = FontRegistry::new();
+
         let mut layout_tree = build_layout_tree(&styled_tree);
 
         layout_tree.layout(
             Value::Length(800.0, Unit::Px),
             Value::Length(800.0, Unit::Px),
+            Value::Length(800.0, Unit::Px),
+            &fonts,
         );
         layout_tree.calc_abs();
 
@@ -447,11 +621,15 @@ mod tests {
 
         let styled_tree = style_tree(&root, &css);
 
+        let fonts = FontRegistry::new();
+
         let mut layout_tree = build_layout_tree(&styled_tree);
 
         layout_tree.layout(
             Value::Length(800.0, Unit::Px),
             Value::Length(800.0, Unit::Px),
+            Value::Length(800.0, Unit::Px),
+            &fonts,
         );
         layout_tree.calc_abs();
 
@@ -474,4 +652,163 @@ mod tests {
         let mut file = BufWriter::new(File::create("output-inline-block.png").unwrap());
         image::DynamicImage::ImageRgba8(img).write_to(&mut file, image::ImageFormat::Png);
     }
+
+    #[test]
+    fn test_text_rasterization() {
+        let root = html::parse(
+            "<div class=\"page\">
+  <p class=\"title\">Robinson renders text</p>
+  <p>The quick brown fox jumps over the lazy dog, then keeps running until the line wraps.</p>
+  <p class=\"note\">Set in Noto Serif Italic.</p>
+</div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "@font-face { font-family: \"Noto Serif\"; src: url(NotoSerif-Regular.ttf); }
+@font-face { font-family: \"Noto Serif\"; src: url(NotoSerif-Bold.ttf); font-weight: bold; }
+@font-face { font-family: \"Noto Serif\"; src: url(NotoSerif-Italic.ttf); font-style: italic; }
+.page { display: block; padding: 12px; background: #ffffee; font-family: \"Noto Serif\"; font-size: 18px; color: #333333; }
+p { display: block; margin-bottom: 8px; }
+.title { font-size: 28px; font-weight: bold; color: #aa0000; }
+.note { font-style: italic; background: #eeeeff; }"
+                .to_owned(),
+        );
+
+        let mut fonts = FontRegistry::new();
+        fonts.load_font_faces(&css, &FileSystemLoader::new("testfiles"));
+
+        let styled_tree = style_tree(&root, &css);
+
+        let mut layout_tree = build_layout_tree(&styled_tree);
+
+        layout_tree.layout(
+            Value::Length(400.0, Unit::Px),
+            Value::Length(400.0, Unit::Px),
+            Value::Length(400.0, Unit::Px),
+            &fonts,
+        );
+        layout_tree.calc_abs();
+
+        let canvas = paint(
+            &layout_tree,
+            Rect {
+                x: 0.,
+                y: 0.,
+                width: 400.,
+                height: 300.,
+            },
+        );
+        // Some pixels got the title's color blended in.
+        assert!(
+            canvas
+                .pixels
+                .iter()
+                .any(|color| color.r > 150 && color.g < 100 && color.b < 100)
+        );
+        let (w, h) = (canvas.width as u32, canvas.height as u32);
+
+        let img = image::ImageBuffer::from_fn(w, h, move |x, y| {
+            let color = canvas.pixels[(y * w + x) as usize];
+            image::Rgba([color.r, color.g, color.b, color.a])
+        });
+
+        let mut file = BufWriter::new(File::create("output-text.png").unwrap());
+        image::DynamicImage::ImageRgba8(img).write_to(&mut file, image::ImageFormat::Png);
+    }
+
+    #[test]
+    fn test_inline_fragments() {
+        let root = html::parse(
+            "<p>Robinson paints <span class=\"mark\">a highlight that goes on over the line break</span></p>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "@font-face { font-family: Noto; src: url(NotoSerif-Regular.ttf); }
+p { display: block; font-family: Noto; font-size: 20px; }
+.mark { padding: 0 4px; border-width: 2px; background: #ffff00; border-color: #ff0000; }"
+                .to_owned(),
+        );
+        let mut fonts = FontRegistry::new();
+        fonts.load_font_faces(&css, &FileSystemLoader::new("testfiles"));
+        let styled_tree = style_tree(&root, &css);
+        let mut layout_tree = build_layout_tree(&styled_tree);
+        layout_tree.layout(
+            Value::Length(200.0, Unit::Px),
+            Value::Length(400.0, Unit::Px),
+            Value::Length(200.0, Unit::Px),
+            &fonts,
+        );
+        layout_tree.calc_abs();
+
+        let list = build_display_list(&layout_tree);
+        let rects = |r, g, b| -> Vec<Rect> {
+            list.iter()
+                .filter_map(|command| match command {
+                    DisplayCommand::SolidColor(color, rect)
+                        if (color.r, color.g, color.b) == (r, g, b) =>
+                    {
+                        Some(*rect)
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+        // A background for each line the span is on, under its text.
+        let backgrounds = rects(255, 255, 0);
+        assert!(backgrounds.len() > 1);
+        assert!(backgrounds.windows(2).all(|pair| pair[0].y < pair[1].y));
+        // The left border is only on the first line, and the right one only on the last.
+        let borders = rects(255, 0, 0);
+        let sides = borders.iter().filter(|rect| rect.width == 2.0).count();
+        assert_eq!(sides, 2);
+    }
+
+    #[test]
+    fn test_stacking_order() {
+        let root = html::parse(
+            "<div class=\"root\"><div class=\"high\"></div><div class=\"low\"></div><div class=\"flow\"></div><div class=\"auto\"><div class=\"inner\"></div></div></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            "div { display: block; height: 10px; }
+.root { background: #000000; }
+.high { position: relative; z-index: 2; background: #ff0000; }
+.low { position: relative; z-index: -1; background: #00ff00; }
+.flow { background: #0000ff; }
+.auto { position: relative; background: #ffffff; }
+.inner { position: absolute; z-index: 1; background: #ffff00; }"
+                .to_owned(),
+        );
+
+        let styled_tree = style_tree(&root, &css);
+        let fonts = FontRegistry::new();
+        let mut layout_tree = build_layout_tree(&styled_tree);
+        layout_tree.layout(
+            Value::Length(400.0, Unit::Px),
+            Value::Length(400.0, Unit::Px),
+            Value::Length(400.0, Unit::Px),
+            &fonts,
+        );
+        layout_tree.calc_abs();
+
+        let colors: Vec<(u8, u8, u8)> = build_display_list(&layout_tree)
+            .iter()
+            .map(|command| match command {
+                DisplayCommand::SolidColor(color, _) | DisplayCommand::Glyph(color, _, _) => {
+                    (color.r, color.g, color.b)
+                }
+            })
+            .collect();
+        assert_eq!(
+            colors,
+            [
+                (0, 0, 0),
+                (0, 255, 0),
+                (0, 0, 255),
+                (255, 255, 255),
+                (255, 255, 0),
+                (255, 0, 0)
+            ]
+        );
+    }
 }
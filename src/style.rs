@@ -1,5 +1,6 @@
 use super::css::{
-    Rule, Selector, Selector::Simple, SimpleSelector, Specificity, Stylesheet, Value,
+    ColorScheme, MediaFeature, MediaQuery, MediaQueryList, MediaRange, Orientation, PseudoElement,
+    Rule, Selector, Selector::Simple, SimpleSelector, Specificity, Stylesheet, Unit, Value,
 };
 use super::dom::{ElementData, Node, NodeType::*};
 use std::collections::{HashMap, HashSet};
@@ -13,11 +14,27 @@ pub struct StyledNode<'a> {
     pub(crate) node: &'a Node, // pointer to a DOM node
     pub(crate) specified_values: PropertyMap,
     pub(crate) children: Vec<StyledNode<'a>>,
+    // The values of the counters in scope at an element, outermost first, after its own
+    // `counter-*` properties.
+    pub(crate) counters: Vec<(String, i32)>,
+    // Set on the `::before` and `::after` pseudo-elements of an element, which are its first and
+    // last children, and are styled by the rules that end with them.
+    pub(crate) pseudo_element: Option<PseudoElement>,
 }
 
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
+impl<'a> StyledNode<'a> {
+    // The value of the innermost counter called `name`, or 0 if there's none.
+    pub fn counter(&self, name: &str) -> i32 {
+        self.counters
+            .iter()
+            .rfind(|(counter, _)| counter == name)
+            .map_or(0, |&(_, value)| value)
+    }
+}
+
+fn matches(elem: &ElementData, selector: &Selector, pseudo: Option<PseudoElement>) -> bool {
     match selector {
-        Simple(s) => matches_simple_selector(elem, s),
+        Simple(s) => s.pseudo_element == pseudo && matches_simple_selector(elem, s),
     }
 }
 
@@ -58,35 +75,141 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
     return true;
 }
 
+// The rendering environment that `@media` queries are evaluated against.
+#[derive(Debug, Clone)]
+pub struct MediaEnvironment {
+    pub media_type: String,
+    // Viewport size in px.
+    pub width: f32,
+    pub height: f32,
+    // Device pixels per CSS px.
+    pub resolution: f32,
+    pub color_scheme: ColorScheme,
+}
+
+impl Default for MediaEnvironment {
+    fn default() -> Self {
+        MediaEnvironment {
+            media_type: "screen".to_string(),
+            width: 800.0,
+            height: 600.0,
+            resolution: 1.0,
+            color_scheme: ColorScheme::Light,
+        }
+    }
+}
+
+impl MediaEnvironment {
+    pub fn orientation(&self) -> Orientation {
+        if self.height >= self.width {
+            Orientation::Portrait
+        } else {
+            Orientation::Landscape
+        }
+    }
+}
+
+fn media_query_list_matches(list: &MediaQueryList, media: &MediaEnvironment) -> bool {
+    list.iter().any(|query| media_query_matches(query, media))
+}
+
+// A query with a feature that isn't known or supported never matches, even after `not`.
+// https://www.w3.org/TR/mediaqueries-4/#evaluating
+fn media_query_matches(query: &MediaQuery, media: &MediaEnvironment) -> bool {
+    if query
+        .features
+        .iter()
+        .any(|feature| matches!(feature, MediaFeature::Unknown(_)))
+    {
+        return false;
+    }
+    let type_matches = query.media_type == "all" || query.media_type == media.media_type;
+    let matched = type_matches
+        && query
+            .features
+            .iter()
+            .all(|feature| media_feature_matches(feature, media));
+    matched != query.negated
+}
+
+fn media_feature_matches(feature: &MediaFeature, media: &MediaEnvironment) -> bool {
+    fn in_range(range: &MediaRange, actual: f32, expected: f32) -> bool {
+        match range {
+            MediaRange::Min => actual >= expected,
+            MediaRange::Max => actual <= expected,
+            MediaRange::Exact => actual == expected,
+        }
+    }
+
+    match feature {
+        MediaFeature::Width(range, px) => in_range(range, media.width, *px),
+        MediaFeature::Height(range, px) => in_range(range, media.height, *px),
+        MediaFeature::Resolution(range, dppx) => in_range(range, media.resolution, *dppx),
+        MediaFeature::Orientation(orientation) => media.orientation() == *orientation,
+        MediaFeature::PrefersColorScheme(scheme) => media.color_scheme == *scheme,
+        MediaFeature::Unknown(_) => false,
+    }
+}
+
 type MatchedRule<'a> = (Specificity, &'a Rule);
 
-// If `rule` matches `elem`, return a `MatchedRule`. Otherwise return `None`.
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
+// If `rule` matches `elem`, or its `pseudo` element, return a `MatchedRule`. Otherwise return
+// `None`.
+fn match_rule<'a>(
+    elem: &ElementData,
+    pseudo: Option<PseudoElement>,
+    rule: &'a Rule,
+    media: &MediaEnvironment,
+) -> Option<MatchedRule<'a>> {
+    if !rule
+        .media
+        .iter()
+        .all(|list| media_query_list_matches(list, media))
+    {
+        return None;
+    }
+
     // Find the first (highest-specificity) matching selector.
     rule.selectors
         .iter()
-        .find(|selector| matches(elem, selector))
+        .find(|selector| matches(elem, selector, pseudo))
         .map(|selector| (selector.specificity(), rule))
 }
 
-// Find all CSS rules that match the given element.
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a Stylesheet) -> Vec<MatchedRule<'a>> {
+// Find all CSS rules that match the given element, or its `pseudo` element.
+fn matching_rules<'a>(
+    elem: &ElementData,
+    pseudo: Option<PseudoElement>,
+    stylesheet: &'a Stylesheet,
+    media: &MediaEnvironment,
+) -> Vec<MatchedRule<'a>> {
     stylesheet
         .rules
         .iter()
-        .filter_map(|rule| match_rule(elem, rule))
+        .filter_map(|rule| match_rule(elem, pseudo, rule, media))
         .collect()
 }
 
-// Apply styles to a single element, returning the specified values.
-fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap {
+// Apply styles to a single element, or its `pseudo` element, returning the specified values.
+fn specified_values(
+    elem: &ElementData,
+    pseudo: Option<PseudoElement>,
+    stylesheet: &Stylesheet,
+    media: &MediaEnvironment,
+) -> PropertyMap {
     let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+    let mut rules = matching_rules(elem, pseudo, stylesheet, media);
 
     // Go through the rules from lowest to highest specificity.
     rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
     for (_, rule) in rules {
         for declaration in &rule.declarations {
+            // A shorthand overrides the longhands declared before it.
+            if let Some(longhands) = longhands(&declaration.name) {
+                for longhand in longhands {
+                    values.remove(*longhand);
+                }
+            }
             values.insert(declaration.name.clone(), declaration.value.clone());
         }
     }
@@ -95,17 +218,607 @@ fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap
 
 // Apply a stylesheet to an entire DOM tree, returning a StyledNode tree.
 pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a Stylesheet) -> StyledNode<'a> {
-    StyledNode {
-        node: root,
-        specified_values: match root.node_type {
-            Element(ref elem) => specified_values(elem, stylesheet),
-            Text(_) => HashMap::new(),
-        },
-        children: root
-            .children
+    style_tree_with_media(root, stylesheet, &MediaEnvironment::default())
+}
+
+// Like `style_tree`, but evaluate `@media` rules against the given environment.
+pub fn style_tree_with_media<'a>(
+    root: &'a Node,
+    stylesheet: &'a Stylesheet,
+    media: &MediaEnvironment,
+) -> StyledNode<'a> {
+    styled_node(root, stylesheet, media, &HashMap::new(), &mut Vec::new(), 0)
+}
+
+// Style one node given the computed values of its parent, then recurse into its children.
+// `counters` are the counters in scope, of which those from `siblings` on were created by the
+// preceding siblings of the node.
+fn styled_node<'a>(
+    node: &'a Node,
+    stylesheet: &'a Stylesheet,
+    media: &MediaEnvironment,
+    parent_values: &PropertyMap,
+    counters: &mut Vec<Counter>,
+    siblings: usize,
+) -> StyledNode<'a> {
+    let mut values = match node.node_type {
+        Element(ref elem) => specified_values(elem, None, stylesheet, media),
+        Text(_) => HashMap::new(),
+    };
+    compute_values(&mut values, parent_values);
+
+    let mut styled = StyledNode {
+        node,
+        children: Vec::new(),
+        specified_values: values,
+        counters: Vec::new(),
+        pseudo_element: None,
+    };
+    let Element(ref elem) = node.node_type else {
+        return styled;
+    };
+    update_counters(&styled, elem, counters, siblings);
+    styled.counters = counters
+        .iter()
+        .map(|counter| (counter.name.clone(), counter.value))
+        .collect();
+    // The counters the children create are in scope until the end of this node.
+    let start = counters.len();
+    let pseudo_element = |pseudo, counters: &mut Vec<Counter>| {
+        let values = &styled.specified_values;
+        pseudo_element_node(node, pseudo, stylesheet, media, values, counters, start)
+    };
+    let before = pseudo_element(PseudoElement::Before, counters);
+    let mut children: Vec<StyledNode<'a>> = before.into_iter().collect();
+    for child in &node.children {
+        let values = &styled.specified_values;
+        children.push(styled_node(
+            child, stylesheet, media, values, counters, start,
+        ));
+    }
+    children.extend(pseudo_element(PseudoElement::After, counters));
+    styled.children = children;
+    counters.truncate(start);
+    styled
+}
+
+// Style the `::before` or `::after` pseudo-element of an element, which is only generated when
+// its `content` is a string, once the counters in it are resolved.
+// https://www.w3.org/TR/css-pseudo-4/#generated-content
+fn pseudo_element_node<'a>(
+    node: &'a Node,
+    pseudo: PseudoElement,
+    stylesheet: &Stylesheet,
+    media: &MediaEnvironment,
+    parent_values: &PropertyMap,
+    counters: &mut Vec<Counter>,
+    siblings: usize,
+) -> Option<StyledNode<'a>> {
+    let Element(ref elem) = node.node_type else {
+        return None;
+    };
+    let mut values = specified_values(elem, Some(pseudo), stylesheet, media);
+    if !matches!(
+        values.get("content"),
+        Some(Value::Str(_) | Value::Function(_, _) | Value::List(_))
+    ) {
+        return None;
+    }
+    compute_values(&mut values, parent_values);
+
+    let mut styled = StyledNode {
+        node,
+        children: Vec::new(),
+        specified_values: values,
+        counters: Vec::new(),
+        pseudo_element: Some(pseudo),
+    };
+    update_counters(&styled, elem, counters, siblings);
+    resolve_content(&mut styled.specified_values, counters);
+    styled.counters = counters
+        .iter()
+        .map(|counter| (counter.name.clone(), counter.value))
+        .collect();
+    match styled.specified_values.get("content") {
+        Some(Value::Str(_)) => Some(styled),
+        _ => None,
+    }
+}
+
+// Turn the specified values of a node into the values layout uses, given the computed values of
+// its parent.
+fn compute_values(values: &mut PropertyMap, parent_values: &PropertyMap) {
+    inherit_properties(values, parent_values);
+    substitute_variables(values);
+    expand_shorthands(values);
+    compute_line_height(values);
+}
+
+// A counter in scope during the traversal of the document.
+// https://www.w3.org/TR/css-lists-3/#creating-a-counter
+#[derive(Clone, Debug)]
+struct Counter {
+    name: String,
+    value: i32,
+    // Created by `reversed()`, like the `list-item` counter of an `<ol reversed>`: list items
+    // count it down.
+    reversed: bool,
+}
+
+// Apply the `counter-reset`, `counter-increment` and `counter-set` of an element, in that order.
+// A reset creates a new counter, unless a preceding sibling created one of the same name, which it
+// resets instead. Lists reset `list-item` and list items increment it, unless their properties
+// say otherwise.
+// https://www.w3.org/TR/css-lists-3/#auto-numbering
+fn update_counters(
+    styled: &StyledNode,
+    elem: &ElementData,
+    counters: &mut Vec<Counter>,
+    siblings: usize,
+) {
+    let values = &styled.specified_values;
+    let resets = match values.get("counter-reset") {
+        Some(value) => counter_list(value, None),
+        None if styled.pseudo_element.is_none() => list_item_reset(elem),
+        None => Vec::new(),
+    };
+    for (name, value, reversed) in resets {
+        // A reversed counter without a value counts down to 1 over the list items.
+        let value = value.unwrap_or_else(|| match reversed {
+            true => list_items(styled.node) as i32 + 1,
+            false => 0,
+        });
+        let counter = Counter {
+            name,
+            value,
+            reversed,
+        };
+        match counters[siblings..]
+            .iter_mut()
+            .rfind(|sibling| sibling.name == counter.name)
+        {
+            Some(sibling) => *sibling = counter,
+            None => counters.push(counter),
+        }
+    }
+
+    let mut increments = values
+        .get("counter-increment")
+        .map_or(Vec::new(), |value| counter_list(value, Some(1)));
+    if styled.display_keyword() == "list-item"
+        && !increments.iter().any(|(name, _, _)| name == "list-item")
+    {
+        let reversed = innermost(counters, "list-item").reversed;
+        increments.push((
+            "list-item".to_string(),
+            Some(if reversed { -1 } else { 1 }),
+            false,
+        ));
+    }
+    for (name, by, _) in increments {
+        let counter = innermost(counters, &name);
+        counter.value = counter.value.saturating_add(by.unwrap_or(1));
+    }
+
+    let sets = values
+        .get("counter-set")
+        .map_or(Vec::new(), |value| counter_list(value, Some(0)));
+    for (name, value, _) in sets {
+        innermost(counters, &name).value = value.unwrap_or(0);
+    }
+}
+
+// The innermost counter called `name`. If there's none, a new one is created on the element.
+fn innermost<'c>(counters: &'c mut Vec<Counter>, name: &str) -> &'c mut Counter {
+    let index = match counters.iter().rposition(|counter| counter.name == name) {
+        Some(index) => index,
+        None => {
+            counters.push(Counter {
+                name: name.to_string(),
+                value: 0,
+                reversed: false,
+            });
+            counters.len() - 1
+        }
+    };
+    &mut counters[index]
+}
+
+// The counters in a `counter-*` value like `section 2 figure` or `reversed(list-item)`, with their
+// values, if given, and whether they're reversed. `none` is an empty list.
+fn counter_list(value: &Value, default: Option<i32>) -> Vec<(String, Option<i32>, bool)> {
+    let values = match value {
+        Value::List(values) => values.clone(),
+        value => vec![value.clone()],
+    };
+    let mut counters: Vec<(String, Option<i32>, bool)> = Vec::new();
+    for value in values {
+        match value {
+            Value::Keyword(name) if name == "none" => {}
+            Value::Keyword(name) => counters.push((name, default, false)),
+            Value::Function(function, args) if function == "reversed" => {
+                if let [Value::Keyword(name)] = &args[..] {
+                    counters.push((name.clone(), None, true));
+                }
+            }
+            Value::Number(number) => {
+                if let Some(counter) = counters.last_mut() {
+                    counter.1 = Some(number as i32);
+                }
+            }
+            _ => {}
+        }
+    }
+    counters
+}
+
+// The `list-item` counter that lists reset, like the HTML user agent style sheet does: from the
+// `start` attribute of an `ol`, counting down if it's `reversed`.
+fn list_item_reset(elem: &ElementData) -> Vec<(String, Option<i32>, bool)> {
+    let name = "list-item".to_string();
+    match &*elem.tag_name.to_ascii_lowercase() {
+        "ol" => {
+            let reversed = elem.attrs.contains_key("reversed");
+            let start = elem
+                .attrs
+                .get("start")
+                .and_then(|start| start.trim().parse::<i32>().ok());
+            let value = match reversed {
+                true => start.map(|start| start.saturating_add(1)),
+                false => Some(start.unwrap_or(1).saturating_sub(1)),
+            };
+            vec![(name, value, reversed)]
+        }
+        "ul" | "menu" => vec![(name, Some(0), false)],
+        _ => Vec::new(),
+    }
+}
+
+// The number of `li` children of a list.
+fn list_items(node: &Node) -> usize {
+    node.children
+        .iter()
+        .filter(|child| matches!(child.node_type, Element(ref elem) if elem.tag_name.eq_ignore_ascii_case("li")))
+        .count()
+}
+
+// Make a `content` value with `counter()` and `counters()` in it the string it stands for.
+// https://www.w3.org/TR/css-lists-3/#counter-functions
+fn resolve_content(values: &mut PropertyMap, counters: &[Counter]) {
+    let items = match values.get("content") {
+        Some(Value::List(items)) => items.clone(),
+        Some(item @ (Value::Str(_) | Value::Function(_, _))) => vec![item.clone()],
+        _ => return,
+    };
+    let value = |name: &str| {
+        counters
             .iter()
-            .map(|child| style_tree(child, stylesheet))
-            .collect(),
+            .rfind(|counter| counter.name == name)
+            .map_or(0, |counter| counter.value)
+    };
+    let mut text = String::new();
+    for item in items {
+        match item {
+            Value::Str(string) => text.push_str(&string),
+            Value::Function(function, args) => match (function.as_str(), &args[..]) {
+                ("counter", [Value::Keyword(name)]) => {
+                    text.push_str(&counter_text(value(name), "decimal"))
+                }
+                ("counter", [Value::Keyword(name), Value::Keyword(style)]) => {
+                    text.push_str(&counter_text(value(name), style))
+                }
+                ("counters", [Value::Keyword(name), Value::Str(separator), rest @ ..]) => {
+                    let style = match rest {
+                        [Value::Keyword(style)] => style.as_str(),
+                        _ => "decimal",
+                    };
+                    let mut values: Vec<String> = counters
+                        .iter()
+                        .filter(|counter| counter.name == *name)
+                        .map(|counter| counter_text(counter.value, style))
+                        .collect();
+                    if values.is_empty() {
+                        values.push(counter_text(0, style));
+                    }
+                    text.push_str(&values.join(separator));
+                }
+                _ => {
+                    values.remove("content");
+                    return;
+                }
+            },
+            _ => {
+                values.remove("content");
+                return;
+            }
+        }
+    }
+    values.insert("content".to_string(), Value::Str(text));
+}
+
+// The representation of a counter value in a counter style, like `iv` in `lower-roman`. Other
+// styles, and values a style can't represent, fall back to `decimal`.
+// https://www.w3.org/TR/css-counter-styles-3/#predefined-counters
+pub fn counter_text(value: i32, style: &str) -> String {
+    match style {
+        "disc" => Some("•".to_string()),
+        "circle" => Some("◦".to_string()),
+        "square" => Some("▪".to_string()),
+        "decimal-leading-zero" if (0..10).contains(&value) => Some(format!("0{}", value)),
+        "lower-alpha" | "lower-latin" => alphabetic(value, &LATIN),
+        "upper-alpha" | "upper-latin" => alphabetic(value, &LATIN).map(|n| n.to_uppercase()),
+        "lower-greek" => alphabetic(value, &GREEK),
+        "lower-roman" => roman(value).map(|n| n.to_lowercase()),
+        "upper-roman" => roman(value),
+        _ => None,
+    }
+    .unwrap_or_else(|| value.to_string())
+}
+
+const LATIN: [char; 26] = [
+    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's',
+    't', 'u', 'v', 'w', 'x', 'y', 'z',
+];
+
+const GREEK: [char; 24] = [
+    'α', 'β', 'γ', 'δ', 'ε', 'ζ', 'η', 'θ', 'ι', 'κ', 'λ', 'μ', 'ν', 'ξ', 'ο', 'π', 'ρ', 'σ', 'τ',
+    'υ', 'φ', 'χ', 'ψ', 'ω',
+];
+
+// A positive number in bijective base-n with the letters of an alphabet: a, b, ..., z, aa, ab...
+fn alphabetic(value: i32, letters: &[char]) -> Option<String> {
+    if value < 1 {
+        return None;
+    }
+    let base = letters.len() as i32;
+    let mut n = value;
+    let mut text = Vec::new();
+    while n > 0 {
+        n -= 1;
+        text.push(letters[(n % base) as usize]);
+        n /= base;
+    }
+    Some(text.into_iter().rev().collect())
+}
+
+// A number from 1 to 3999 in upper-case Roman numerals.
+fn roman(value: i32) -> Option<String> {
+    const NUMERALS: [(i32, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    if !(1..4000).contains(&value) {
+        return None;
+    }
+    let mut n = value;
+    let mut text = String::new();
+    for (numeral_value, numeral) in NUMERALS {
+        while n >= numeral_value {
+            text.push_str(numeral);
+            n -= numeral_value;
+        }
+    }
+    Some(text)
+}
+
+// Shorthands for the four sides of a box, with their longhands in top, right, bottom, left order,
+// and for the gaps between rows and columns.
+const BOX_SHORTHANDS: &[(&str, &[&str])] = &[
+    (
+        "margin",
+        &["margin-top", "margin-right", "margin-bottom", "margin-left"],
+    ),
+    (
+        "padding",
+        &[
+            "padding-top",
+            "padding-right",
+            "padding-bottom",
+            "padding-left",
+        ],
+    ),
+    (
+        "border-width",
+        &[
+            "border-top-width",
+            "border-right-width",
+            "border-bottom-width",
+            "border-left-width",
+        ],
+    ),
+    ("gap", &["row-gap", "column-gap"]),
+];
+
+fn longhands(shorthand: &str) -> Option<&'static [&'static str]> {
+    BOX_SHORTHANDS
+        .iter()
+        .find(|(name, _)| *name == shorthand)
+        .map(|(_, longhands)| *longhands)
+}
+
+// Set the longhands of each shorthand from its values, like `margin: 0 auto`, unless they were
+// declared after it.
+fn expand_shorthands(values: &mut PropertyMap) {
+    for (shorthand, longhands) in BOX_SHORTHANDS {
+        let sides = match values.get(*shorthand) {
+            Some(Value::List(sides)) => sides.clone(),
+            Some(value) => vec![value.clone()],
+            None => continue,
+        };
+        if sides.len() > longhands.len() {
+            continue;
+        }
+        // Missing sides copy the opposite one: right from top, bottom from top, left from right.
+        let side = |i: usize| match (i, sides.len()) {
+            (_, 1) => &sides[0],
+            (2, 2) => &sides[0],
+            (3, 2) | (3, 3) => &sides[1],
+            _ => &sides[i],
+        };
+        for (i, longhand) in longhands.iter().enumerate() {
+            if !values.contains_key(*longhand) {
+                values.insert(longhand.to_string(), side(i).clone());
+            }
+        }
+    }
+}
+
+// A percentage `line-height` is of the node's own font size. Children inherit the length, while
+// they inherit a number as it is, to multiply their own font size by.
+// https://www.w3.org/TR/CSS22/visudet.html#propdef-line-height
+fn compute_line_height(values: &mut PropertyMap) {
+    if let Some(&Value::Percentage(percentage)) = values.get("line-height") {
+        let font_size = match values.get("font-size") {
+            Some(&Value::Length(size, Unit::Px)) => size,
+            _ => 16.0,
+        };
+        let line_height = Value::Length(font_size * percentage / 100.0, Unit::Px);
+        values.insert("line-height".to_string(), line_height);
+    }
+}
+
+// Properties that take their parent's value when a node doesn't specify one.
+const INHERITED_PROPERTIES: &[&str] = &[
+    "color",
+    "direction",
+    "font-family",
+    "font-size",
+    "font-style",
+    "font-weight",
+    "line-height",
+    "list-style-position",
+    "list-style-type",
+    "text-align",
+    "text-indent",
+];
+
+fn is_custom_property(name: &str) -> bool {
+    name.starts_with("--")
+}
+
+fn is_inherited(name: &str) -> bool {
+    is_custom_property(name) || INHERITED_PROPERTIES.contains(&name)
+}
+
+// Copy the parent's value of each inherited property, unless the node sets its own.
+fn inherit_properties(values: &mut PropertyMap, parent_values: &PropertyMap) {
+    for (name, value) in parent_values {
+        if is_inherited(name) && !values.contains_key(name) {
+            values.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+// Replace every `var()` reference with the value of the custom property it names.
+//
+// Custom properties are resolved first, so they may refer to each other. Properties that take
+// part in a reference cycle, or that refer to a missing property without a fallback, are invalid
+// at computed-value time and are dropped, which leaves them unset.
+fn substitute_variables(values: &mut PropertyMap) {
+    let mut resolver = VariableResolver {
+        values,
+        resolved: HashMap::new(),
+        stack: Vec::new(),
+        cyclic: HashSet::new(),
+    };
+    let custom_names: Vec<String> = values
+        .keys()
+        .filter(|name| is_custom_property(name))
+        .cloned()
+        .collect();
+    for name in &custom_names {
+        resolver.resolve_custom_property(name);
+    }
+    let mut substituted = HashMap::new();
+    for (name, value) in values.iter() {
+        let value = if is_custom_property(name) {
+            resolver.resolved[name].clone()
+        } else {
+            resolver.substitute(value)
+        };
+        if let Some(value) = value {
+            substituted.insert(name.clone(), value);
+        }
+    }
+    *values = substituted;
+}
+
+struct VariableResolver<'m> {
+    values: &'m PropertyMap,
+    // Computed value of each custom property visited so far (`None` if invalid).
+    resolved: HashMap<String, Option<Value>>,
+    // Custom properties currently being resolved, innermost last.
+    stack: Vec<String>,
+    // Custom properties found to take part in a reference cycle.
+    cyclic: HashSet<String>,
+}
+
+impl<'m> VariableResolver<'m> {
+    fn resolve_custom_property(&mut self, name: &str) -> Option<Value> {
+        if let Some(value) = self.resolved.get(name) {
+            return value.clone();
+        }
+        if let Some(start) = self.stack.iter().position(|n| n == name) {
+            // Every property from `name` up to the top of the stack is part of the cycle.
+            self.cyclic.extend(self.stack[start..].iter().cloned());
+            return None;
+        }
+        let value = self.values.get(name)?;
+
+        self.stack.push(name.to_string());
+        let substituted = self.substitute(value);
+        self.stack.pop();
+
+        let value = if self.cyclic.contains(name) {
+            None
+        } else {
+            substituted
+        };
+        self.resolved.insert(name.to_string(), value.clone());
+        value
+    }
+
+    fn substitute(&mut self, value: &Value) -> Option<Value> {
+        match value {
+            Value::Var(name, fallback) => match self.resolve_custom_property(name) {
+                Some(value) => Some(value),
+                None => fallback
+                    .as_ref()
+                    .and_then(|fallback| self.substitute(fallback)),
+            },
+            // A reference inside a space-separated list is replaced by all the values it
+            // stands for, so `margin: var(--y) 4px` with `--y: 2px 0` is `2px 0 4px`.
+            Value::List(values) => {
+                let mut substituted = Vec::new();
+                for value in values {
+                    match self.substitute(value)? {
+                        Value::List(values) => substituted.extend(values),
+                        value => substituted.push(value),
+                    }
+                }
+                Some(Value::List(substituted))
+            }
+            Value::CommaList(values) => Some(Value::CommaList(self.substitute_all(values)?)),
+            Value::Function(name, args) => {
+                Some(Value::Function(name.clone(), self.substitute_all(args)?))
+            }
+            _ => Some(value.clone()),
+        }
+    }
+
+    fn substitute_all(&mut self, values: &[Value]) -> Option<Vec<Value>> {
+        values.iter().map(|value| self.substitute(value)).collect()
     }
 }
 
@@ -130,4 +843,214 @@ div.note { margin-bottom: 20px; padding: 10px; }
 
         println!("{:?}", styled_tree)
     }
+
+    #[test]
+    fn test_custom_properties() {
+        use super::super::css::{Color, Unit};
+
+        let root = html::parse(
+            "<div class=\"theme\"><p class=\"card\"><span class=\"label\">Hi</span></p></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            ".theme { --gap: 8px; --brand: #336699; --loop-a: var(--loop-b); --loop-b: var(--loop-a); }
+.card { --gap: 12px; --pair: 2px var(--gap); padding: var(--gap); margin: var(--missing, var(--gap)); border-width: var(--pair) 0; }
+.label { background: var(--brand); color: var(--loop-a, #000000); border-width: var(--nope); }"
+                .to_owned(),
+        );
+
+        let styled_tree = style_tree(&root, &css);
+        let card = &styled_tree.children[0];
+        let label = &card.children[0];
+
+        // Overridden tokens shadow inherited ones and fallbacks may contain references.
+        assert_eq!(card.value("padding"), Some(Value::Length(12.0, Unit::Px)));
+        assert_eq!(card.value("margin"), Some(Value::Length(12.0, Unit::Px)));
+        // References inside lists are substituted and spliced into the list.
+        assert_eq!(
+            card.value("border-width"),
+            Some(Value::List(vec![
+                Value::Length(2.0, Unit::Px),
+                Value::Length(12.0, Unit::Px),
+                Value::Number(0.0),
+            ]))
+        );
+
+        // Tokens inherit through the tree.
+        assert_eq!(
+            label.value("background"),
+            Some(Value::ColorValue(Color {
+                r: 0x33,
+                g: 0x66,
+                b: 0x99,
+                a: 255
+            }))
+        );
+
+        // Properties in a cycle are invalid, so references to them use the fallback.
+        assert_eq!(styled_tree.value("--loop-a"), None);
+        assert_eq!(styled_tree.value("--loop-b"), None);
+        assert_eq!(
+            label.value("color"),
+            Some(Value::ColorValue(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255
+            }))
+        );
+
+        // A reference to a missing property without a fallback leaves the property unset.
+        assert_eq!(label.value("border-width"), None);
+    }
+
+    #[test]
+    fn test_box_shorthands() {
+        use super::super::css::Unit;
+
+        let root = html::parse("<p></p>".to_string());
+        let css = css::parse(
+            "p { margin-left: 1px; margin: 2px auto; padding: 1px 2px 3px; padding-left: 9px; gap: 4px; }"
+                .to_owned(),
+        );
+        let styled = style_tree(&root, &css);
+        let px = |px| Some(Value::Length(px, Unit::Px));
+        let auto = Some(Value::Keyword("auto".to_string()));
+
+        // The shorthand overrides the earlier longhand.
+        assert_eq!(styled.value("margin-top"), px(2.0));
+        assert_eq!(styled.value("margin-bottom"), px(2.0));
+        assert_eq!(styled.value("margin-left"), auto);
+        assert_eq!(styled.value("margin-right"), auto);
+        // A later longhand overrides the shorthand.
+        assert_eq!(styled.value("padding-top"), px(1.0));
+        assert_eq!(styled.value("padding-right"), px(2.0));
+        assert_eq!(styled.value("padding-bottom"), px(3.0));
+        assert_eq!(styled.value("padding-left"), px(9.0));
+        assert_eq!(styled.value("border-top-width"), None);
+        assert_eq!(styled.value("row-gap"), px(4.0));
+        assert_eq!(styled.value("column-gap"), px(4.0));
+    }
+
+    #[test]
+    fn test_counters() {
+        let root = html::parse(
+            "<div class=\"doc\"><p class=\"section\"></p><p class=\"figure\"></p><div class=\"sub\"><p class=\"figure\"></p></div><p class=\"section\"></p><p class=\"figure\"></p><p class=\"missing\"></p><ol start=\"5\"><li></li><li></li></ol></div>"
+                .to_string(),
+        );
+        let css = css::parse(
+            ".doc { counter-reset: section; }
+.section { counter-increment: section; counter-reset: figure; }
+.section::before { content: counter(section, upper-roman) \". \"; }
+.figure { counter-increment: figure; }
+.figure::after { content: \"Figure \" counters(section, \".\") \"-\" counter(figure); }
+.sub { counter-reset: section 4; }
+.missing::before { content: counter(none) counters(none, \".\", lower-alpha); }
+.missing::after { content: none; }"
+                .to_owned(),
+        );
+        let styled = style_tree(&root, &css);
+        // The text of the only pseudo-element of an element.
+        let content = |node: &StyledNode| {
+            assert_eq!(node.children.len(), 1);
+            match node.children[0].value("content") {
+                Some(Value::Str(text)) => text,
+                value => panic!("content should be a string, not {:?}", value),
+            }
+        };
+
+        let children = &styled.children;
+        assert_eq!(content(&children[0]), "I. ");
+        assert_eq!(content(&children[1]), "Figure 1-1");
+        // A reset nests a new counter, in scope for the element's descendants and its following
+        // siblings.
+        assert_eq!(content(&children[2].children[0]), "Figure 1.4-2");
+        assert_eq!(content(&children[3]), "V. ");
+        // A reset of a counter a preceding sibling created sets that counter again.
+        assert_eq!(content(&children[4]), "Figure 1.5-1");
+        assert_eq!(content(&children[5]), "00");
+        // Lists reset `list-item`, and list items increment it.
+        let items = &children[6].children;
+        assert_eq!(
+            (items[0].counter("list-item"), items[1].counter("list-item")),
+            (5, 6)
+        );
+    }
+
+    #[test]
+    fn test_counter_limits() {
+        let root = html::parse(
+            "<div><ol reversed=\"\" start=\"2147483647\"><li></li><li></li></ol><ol start=\"-2147483648\"><li></li></ol><p class=\"c\"></p><p class=\"c\"></p></div>"
+                .to_string(),
+        );
+        let css = css::parse(".c { counter-increment: c 2147483647; }".to_owned());
+        let styled = style_tree(&root, &css);
+
+        // Counters stop at the limits of their values instead of overflowing.
+        let children = &styled.children;
+        let items = |list: &StyledNode| -> Vec<i32> {
+            list.children
+                .iter()
+                .map(|item| item.counter("list-item"))
+                .collect()
+        };
+        assert_eq!(items(&children[0]), [i32::MAX - 1, i32::MAX - 2]);
+        assert_eq!(items(&children[1]), [i32::MIN + 1]);
+        assert_eq!(children[2].counter("c"), i32::MAX);
+        assert_eq!(children[3].counter("c"), i32::MAX);
+    }
+
+    #[test]
+    fn test_media_queries() {
+        use super::super::css::Unit;
+
+        let root = html::parse("<div class=\"page\"></div>".to_string());
+        let css = css::parse(
+            ".page { width: 320px; padding: 4px; }
+@media (min-width: 768px) { .page { width: 720px; } }
+@media screen and (orientation: portrait) { .page { padding: 8px; } }
+@media print { .page { width: 100px; } }
+@media (prefers-color-scheme: dark) and (min-resolution: 2dppx) { .page { margin: 1px; } }
+@media not screen and (foo: bar), not all and (hover: hover) { .page { width: 1px; } }"
+                .to_owned(),
+        );
+
+        let phone = MediaEnvironment {
+            width: 375.0,
+            height: 812.0,
+            resolution: 3.0,
+            color_scheme: ColorScheme::Dark,
+            ..Default::default()
+        };
+        let styled_phone = style_tree_with_media(&root, &css, &phone);
+        // The negated queries with unknown features don't match either.
+        assert_eq!(
+            styled_phone.value("width"),
+            Some(Value::Length(320.0, Unit::Px))
+        );
+        assert_eq!(
+            styled_phone.value("padding"),
+            Some(Value::Length(8.0, Unit::Px))
+        );
+        assert_eq!(
+            styled_phone.value("margin"),
+            Some(Value::Length(1.0, Unit::Px))
+        );
+
+        let desktop = MediaEnvironment {
+            width: 1280.0,
+            height: 800.0,
+            ..Default::default()
+        };
+        let styled_desktop = style_tree_with_media(&root, &css, &desktop);
+        assert_eq!(
+            styled_desktop.value("width"),
+            Some(Value::Length(720.0, Unit::Px))
+        );
+        assert_eq!(
+            styled_desktop.value("padding"),
+            Some(Value::Length(4.0, Unit::Px))
+        );
+        assert_eq!(styled_desktop.value("margin"), None);
+    }
 }